@@ -0,0 +1,883 @@
+use chrono::{DateTime, Utc};
+use clap::{Args, ValueEnum};
+use log::{debug, error};
+use plotters::prelude::*;
+use plotters::style::full_palette::{GREEN_700, RED_700};
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use serde_json::json;
+use std::fs::File;
+
+use crate::bar::Bar;
+use crate::overlay::PriceOverlay;
+use crate::strategies::{build_strategy, BollingerMode, BuyAndHold, StrategyKind, StrategyParams};
+use crate::{axis_title, fetch_stock, fetch_warmup_history, finite_range, stock_picture_creator, Stock, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+#[derive(Args, Debug)]
+pub struct BacktestArgs {
+    /// Strategy to backtest, compared with buying and holding from the first fill
+    #[arg(long, value_enum, default_value_t = StrategyKind::SmaCross)]
+    pub strategy: StrategyKind,
+    /// Period of the fast moving average of the SMA/EMA crossovers
+    #[arg(long, default_value_t = 20)]
+    pub fast_period: usize,
+    /// Period of the slow moving average of the SMA/EMA crossovers
+    #[arg(long, default_value_t = 50)]
+    pub slow_period: usize,
+    /// Period of the RSI strategy's RSI, it trades the --rsi-oversold/--rsi-overbought levels
+    #[arg(long, default_value_t = 14)]
+    pub rsi_period: usize,
+    /// Period of the Bollinger strategy's bands
+    #[arg(long, default_value_t = 20)]
+    pub bb_period: usize,
+    /// Standard deviations between the Bollinger strategy's average and its bands
+    #[arg(long, default_value_t = 2.0)]
+    pub bb_multiplier: f64,
+    /// Whether the Bollinger strategy buys touches of the lower band or breakouts above the upper band
+    #[arg(long, value_enum, default_value_t = BollingerMode::Touch)]
+    pub bb_mode: BollingerMode,
+    /// Fast EMA period of the MACD strategy
+    #[arg(long, default_value_t = 12)]
+    pub macd_fast: usize,
+    /// Slow EMA period of the MACD strategy
+    #[arg(long, default_value_t = 26)]
+    pub macd_slow: usize,
+    /// Signal line period of the MACD strategy
+    #[arg(long, default_value_t = 9)]
+    pub macd_signal: usize,
+    /// Cash the backtest starts with
+    #[arg(long, default_value_t = 10_000.0)]
+    pub capital: f64,
+    /// Price orders are filled at
+    #[arg(long, value_enum, default_value_t = FillPrice::NextOpen)]
+    pub fill: FillPrice,
+    /// Fixed commission per order
+    #[arg(long, default_value_t = 0.0)]
+    pub commission: f64,
+    /// Commission in percent of the traded value, charged on top of --commission
+    #[arg(long, default_value_t = 0.0)]
+    pub commission_percent: f64,
+    /// Slippage in percent of the fill price, buys fill higher and sells lower
+    #[arg(long, default_value_t = 0.0)]
+    pub slippage: f64,
+    /// How --size is applied to the strategy's target position
+    #[arg(long, value_enum, default_value_t = Sizing::Equity)]
+    pub sizing: Sizing,
+    /// Position size of a full target position: fraction of the equity, amount of cash or number of shares
+    #[arg(long, default_value_t = 1.0)]
+    pub size: f64,
+}
+
+impl BacktestArgs {
+    // Rejects strategy periods, sizes and costs no backtest can run with, the error says why
+    pub fn check(&self) -> Result<(), String> {
+        if [self.fast_period, self.rsi_period, self.bb_period, self.macd_fast, self.macd_signal].contains(&0)
+            || self.fast_period >= self.slow_period || self.macd_fast >= self.macd_slow {
+            return Err("Strategy periods must be at least 1 with the fast periods shorter than the slow periods".to_owned());
+        }
+        if self.bb_multiplier <= 0.0 {
+            return Err("The Bollinger Band multiplier must be positive".to_owned());
+        }
+        if self.capital <= 0.0 || self.size <= 0.0 {
+            return Err("The capital and the position size must be positive".to_owned());
+        }
+        if self.commission < 0.0 || self.commission_percent < 0.0 || self.slippage < 0.0 {
+            return Err("Commissions and slippage must not be negative".to_owned());
+        }
+        Ok(())
+    }
+}
+
+// A trading rule replayed bar by bar. After every bar it returns the position it wants to hold as a fraction
+// of the sizing: 1.0 fully long, 0.0 flat, -1.0 fully short, anything in between for a partial position.
+// `on_bar` may only look at `bars[..=index]`, the engine decides when the order is filled.
+pub trait Strategy {
+    fn name(&self) -> String;
+    fn on_bar(&mut self, bars: &[Bar], index: usize) -> f64;
+}
+
+// Price a position change is filled at
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum FillPrice {
+    /// Open of the bar after the one that produced the signal
+    NextOpen,
+    /// Close of the bar that produced the signal
+    Close,
+}
+
+// What a target position of 1.0 means
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Sizing {
+    /// --size is the fraction of the current equity to invest
+    Equity,
+    /// --size is a fixed amount of cash to invest
+    Cash,
+    /// --size is a fixed number of shares
+    Shares,
+}
+
+#[derive(Clone, Copy)]
+pub struct BacktestConfig {
+    pub capital: f64,
+    pub fill: FillPrice,
+    // Fixed commission per order plus a percentage of the traded value
+    pub commission: f64,
+    pub commission_percent: f64,
+    // Percentage the fill price moves against the order
+    pub slippage_percent: f64,
+    pub sizing: Sizing,
+    pub size: f64,
+}
+
+// A round trip from opening a position to closing (or reversing) it. `quantity` is the largest number of
+// shares held, negative for a short position. Trades still open after the last bar are valued at its close.
+pub struct Trade {
+    pub entry_index: usize,
+    pub exit_index: usize,
+    pub quantity: f64,
+    pub entry_price: f64,
+    pub exit_price: f64,
+    // Profit or loss after commissions
+    pub pnl: f64,
+    pub open: bool,
+}
+
+impl Trade {
+    // P&L relative to the value of the position when it was opened
+    pub fn return_percent(&self) -> f64 {
+        self.pnl / (self.quantity.abs() * self.entry_price) * 100.0
+    }
+}
+
+pub struct Backtest {
+    pub strategy_name: String,
+    pub capital: f64,
+    pub fill: FillPrice,
+    // Index of the first replayed bar, the trades' bar indices count from the first bar including the history
+    pub start: usize,
+    // One value per replayed bar, marked at the close
+    pub times: Vec<DateTime<Utc>>,
+    pub equity: Vec<f64>,
+    pub positions: Vec<f64>,
+    pub trades: Vec<Trade>,
+}
+
+pub struct Metrics {
+    pub final_equity: f64,
+    pub trades: usize,
+    pub total_return: f64,
+    pub cagr: f64,
+    pub sharpe: f64,
+    pub sortino: f64,
+    pub max_drawdown: f64,
+    pub win_rate: f64,
+    pub profit_factor: f64,
+}
+
+// Books fills into trades: a position is opened from flat, grown or reduced, and closed when it returns to flat
+// or is reversed, which closes the trade and opens one in the other direction
+struct TradeBook {
+    open: Option<Trade>,
+    closed: Vec<Trade>,
+}
+
+impl TradeBook {
+    fn fill(&mut self, index: usize, old_position: f64, new_position: f64, price: f64, commission: f64) {
+        let traded = (new_position - old_position).abs();
+        let closes = old_position != 0.0 && (new_position == 0.0 || old_position.signum() != new_position.signum());
+        if let Some(mut trade) = self.open.take() {
+            if closes {
+                // The commission is split between the closing and the opening part of the order
+                trade.pnl += old_position * (price - trade.entry_price) - commission * old_position.abs() / traded;
+                trade.exit_index = index;
+                trade.exit_price = price;
+                trade.open = false;
+                self.closed.push(trade);
+            } else {
+                if new_position.abs() > old_position.abs() {
+                    trade.entry_price = (old_position * trade.entry_price + (new_position - old_position) * price) / new_position;
+                    trade.quantity = new_position;
+                } else {
+                    trade.pnl += (old_position - new_position) * (price - trade.entry_price);
+                }
+                trade.pnl -= commission;
+                trade.exit_index = index;
+                trade.exit_price = price;
+                self.open = Some(trade);
+                return;
+            }
+        }
+        if new_position != 0.0 {
+            let opening_commission = commission * new_position.abs() / traded;
+            self.open = Some(Trade {
+                entry_index: index,
+                exit_index: index,
+                quantity: new_position,
+                entry_price: price,
+                exit_price: price,
+                pnl: -opening_commission,
+                open: true,
+            });
+        }
+    }
+}
+
+// Replays `bars[start..]` through the strategy, the bars before `start` are history the strategy's
+// indicators were warmed up on
+pub fn run_backtest(bars: &[Bar], start: usize, strategy: &mut dyn Strategy, config: &BacktestConfig) -> Backtest {
+    let mut cash = config.capital;
+    let mut position = 0.0;
+    let mut target = 0.0;
+    let mut pending: Option<f64> = None;
+    let mut book = TradeBook { open: None, closed: Vec::new() };
+    let (mut times, mut equity, mut positions) = (Vec::new(), Vec::new(), Vec::new());
+
+    let mut execute = |index: usize, exposure: f64, price: f64, cash: &mut f64, position: &mut f64| {
+        let desired = match config.sizing {
+            Sizing::Equity => exposure * config.size * (*cash + *position * price) / price,
+            Sizing::Cash => exposure * config.size / price,
+            Sizing::Shares => exposure * config.size,
+        };
+        let delta = desired - *position;
+        if delta == 0.0 || !delta.is_finite() {
+            return;
+        }
+        let fill_price = price * (1.0 + delta.signum() * config.slippage_percent / 100.0);
+        let commission = config.commission + config.commission_percent / 100.0 * delta.abs() * fill_price;
+        *cash -= delta * fill_price + commission;
+        book.fill(index, *position, desired, fill_price, commission);
+        *position = desired;
+    };
+
+    for (index, bar) in bars.iter().enumerate().skip(start) {
+        if let Some(exposure) = pending.take() {
+            execute(index, exposure, bar.open, &mut cash, &mut position);
+        }
+        // Only a change of the target trades, so an equity sized position is not rebalanced on every bar
+        let exposure = strategy.on_bar(bars, index);
+        if exposure.is_finite() && exposure != target {
+            target = exposure;
+            match config.fill {
+                FillPrice::Close => execute(index, exposure, bar.close, &mut cash, &mut position),
+                FillPrice::NextOpen => pending = Some(exposure),
+            }
+        }
+        times.push(bar.time());
+        equity.push(cash + position * bar.close);
+        positions.push(position);
+    }
+
+    let mut trades = book.closed;
+    if let (Some(mut trade), Some(last_bar)) = (book.open, bars.last()) {
+        trade.pnl += position * (last_bar.close - trade.entry_price);
+        trade.exit_index = bars.len() - 1;
+        trade.exit_price = last_bar.close;
+        trades.push(trade);
+    }
+
+    Backtest {
+        strategy_name: strategy.name(),
+        capital: config.capital,
+        fill: config.fill,
+        start,
+        times,
+        equity,
+        positions,
+        trades,
+    }
+}
+
+// Years from the first to the last time, 0 without times
+pub fn years_between(times: &[DateTime<Utc>]) -> f64 {
+    match (times.first(), times.last()) {
+        (Some(first), Some(last)) => (*last - *first).num_seconds() as f64 / (365.25 * 86_400.0),
+        _ => 0.0,
+    }
+}
+
+// Per bar returns of an equity curve
+pub fn returns(equity: &[f64]) -> Vec<f64> {
+    equity.windows(2).map(|pair| pair[1] / pair[0] - 1.0).collect()
+}
+
+// Largest fall from a previous peak of the equity curve for every bar, as a negative fraction
+pub fn drawdowns(equity: &[f64]) -> Vec<f64> {
+    let mut peak = f64::MIN;
+    equity.iter().map(|&value| {
+        peak = peak.max(value);
+        value / peak - 1.0
+    }).collect()
+}
+
+// Metrics of an equity curve starting at `capital`. Sharpe and Sortino ratios are annualised with the number
+// of bars per year in the curve and assume a risk-free rate of zero.
+pub fn metrics(times: &[DateTime<Utc>], equity: &[f64], capital: f64, trades: &[Trade]) -> Metrics {
+    let final_equity = equity.last().copied().unwrap_or(capital);
+    let total_return = final_equity / capital - 1.0;
+    let years = years_between(times);
+    let cagr = if years > 0.0 { (final_equity / capital).powf(1.0 / years) - 1.0 } else { f64::NAN };
+
+    let returns = returns(equity);
+    let bars_per_year = if years > 0.0 { returns.len() as f64 / years } else { f64::NAN };
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    let deviation = (returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / returns.len() as f64).sqrt();
+    let downside_deviation = (returns.iter().map(|r| r.min(0.0).powi(2)).sum::<f64>() / returns.len() as f64).sqrt();
+    let sharpe = mean / deviation * bars_per_year.sqrt();
+    let sortino = mean / downside_deviation * bars_per_year.sqrt();
+    let max_drawdown = -drawdowns(equity).into_iter().fold(0.0, f64::min);
+
+    let winners = trades.iter().filter(|trade| trade.pnl > 0.0).count();
+    let win_rate = winners as f64 / trades.len() as f64;
+    // Folded from +0.0, an empty f64 sum is -0.0 and would give a profit factor of -0.00 without winners
+    let gross_profit = trades.iter().filter(|trade| trade.pnl > 0.0).fold(0.0, |sum, trade| sum + trade.pnl);
+    let gross_loss = trades.iter().filter(|trade| trade.pnl < 0.0).fold(0.0, |sum, trade| sum - trade.pnl);
+    let profit_factor = if gross_loss > 0.0 { gross_profit / gross_loss } else if gross_profit > 0.0 { f64::INFINITY } else { f64::NAN };
+
+    Metrics { final_equity, trades: trades.len(), total_return, cagr, sharpe, sortino, max_drawdown, win_rate, profit_factor }
+}
+
+impl Backtest {
+    pub fn metrics(&self) -> Metrics {
+        metrics(&self.times, &self.equity, self.capital, &self.trades)
+    }
+
+    // Closes a position still open after the last bar at that bar's close, paying the slippage and commission
+    // of the closing order, so the final equity is cash another backtest can start with
+    pub fn close_at_last_bar(&mut self, last_bar: &Bar, config: &BacktestConfig) {
+        let (Some(position), Some(equity)) = (self.positions.last_mut(), self.equity.last_mut()) else {
+            return;
+        };
+        if *position == 0.0 {
+            return;
+        }
+        let fill_price = last_bar.close * (1.0 - position.signum() * config.slippage_percent / 100.0);
+        let commission = config.commission + config.commission_percent / 100.0 * position.abs() * fill_price;
+        let cost = *position * (last_bar.close - fill_price) + commission;
+        *equity -= cost;
+        *position = 0.0;
+        if let Some(trade) = self.trades.iter_mut().rev().find(|trade| trade.open) {
+            trade.pnl -= cost;
+            trade.exit_price = fill_price;
+            trade.open = false;
+        }
+    }
+
+    // Prints the trade log and the metrics next to those of the benchmark
+    pub fn print(&self, benchmark: &Backtest, bars: &[Bar], ticker_name: &str) {
+        let fill = match self.fill {
+            FillPrice::NextOpen => "next open",
+            FillPrice::Close => "close",
+        };
+        println!("Backtest of {} on {}, filled at the {}", self.strategy_name, ticker_name, fill);
+        println!("Trades ({})", self.trades.len());
+        for trade in &self.trades {
+            println!("  {} {:<5} {:>10.2} shares at {:>9.2} -> {} at {:>9.2}{}  P&L {:>10.2} ({:+.2}%)",
+                     bars[trade.entry_index].time().format("%Y-%m-%d"),
+                     if trade.quantity > 0.0 { "long" } else { "short" },
+                     trade.quantity.abs(), trade.entry_price,
+                     bars[trade.exit_index].time().format("%Y-%m-%d"), trade.exit_price,
+                     if trade.open { " (open)" } else { "" },
+                     trade.pnl, trade.return_percent());
+        }
+        print_metrics(&[(&self.strategy_name, &self.metrics()), (&benchmark.strategy_name, &benchmark.metrics())]);
+    }
+
+    // Writes backtest_<ticker>.json with the trades, the equity curve and the metrics of the strategy and the
+    // equity curve and metrics of the benchmark, returns the name of the file
+    pub fn write(&self, benchmark: &Backtest, bars: &[Bar], ticker_name: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let date = |index: usize| bars[index].time().format("%Y-%m-%d").to_string();
+        let trades: Vec<serde_json::Value> = self.trades.iter()
+            .map(|trade| json!({
+                "entry_date": date(trade.entry_index),
+                "exit_date": date(trade.exit_index),
+                "quantity": trade.quantity,
+                "entry_price": trade.entry_price,
+                "exit_price": trade.exit_price,
+                "pnl": trade.pnl,
+                "return_percent": trade.return_percent(),
+                "open": trade.open,
+            }))
+            .collect();
+        let metrics = self.metrics();
+        let document = json!({
+            "ticker": ticker_name,
+            "strategy": self.strategy_name,
+            "capital": self.capital,
+            "dates": self.times.iter().map(|time| time.format("%Y-%m-%d").to_string()).collect::<Vec<String>>(),
+            "equity": self.equity,
+            "positions": self.positions,
+            "trades": trades,
+            "metrics": metrics.to_json(),
+            "benchmark": {
+                "strategy": benchmark.strategy_name,
+                "equity": benchmark.equity,
+                "metrics": benchmark.metrics().to_json(),
+            },
+        });
+
+        let backtest_file_name = "backtest_".to_owned() + ticker_name + ".json";
+        serde_json::to_writer_pretty(File::create(&backtest_file_name)?, &document)?;
+        Ok(backtest_file_name)
+    }
+}
+
+// Table of metrics with one column per (name, metrics) pair
+pub fn print_metrics(columns: &[(&str, &Metrics)]) {
+    let width = columns.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(12);
+    let row = |label: &str, value: &dyn Fn(&Metrics) -> String| {
+        let values: Vec<String> = columns.iter().map(|(_, metrics)| format!("{:>width$}", value(metrics))).collect();
+        println!("  {:<14} {}", label, values.join("  "));
+    };
+    let names: Vec<String> = columns.iter().map(|(name, _)| format!("{:>width$}", name)).collect();
+    println!("  {:<14} {}", "", names.join("  "));
+    row("Final equity", &|metrics| format!("{:.2}", metrics.final_equity));
+    row("Total return", &|metrics| format!("{:+.2}%", metrics.total_return * 100.0));
+    row("CAGR", &|metrics| format!("{:+.2}%", metrics.cagr * 100.0));
+    row("Sharpe ratio", &|metrics| format!("{:.2}", metrics.sharpe));
+    row("Sortino ratio", &|metrics| format!("{:.2}", metrics.sortino));
+    row("Max drawdown", &|metrics| format!("{:.2}%", metrics.max_drawdown * 100.0));
+    row("Trades", &|metrics| metrics.trades.to_string());
+    row("Win rate", &|metrics| format!("{:.1}%", metrics.win_rate * 100.0));
+    row("Profit factor", &|metrics| format!("{:.2}", metrics.profit_factor));
+}
+
+impl Backtest {
+    // Buy and sell markers for the replayed bars' price chart: triangles below the low of the bars where the
+    // position grew and above the high of the bars where it shrank
+    pub fn trade_overlays(&self, bars: &[Bar]) -> Vec<PriceOverlay> {
+        let replayed = &bars[self.start..];
+        let mut buys = vec![f64::NAN; replayed.len()];
+        let mut sells = vec![f64::NAN; replayed.len()];
+        let mut previous = 0.0;
+        for (i, &position) in self.positions.iter().enumerate() {
+            if position > previous {
+                buys[i] = replayed[i].low;
+            } else if position < previous {
+                sells[i] = replayed[i].high;
+            }
+            previous = position;
+        }
+        vec![
+            PriceOverlay::Triangles { label: "Buy".to_owned(), values: buys, pointing_up: true, color: GREEN_700 },
+            PriceOverlay::Triangles { label: "Sell".to_owned(), values: sells, pointing_up: false, color: RED_700 },
+        ]
+    }
+}
+
+// Equity of the strategy against buy-and-hold, the bars a losing trade was held are shaded red
+pub fn equity_chart(result: &Backtest, benchmark: &Backtest, ticker_name: &str, currency: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "equity_".to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let times = &result.times;
+    let (min_value, max_value) = finite_range(result.equity.iter().chain(benchmark.equity.iter()));
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Equity of {} for {}", result.strategy_name, ticker_name), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(times[0]..times[times.len() - 1], min_value..max_value)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Equity", currency))
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    let losing: Vec<(DateTime<Utc>, DateTime<Utc>)> = result.trades.iter()
+        .filter(|trade| trade.pnl < 0.0)
+        .map(|trade| (times[trade.entry_index - result.start], times[trade.exit_index - result.start]))
+        .collect();
+    if !losing.is_empty() {
+        chart.draw_series(losing.iter().map(|&(entry, exit)| Rectangle::new([(entry, min_value), (exit, max_value)], RED.mix(0.1).filled())))?
+            .label("Losing trade")
+            .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], RED.mix(0.2).filled()));
+    }
+
+    for (backtest, color) in [(benchmark, BLACK), (result, BLUE)] {
+        chart.draw_series(LineSeries::new(
+            times.iter().zip(backtest.equity.iter()).map(|(&time, &value)| (time, value)),
+            color.stroke_width(2),
+        ))?
+            .label(backtest.strategy_name.clone())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+// Underwater chart: how far the equity of the strategy and of buy-and-hold is below its previous peak
+pub fn drawdown_chart(result: &Backtest, benchmark: &Backtest, ticker_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "drawdown_".to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let times = &result.times;
+    let strategy_drawdowns: Vec<f64> = drawdowns(&result.equity).iter().map(|drawdown| drawdown * 100.0).collect();
+    let benchmark_drawdowns: Vec<f64> = drawdowns(&benchmark.equity).iter().map(|drawdown| drawdown * 100.0).collect();
+    let (min_value, _) = finite_range(strategy_drawdowns.iter().chain(benchmark_drawdowns.iter()));
+    // Leave room above zero so the zero line is not on the frame
+    let min_value = min_value.min(-1.0);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Drawdown of {} for {}", result.strategy_name, ticker_name), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(times[0]..times[times.len() - 1], min_value * 1.05..-min_value * 0.05)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc("Drawdown (%)")
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    chart.draw_series(AreaSeries::new(
+        times.iter().zip(strategy_drawdowns.iter()).map(|(&time, &drawdown)| (time, drawdown)),
+        0.0,
+        RED.mix(0.3),
+    ).border_style(RED_700))?
+        .label(result.strategy_name.clone())
+        .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], RED.mix(0.3).filled()));
+
+    chart.draw_series(LineSeries::new(
+        times.iter().zip(benchmark_drawdowns.iter()).map(|(&time, &drawdown)| (time, drawdown)),
+        BLACK,
+    ))?
+        .label(benchmark.strategy_name.clone())
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK));
+
+    // Mark the deepest point of the strategy's drawdown
+    if let Some((i, &deepest)) = strategy_drawdowns.iter().enumerate().min_by(|a, b| a.1.total_cmp(b.1)) {
+        if deepest < 0.0 {
+            chart.draw_series(std::iter::once(TriangleMarker::new((times[i], deepest), 8, BLACK.filled())))?;
+            chart.draw_series(std::iter::once(Text::new(
+                format!("Max drawdown {:.2}% on {}", deepest, times[i].format("%Y-%m-%d")),
+                (times[i], deepest),
+                ("sans-serif", 15).into_font().color(&BLACK).pos(Pos::new(HPos::Left, VPos::Top)),
+            )))?;
+        }
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::LowerLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+impl Metrics {
+    // Non-finite metrics (no trades, no losing trades, ...) are written as null
+    pub fn to_json(&self) -> serde_json::Value {
+        let value = |metric: f64| if metric.is_finite() { json!(metric) } else { serde_json::Value::Null };
+        json!({
+            "final_equity": self.final_equity,
+            "trades": self.trades,
+            "total_return": value(self.total_return),
+            "cagr": value(self.cagr),
+            "sharpe": value(self.sharpe),
+            "sortino": value(self.sortino),
+            "max_drawdown": value(self.max_drawdown),
+            "win_rate": value(self.win_rate),
+            "profit_factor": value(self.profit_factor),
+        })
+    }
+}
+
+pub fn strategy_params(stock_args: &Stock, backtest_args: &BacktestArgs) -> StrategyParams {
+    StrategyParams {
+        fast_period: backtest_args.fast_period,
+        slow_period: backtest_args.slow_period,
+        rsi_period: backtest_args.rsi_period,
+        rsi_oversold: stock_args.rsi_oversold,
+        rsi_overbought: stock_args.rsi_overbought,
+        bb_period: backtest_args.bb_period,
+        bb_multiplier: backtest_args.bb_multiplier,
+        bb_mode: backtest_args.bb_mode,
+        macd_fast: backtest_args.macd_fast,
+        macd_slow: backtest_args.macd_slow,
+        macd_signal: backtest_args.macd_signal,
+    }
+}
+
+pub fn backtest_config(backtest_args: &BacktestArgs) -> BacktestConfig {
+    BacktestConfig {
+        capital: backtest_args.capital,
+        fill: backtest_args.fill,
+        commission: backtest_args.commission,
+        commission_percent: backtest_args.commission_percent,
+        slippage_percent: backtest_args.slippage,
+        sizing: backtest_args.sizing,
+        size: backtest_args.size,
+    }
+}
+
+// Fetches the stock prices to backtest on, preceded by `warmup` bars of history with --warmup-history.
+// Returns the bars, the index of the first bar of the requested range and the currency.
+pub async fn fetch_backtest_bars(stock_args: &Stock, warmup: usize) -> Option<(Vec<Bar>, usize, String)> {
+    let response = fetch_stock(&stock_args.name, &stock_args.interval, &stock_args.range).await;
+    let currency = response.as_ref().ok().and_then(|data| data.metadata().ok()).and_then(|metadata| metadata.currency).unwrap_or_default();
+    let stock_prices: Vec<Bar> = match response.and_then(|data| data.quotes()) {
+        Ok(quotes) => quotes.iter().map(Bar::from_quote).collect(),
+        Err(e) => {
+            println!("Error occured check log file for more details");
+            error!("Yahoo finance errored out while fetching the stock prices for the backtest:{}", e);
+            return None;
+        }
+    };
+    if stock_prices.is_empty() {
+        println!("No stock prices found for {}", stock_args.name);
+        return None;
+    }
+    let mut bars: Vec<Bar> = Vec::new();
+    if stock_args.warmup_history {
+        match fetch_warmup_history(&stock_args.name, &stock_args.interval, stock_prices[0].timestamp, warmup).await {
+            Ok(history) => bars = history,
+            Err(e) => {
+                println!("Error while fetching warm-up history check log file for more details");
+                error!("Error while fetching warm-up history:{:?}",e)
+            }
+        }
+    }
+    let start = bars.len();
+    bars.extend(stock_prices);
+    Some((bars, start, currency))
+}
+
+// Backtests the strategy and the buy-and-hold benchmark
+pub async fn run_command(stock_args: &Stock, backtest_args: &BacktestArgs) {
+    if let Err(e) = backtest_args.check() {
+        println!("{}", e);
+        return;
+    }
+    let params = strategy_params(stock_args, backtest_args);
+    let Some((bars, start, currency)) = fetch_backtest_bars(stock_args, params.warmup(backtest_args.strategy)).await else {
+        return;
+    };
+    let config = backtest_config(backtest_args);
+    let mut strategy = build_strategy(backtest_args.strategy, &params, &bars);
+    let result = run_backtest(&bars, start, strategy.as_mut(), &config);
+    let benchmark = run_backtest(&bars, start, &mut BuyAndHold, &config);
+    result.print(&benchmark, &bars, &stock_args.name);
+
+    let charts = [
+        ("Trade Chart", stock_picture_creator("backtest_chart_", &bars[start..], &result.trade_overlays(&bars), &stock_args.name, &currency)),
+        ("Equity Chart", equity_chart(&result, &benchmark, &stock_args.name, &currency)),
+        ("Drawdown Chart", drawdown_chart(&result, &benchmark, &stock_args.name)),
+    ];
+    for (chart_name, chart) in charts {
+        match chart {
+            Ok(_) => {debug!("Backtest {} Created Successfully", chart_name)}
+            Err(e) => {
+                println!("Error while creating the backtest {} check log file for more details", chart_name.to_lowercase());
+                error!("Error while creating the Backtest {}:{:?}", chart_name, e)
+            }
+        }
+    }
+    if stock_args.export {
+        match result.write(&benchmark, &bars, &stock_args.name) {
+            Ok(backtest_file_name) => println!("Backtest written to {}", backtest_file_name),
+            Err(e) => {
+                println!("Error while writing the backtest check log file for more details");
+                error!("Error while writing the backtest:{:?}",e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Replays a fixed list of target positions, one per bar
+    struct Scripted(Vec<f64>);
+
+    impl Strategy for Scripted {
+        fn name(&self) -> String {
+            "Scripted".to_owned()
+        }
+
+        fn on_bar(&mut self, _bars: &[Bar], index: usize) -> f64 {
+            self.0[index]
+        }
+    }
+
+    // Daily bars from (open, close) pairs
+    fn bars(prices: &[(f64, f64)]) -> Vec<Bar> {
+        prices.iter().enumerate()
+            .map(|(i, &(open, close))| Bar::new(i as u64 * 86_400, open, open.max(close), open.min(close), close, 0.0))
+            .collect()
+    }
+
+    fn closes(closes: &[f64]) -> Vec<Bar> {
+        bars(&closes.iter().map(|&close| (close, close)).collect::<Vec<(f64, f64)>>())
+    }
+
+    fn config(fill: FillPrice, sizing: Sizing, size: f64) -> BacktestConfig {
+        BacktestConfig { capital: 1000.0, fill, commission: 0.0, commission_percent: 0.0, slippage_percent: 0.0, sizing, size }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn partial_reduction_books_one_trade() {
+        // Buy 10 at 10, sell 5 at 15 (+25), sell 5 at 11 (+5), three orders at 1 each
+        let bars = closes(&[10.0, 12.0, 15.0, 11.0]);
+        let config = BacktestConfig { commission: 1.0, ..config(FillPrice::Close, Sizing::Shares, 10.0) };
+        let result = run_backtest(&bars, 0, &mut Scripted(vec![1.0, 1.0, 0.5, 0.0]), &config);
+        assert_eq!(result.trades.len(), 1);
+        let trade = &result.trades[0];
+        assert_eq!((trade.entry_index, trade.exit_index, trade.open), (0, 3, false));
+        assert_close(trade.quantity, 10.0);
+        assert_close(trade.entry_price, 10.0);
+        assert_close(trade.exit_price, 11.0);
+        assert_close(trade.pnl, 27.0);
+        assert_eq!(result.positions, vec![10.0, 10.0, 5.0, 0.0]);
+        for (actual, expected) in result.equity.iter().zip([999.0, 1019.0, 1048.0, 1027.0]) {
+            assert_close(*actual, expected);
+        }
+    }
+
+    #[test]
+    fn reversal_splits_the_commission_and_values_the_open_trade_at_the_last_close() {
+        // Long 10 at 10, reversed to short 10 at 12 with a 2.4 commission on the 20 shares traded, the short is
+        // still open at the last close of 8
+        let bars = closes(&[10.0, 12.0, 8.0]);
+        let config = BacktestConfig { commission_percent: 1.0, ..config(FillPrice::Close, Sizing::Shares, 10.0) };
+        let result = run_backtest(&bars, 0, &mut Scripted(vec![1.0, -1.0, -1.0]), &config);
+        assert_eq!(result.trades.len(), 2);
+        let (long, short) = (&result.trades[0], &result.trades[1]);
+        // 20 profit less the 1.0 opening commission and half of the 2.4
+        assert_close(long.pnl, 17.8);
+        assert!(!long.open);
+        assert_close(short.quantity, -10.0);
+        assert_close(short.entry_price, 12.0);
+        // 40 profit less the other half of the 2.4
+        assert_close(short.pnl, 38.8);
+        assert_close(short.exit_price, 8.0);
+        assert_eq!((short.exit_index, short.open), (2, true));
+        assert_close(result.equity[2], 1000.0 + 17.8 + 38.8);
+    }
+
+    #[test]
+    fn next_open_fills_invest_the_equity() {
+        let bars = bars(&[(10.0, 10.0), (10.0, 12.0), (11.0, 11.0), (12.0, 13.0)]);
+        let result = run_backtest(&bars, 0, &mut Scripted(vec![1.0, 0.0, 0.0, 0.0]), &config(FillPrice::NextOpen, Sizing::Equity, 1.0));
+        let trade = &result.trades[0];
+        assert_eq!((trade.entry_index, trade.exit_index), (1, 2));
+        assert_close(trade.quantity, 100.0);
+        assert_close(trade.pnl, 100.0);
+        assert_eq!(result.positions, vec![0.0, 100.0, 0.0, 0.0]);
+        assert_eq!(result.equity, vec![1000.0, 1200.0, 1100.0, 1100.0]);
+    }
+
+    #[test]
+    fn next_open_skips_the_bars_before_start() {
+        // The signal on the last history bar is ignored, the one on the first replayed bar fills at the next open
+        let bars = bars(&[(10.0, 10.0), (10.0, 10.0), (20.0, 20.0)]);
+        let result = run_backtest(&bars, 1, &mut Scripted(vec![1.0, 1.0, 1.0]), &config(FillPrice::NextOpen, Sizing::Shares, 5.0));
+        assert_eq!(result.positions, vec![0.0, 5.0]);
+        assert_eq!(result.trades[0].entry_index, 2);
+        assert_eq!(result.equity, vec![1000.0, 1000.0]);
+    }
+
+    #[test]
+    fn cash_sizing_pays_slippage_both_ways() {
+        // 500 buys 50 shares filled at 10.1, sold at 19.8
+        let bars = closes(&[10.0, 20.0]);
+        let config = BacktestConfig { slippage_percent: 1.0, ..config(FillPrice::Close, Sizing::Cash, 500.0) };
+        let result = run_backtest(&bars, 0, &mut Scripted(vec![1.0, 0.0]), &config);
+        let trade = &result.trades[0];
+        assert_close(trade.quantity, 50.0);
+        assert_close(trade.entry_price, 10.1);
+        assert_close(trade.exit_price, 19.8);
+        assert_close(trade.pnl, 485.0);
+        assert_close(result.equity[1], 1485.0);
+    }
+
+    #[test]
+    fn closing_at_the_last_bar_pays_the_exit_costs() {
+        // 10 shares bought at 10.1 for a commission of 1 are sold at 19.8 for another 1
+        let bars = closes(&[10.0, 20.0]);
+        let config = BacktestConfig { commission: 1.0, slippage_percent: 1.0, ..config(FillPrice::Close, Sizing::Shares, 10.0) };
+        let mut result = run_backtest(&bars, 0, &mut Scripted(vec![1.0, 1.0]), &config);
+        assert_close(result.equity[1], 1098.0);
+        assert!(result.trades[0].open);
+        result.close_at_last_bar(&bars[1], &config);
+        assert_close(result.equity[1], 1095.0);
+        assert_eq!(result.positions, vec![10.0, 0.0]);
+        let trade = &result.trades[0];
+        assert!(!trade.open);
+        assert_close(trade.exit_price, 19.8);
+        assert_close(trade.pnl, 95.0);
+    }
+
+    #[test]
+    fn adding_to_a_position_averages_the_entry_price() {
+        let bars = closes(&[10.0, 16.0, 20.0]);
+        let result = run_backtest(&bars, 0, &mut Scripted(vec![0.5, 1.0, 0.0]), &config(FillPrice::Close, Sizing::Shares, 10.0));
+        let trade = &result.trades[0];
+        assert_close(trade.entry_price, 13.0);
+        assert_close(trade.pnl, 70.0);
+        assert_close(result.equity[2], 1070.0);
+    }
+
+    // A trade that only carries its P&L
+    fn trade(pnl: f64) -> Trade {
+        Trade { entry_index: 0, exit_index: 1, quantity: 1.0, entry_price: 1.0, exit_price: 1.0, pnl, open: false }
+    }
+
+    // Four values three equal steps of a quarter year apart, so there are 4 bars per year
+    fn quarters() -> Vec<DateTime<Utc>> {
+        (0..4).map(|i| DateTime::from_timestamp(i * 7_889_400, 0).unwrap()).collect()
+    }
+
+    #[test]
+    fn ratios_of_a_known_curve() {
+        // Returns +10%, -10%, +10%
+        let metrics = metrics(&quarters(), &[100.0, 110.0, 99.0, 108.9], 100.0, &[trade(20.0), trade(-10.0), trade(5.0)]);
+        assert_close(metrics.total_return, 0.089);
+        assert_close(metrics.cagr, 1.089f64.powf(4.0 / 3.0) - 1.0);
+        // Mean 1/30 over a deviation of sqrt(2/225) and a downside deviation of sqrt(1/300), times sqrt(4)
+        assert_close(metrics.sharpe, (1.0 / 30.0) / (2.0f64 / 225.0).sqrt() * 2.0);
+        assert_close(metrics.sortino, (1.0 / 30.0) / (1.0f64 / 300.0).sqrt() * 2.0);
+        assert_close(metrics.max_drawdown, 0.1);
+        assert_close(metrics.win_rate, 2.0 / 3.0);
+        assert_close(metrics.profit_factor, 2.5);
+    }
+
+    #[test]
+    fn ratios_on_edge_inputs() {
+        let flat = metrics(&quarters(), &[100.0; 4], 100.0, &[]);
+        assert!(flat.sharpe.is_nan() && flat.sortino.is_nan());
+        assert!(flat.win_rate.is_nan() && flat.profit_factor.is_nan());
+        assert_eq!((flat.max_drawdown, flat.trades), (0.0, 0));
+
+        let rising = metrics(&quarters(), &[100.0, 101.0, 102.0, 103.0], 100.0, &[trade(3.0)]);
+        assert_eq!(rising.sortino, f64::INFINITY);
+        assert_eq!(rising.profit_factor, f64::INFINITY);
+
+        let losing = metrics(&quarters(), &[100.0, 99.0, 98.0, 97.0], 100.0, &[trade(-1.0), trade(-2.0)]);
+        assert_eq!(losing.win_rate, 0.0);
+        assert!(losing.profit_factor == 0.0 && losing.profit_factor.is_sign_positive());
+
+        let empty = metrics(&[], &[], 100.0, &[]);
+        assert_eq!((empty.final_equity, empty.total_return), (100.0, 0.0));
+        assert!(empty.cagr.is_nan() && empty.sharpe.is_nan());
+    }
+}
@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+use ta::{Close, High, Low, Open, Volume};
+use yahoo_finance_api::Quote;
+
+// One bar of stock prices (OHLCV), the `ta` range based indicators take it directly as input
+#[derive(Clone, Copy, Debug)]
+pub struct Bar {
+    pub timestamp: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+    // true when the high-low range exceeds 2% of the close
+    pub volatile: bool,
+}
+
+impl Bar {
+    pub fn new(timestamp: u64, open: f64, high: f64, low: f64, close: f64, volume: f64) -> Bar {
+        let intra_day_high_low: f64 = high - low;
+        let threshold = 0.02 * close;
+        Bar { timestamp, open, high, low, close, volume, volatile: intra_day_high_low > threshold }
+    }
+
+    pub fn from_quote(quote: &Quote) -> Bar {
+        Bar::new(quote.timestamp, quote.open, quote.high, quote.low, quote.close, quote.volume as f64)
+    }
+
+    pub fn time(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.timestamp as i64, 0).unwrap()
+    }
+}
+
+impl Open for Bar {
+    fn open(&self) -> f64 {
+        self.open
+    }
+}
+
+impl High for Bar {
+    fn high(&self) -> f64 {
+        self.high
+    }
+}
+
+impl Low for Bar {
+    fn low(&self) -> f64 {
+        self.low
+    }
+}
+
+impl Close for Bar {
+    fn close(&self) -> f64 {
+        self.close
+    }
+}
+
+impl Volume for Bar {
+    fn volume(&self) -> f64 {
+        self.volume
+    }
+}
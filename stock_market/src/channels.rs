@@ -0,0 +1,139 @@
+use chrono::{DateTime, Utc};
+use plotters::prelude::*;
+use plotters::style::full_palette::ORANGE;
+use ta::indicators::{ChandelierExit, KeltnerChannel, Maximum, Minimum};
+use ta::Next;
+
+use crate::bar::Bar;
+use crate::overlay::{draw_price_overlays, PriceOverlay};
+use crate::{axis_title, finite_range, mask_warmup, sma_warmup, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+// Keltner Channels: EMA of the typical price ± `multiplier` times the ATR. Returns (average, upper, lower).
+pub fn keltner_values(bars: &[Bar], period: usize, multiplier: f64) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+    let mut keltner = KeltnerChannel::new(period, multiplier).unwrap();
+    let mut average = Vec::new();
+    let mut upper = Vec::new();
+    let mut lower = Vec::new();
+    for bar in bars {
+        let output = keltner.next(bar);
+        average.push(output.average);
+        upper.push(output.upper);
+        lower.push(output.lower);
+    }
+    for values in [&mut average, &mut upper, &mut lower] {
+        mask_warmup(values, sma_warmup(period));
+    }
+    (average, upper, lower)
+}
+
+// Donchian Channels: highest high and lowest low of the last `period` bars and the midpoint between them.
+// Returns (middle, upper, lower).
+pub fn donchian_values(bars: &[Bar], period: usize) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+    let mut maximum = Maximum::new(period).unwrap();
+    let mut minimum = Minimum::new(period).unwrap();
+    let mut upper: Vec<f64> = bars.iter().map(|bar| maximum.next(bar)).collect();
+    let mut lower: Vec<f64> = bars.iter().map(|bar| minimum.next(bar)).collect();
+    mask_warmup(&mut upper, sma_warmup(period));
+    mask_warmup(&mut lower, sma_warmup(period));
+    let middle: Vec<f64> = upper.iter().zip(lower.iter()).map(|(&upper, &lower)| (upper + lower) / 2.0).collect();
+    (middle, upper, lower)
+}
+
+// ATR stop bands (Chandelier Exit): the stop for long positions hangs `multiplier` ATRs below the highest
+// high of the last `period` bars, the stop for short positions sits as far above the lowest low.
+// Returns (long_stop, short_stop).
+pub fn atr_stop_values(bars: &[Bar], period: usize, multiplier: f64) -> (Vec<f64>, Vec<f64>) {
+    let mut chandelier_exit = ChandelierExit::new(period, multiplier).unwrap();
+    let mut long_stop = Vec::new();
+    let mut short_stop = Vec::new();
+    for bar in bars {
+        let output = chandelier_exit.next(bar);
+        long_stop.push(output.long);
+        short_stop.push(output.short);
+    }
+    mask_warmup(&mut long_stop, sma_warmup(period));
+    mask_warmup(&mut short_stop, sma_warmup(period));
+    (long_stop, short_stop)
+}
+
+// The squeeze is on while the Bollinger Bands sit completely inside the Keltner Channel. Bars where
+// either channel is still warming up are NaN, the others 1.0 (squeeze on) or 0.0 (squeeze off).
+pub fn squeeze_values(bollinger_upper: &[f64], bollinger_lower: &[f64], keltner_upper: &[f64], keltner_lower: &[f64]) -> Vec<f64> {
+    (0..bollinger_upper.len())
+        .map(|i| {
+            let bands = [bollinger_upper[i], bollinger_lower[i], keltner_upper[i], keltner_lower[i]];
+            if !bands.iter().all(|band| band.is_finite()) {
+                f64::NAN
+            } else if bollinger_upper[i] < keltner_upper[i] && bollinger_lower[i] > keltner_lower[i] {
+                1.0
+            } else {
+                0.0
+            }
+        })
+        .collect()
+}
+
+// Layout of a chart showing the close price inside one or more channels
+pub struct ChannelChart<'a> {
+    pub file_prefix: &'a str,
+    pub caption: &'a str,
+    pub channels: Vec<PriceOverlay>,
+    // Bars where the squeeze is on (1.0) are shaded
+    pub squeeze: Option<&'a [f64]>,
+}
+
+pub fn channel_chart(channel: ChannelChart, closing_prices: &[f64], ticker_name: &str, currency: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = channel.file_prefix.to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let channel_values: Vec<f64> = channel.channels.iter().flat_map(|channel| channel.values()).collect();
+    let (min_value, max_value) = finite_range(closing_prices.iter().chain(channel_values.iter()));
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(channel.caption.to_owned() + ticker_name, ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(times[0]..times[times.len() - 1], min_value..max_value)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Price", currency))
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    if let Some(squeeze) = channel.squeeze {
+        // Shade every squeeze bar up to the next bar so consecutive bars form one block
+        let squeeze_bars: Vec<usize> = (0..times.len()).filter(|&i| squeeze[i] == 1.0).collect();
+        let squeeze_series = chart.draw_series(squeeze_bars.iter().map(|&i| {
+            let end = times[(i + 1).min(times.len() - 1)];
+            Rectangle::new([(times[i], min_value), (end, max_value)], ORANGE.mix(0.2).filled())
+        }))?;
+        if !squeeze_bars.is_empty() {
+            squeeze_series
+                .label("Squeeze (Bollinger inside Keltner)")
+                .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], ORANGE.mix(0.4).filled()));
+        }
+    }
+
+    draw_price_overlays(&mut chart, &channel.channels, times)?;
+
+    chart.draw_series(LineSeries::new(
+        times.iter().zip(closing_prices.iter()).map(|(&time, &close)| (time, close)),
+        BLACK,
+    ))?
+        .label("Close")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK));
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
@@ -0,0 +1,329 @@
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use plotters::prelude::*;
+use plotters::style::full_palette::{GREEN_700, RED_700};
+use ta::indicators::AverageTrueRange;
+use ta::Next;
+
+use crate::bar::Bar;
+use crate::{axis_title, finite_range, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+// Price representations that can be drawn next to the regular price chart with --chart-types
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ChartType {
+    /// Heikin-Ashi candles
+    HeikinAshi,
+    /// Renko bricks, box size from --renko-box or the ATR
+    Renko,
+    /// Point & Figure columns, box size from --pnf-box or the ATR and reversal from --pnf-reversal
+    PointFigure,
+}
+
+// Heikin-Ashi candles: the close is the average of the bar's OHLC, the open the midpoint of the previous
+// Heikin-Ashi candle's body, so runs of one colour follow the trend and small pullbacks disappear
+pub fn heikin_ashi_values(bars: &[Bar]) -> Vec<Bar> {
+    let mut candles: Vec<Bar> = Vec::with_capacity(bars.len());
+    for bar in bars {
+        let close = (bar.open + bar.high + bar.low + bar.close) / 4.0;
+        let open = match candles.last() {
+            Some(previous) => (previous.open + previous.close) / 2.0,
+            None => (bar.open + bar.close) / 2.0,
+        };
+        let (high, low) = (bar.high.max(open).max(close), bar.low.min(open).min(close));
+        candles.push(Bar::new(bar.timestamp, open, high, low, close, bar.volume));
+    }
+    candles
+}
+
+// Average true range of the last bar, the default box size of the Renko and Point & Figure charts
+pub fn atr_box_size(bars: &[Bar], period: usize) -> f64 {
+    let mut atr = AverageTrueRange::new(period).unwrap();
+    bars.iter().map(|bar| atr.next(bar)).last().unwrap_or(f64::NAN)
+}
+
+// Most boxes the range of the closes may be split into, smaller boxes would make the Renko and Point & Figure
+// charts unreadable and their bricks and columns grow without bound
+const MAX_BOXES: f64 = 500.0;
+
+// A box size must be positive and split the range of the closes into at most MAX_BOXES boxes. The ATR of
+// flat bars is 0, which would never complete a brick
+pub fn check_box_size(bars: &[Bar], box_size: f64) -> Result<(), Box<dyn std::error::Error>> {
+    if !box_size.is_finite() || box_size <= 0.0 {
+        return Err(format!("the box size {} is not a positive price", box_size).into());
+    }
+    let (lowest, highest) = finite_range(bars.iter().map(|bar| &bar.close));
+    if (highest - lowest) / box_size > MAX_BOXES {
+        return Err(format!("the box size {} splits the closes from {:.2} to {:.2} into more than {} boxes", box_size, lowest, highest, MAX_BOXES).into());
+    }
+    Ok(())
+}
+
+// One Renko brick, rising when close > open. `index` is the bar whose close completed it
+pub struct Brick {
+    pub index: usize,
+    pub open: f64,
+    pub close: f64,
+}
+
+// Renko bricks from the closes: a new brick is added every time the close moves a full box beyond the last
+// brick, a reversal has to move two boxes (past the other end of the last brick)
+pub fn renko_values(bars: &[Bar], box_size: f64) -> Vec<Brick> {
+    let mut bricks = Vec::new();
+    let Some(first) = bars.first() else {
+        return bricks;
+    };
+    let (mut top, mut bottom) = (first.close, first.close);
+    for (index, bar) in bars.iter().enumerate().skip(1) {
+        while bar.close >= top + box_size {
+            bricks.push(Brick { index, open: top, close: top + box_size });
+            bottom = top;
+            top += box_size;
+        }
+        while bar.close <= bottom - box_size {
+            bricks.push(Brick { index, open: bottom, close: bottom - box_size });
+            top = bottom;
+            bottom -= box_size;
+        }
+    }
+    bricks
+}
+
+// One Point & Figure column of X (rising) or O (falling) boxes. `low` and `high` are box numbers, box n
+// covers the price n * box size. `index` is the bar that started the column
+pub struct Column {
+    pub index: usize,
+    pub rising: bool,
+    pub low: i64,
+    pub high: i64,
+}
+
+// Point & Figure columns from the closes: a column is extended while the close fills new boxes in its
+// direction and a new column is started when the close moves `reversal` boxes the other way
+pub fn point_figure_values(bars: &[Bar], box_size: f64, reversal: usize) -> Vec<Column> {
+    let mut columns: Vec<Column> = Vec::new();
+    let Some(first) = bars.first() else {
+        return columns;
+    };
+    let reversal = reversal as i64;
+    let start = (first.close / box_size).floor() as i64;
+    for (index, bar) in bars.iter().enumerate().skip(1) {
+        // Highest box the close fills going up and lowest box it fills going down
+        let (up, down) = ((bar.close / box_size).floor() as i64, (bar.close / box_size).ceil() as i64);
+        match columns.last_mut() {
+            // The first column starts once the close has moved a full box away from the first close
+            None => {
+                if up > start {
+                    columns.push(Column { index, rising: true, low: start, high: up });
+                } else if down < start {
+                    columns.push(Column { index, rising: false, low: down, high: start });
+                }
+            }
+            Some(column) if column.rising => {
+                if up > column.high {
+                    column.high = up;
+                } else if down <= column.high - reversal {
+                    let high = column.high - 1;
+                    columns.push(Column { index, rising: false, low: down, high });
+                }
+            }
+            Some(column) => {
+                if down < column.low {
+                    column.low = down;
+                } else if up >= column.low + reversal {
+                    let low = column.low + 1;
+                    columns.push(Column { index, rising: true, low, high: up });
+                }
+            }
+        }
+    }
+    columns
+}
+
+pub fn heikin_ashi(candles: &[Bar], ticker_name: &str, currency: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let times: Vec<DateTime<Utc>> = candles.iter().map(Bar::time).collect();
+    let chart_file_name = "heikin_ashi_".to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let (min_value, max_value) = finite_range(candles.iter().flat_map(|candle| [candle.high, candle.low]).collect::<Vec<f64>>().iter());
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Heikin-Ashi Chart for ".to_owned() + ticker_name, ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(times[0]..times[times.len() - 1], min_value..max_value)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Price", currency))
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    // Candle bodies about two thirds of the space between two bars
+    let body_width = ((WINDOW_WIDTH - 80) as f64 / candles.len() as f64 * 0.66).clamp(1.0, 15.0) as u32;
+    chart.draw_series(times.iter().zip(candles.iter()).map(|(&time, candle)| {
+        CandleStick::new(time, candle.open, candle.high, candle.low, candle.close, GREEN_700.filled(), RED_700.filled(), body_width)
+    }))?
+        .label("Heikin-Ashi")
+        .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], GREEN_700.filled()));
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+// Renko and Point & Figure charts have no time axis, the x labels show the date each brick or column formed
+fn formation_date(times: &[DateTime<Utc>], indices: &[usize], x: f64) -> String {
+    indices.get(x.max(0.0) as usize).map(|&i| times[i].format("%Y-%m-%d").to_string()).unwrap_or_default()
+}
+
+pub fn renko(bricks: &[Brick], box_size: f64, ticker_name: &str, currency: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "renko_".to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    if bricks.is_empty() {
+        return Err(format!("the closes never moved a full box of {:.2}", box_size).into());
+    }
+    let (min_value, max_value) = finite_range(bricks.iter().flat_map(|brick| [brick.open, brick.close]).collect::<Vec<f64>>().iter());
+    let indices: Vec<usize> = bricks.iter().map(|brick| brick.index).collect();
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Renko Chart for {} (box {:.2})", ticker_name, box_size), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..bricks.len() as f64, min_value..max_value)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Price", currency))
+        .x_label_formatter(&|x| formation_date(times, &indices, *x))
+        .draw()?;
+
+    for (rising, label, color) in [(true, "Rising brick", GREEN_700), (false, "Falling brick", RED_700)] {
+        chart.draw_series(bricks.iter().enumerate().filter(|(_, brick)| (brick.close > brick.open) == rising).map(|(i, brick)| {
+            Rectangle::new([(i as f64 + 0.05, brick.open), (i as f64 + 0.95, brick.close)], color.filled())
+        }))?
+            .label(label)
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], color.filled()));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+pub fn point_figure(
+    columns: &[Column],
+    box_size: f64,
+    reversal: usize,
+    ticker_name: &str,
+    currency: &str,
+    times: &[DateTime<Utc>],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "point_figure_".to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let (Some(lowest), Some(highest)) = (columns.iter().map(|column| column.low).min(), columns.iter().map(|column| column.high).max()) else {
+        return Err(format!("the closes never moved a full box of {:.2}", box_size).into());
+    };
+    let indices: Vec<usize> = columns.iter().map(|column| column.index).collect();
+
+    // Half a box of space around the outer boxes, markers sized to the smaller side of a box
+    let (min_value, max_value) = ((lowest as f64 - 0.5) * box_size, (highest as f64 + 0.5) * box_size);
+    let box_pixels = ((WINDOW_HEIGHT - 120) as f64 / (highest - lowest + 1) as f64).min((WINDOW_WIDTH - 80) as f64 / columns.len() as f64);
+    let marker_size = (box_pixels * 0.4).clamp(1.0, 12.0) as i32;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Point & Figure Chart for {} (box {:.2}, {} box reversal)", ticker_name, box_size, reversal), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(-0.5..columns.len() as f64 - 0.5, min_value..max_value)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Price", currency))
+        .x_label_formatter(&|x| formation_date(times, &indices, x.round()))
+        .draw()?;
+
+    let boxes = |rising: bool| {
+        columns.iter().enumerate()
+            .filter(move |(_, column)| column.rising == rising)
+            .flat_map(move |(i, column)| (column.low..=column.high).map(move |level| (i as f64, level as f64 * box_size)))
+    };
+    chart.draw_series(boxes(true).map(|point| Cross::new(point, marker_size, GREEN_700.stroke_width(2))))?
+        .label("X (rising)")
+        .legend(|(x, y)| Cross::new((x + 10, y), 5, GREEN_700.stroke_width(2)));
+    chart.draw_series(boxes(false).map(|point| Circle::new(point, marker_size, RED_700.stroke_width(2))))?
+        .label("O (falling)")
+        .legend(|(x, y)| Circle::new((x + 10, y), 5, RED_700.stroke_width(2)));
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Daily bars whose open, high and low are the close
+    fn closes(values: &[f64]) -> Vec<Bar> {
+        values.iter().enumerate().map(|(i, &close)| Bar::new(i as u64 * 86_400, close, close, close, close, 0.0)).collect()
+    }
+
+    #[test]
+    fn heikin_ashi_averages_the_bar_and_opens_mid_body() {
+        let bars = vec![Bar::new(0, 10.0, 14.0, 8.0, 12.0, 0.0), Bar::new(86_400, 12.0, 13.0, 11.0, 12.0, 0.0)];
+        let candles = heikin_ashi_values(&bars);
+        assert_eq!((candles[0].open, candles[0].close, candles[0].high, candles[0].low), (11.0, 11.0, 14.0, 8.0));
+        assert_eq!((candles[1].open, candles[1].close), (11.0, 12.0));
+        assert_eq!((candles[1].high, candles[1].low), (13.0, 11.0));
+    }
+
+    #[test]
+    fn renko_reversal_needs_two_boxes() {
+        // Two rising bricks to 12, the drop to 10.9 stays inside the last brick and 9.8 is two boxes down
+        let bricks = renko_values(&closes(&[10.0, 11.2, 12.5, 11.5, 10.9, 9.8]), 1.0);
+        let bricks: Vec<(usize, f64, f64)> = bricks.iter().map(|brick| (brick.index, brick.open, brick.close)).collect();
+        assert_eq!(bricks, vec![(1, 10.0, 11.0), (2, 11.0, 12.0), (5, 11.0, 10.0)]);
+    }
+
+    #[test]
+    fn point_figure_reverses_after_three_boxes() {
+        let columns = point_figure_values(&closes(&[10.0, 12.5, 13.2, 11.0, 10.0, 9.5, 12.9, 13.0]), 1.0, 3);
+        let columns: Vec<(usize, bool, i64, i64)> = columns.iter().map(|column| (column.index, column.rising, column.low, column.high)).collect();
+        assert_eq!(columns, vec![(1, true, 10, 13), (4, false, 10, 12), (7, true, 11, 13)]);
+    }
+
+    #[test]
+    fn box_size_must_be_positive_and_not_too_small() {
+        let bars = closes(&[10.0, 20.0]);
+        assert!(check_box_size(&bars, 1.0).is_ok());
+        assert!(check_box_size(&bars, 0.0).is_err());
+        assert!(check_box_size(&bars, f64::NAN).is_err());
+        assert!(check_box_size(&bars, 0.01).is_err());
+    }
+}
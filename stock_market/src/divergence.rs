@@ -0,0 +1,280 @@
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use plotters::prelude::*;
+use plotters::style::full_palette::{GREEN_700, RED_700};
+
+use crate::bar::Bar;
+use crate::levels::{swing_points, Swing, SwingKind};
+use crate::{axis_title, finite_range, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+// Swings further apart than this many bars are not compared
+const MAX_SWING_GAP: usize = 60;
+
+// Oscillators that can be checked for divergences with --divergences
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum DivergenceSource {
+    /// RSI (14)
+    Rsi,
+    /// MACD histogram (12, 26, 9)
+    Macd,
+}
+
+impl DivergenceSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DivergenceSource::Rsi => "RSI",
+            DivergenceSource::Macd => "MACD histogram",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DivergenceKind {
+    // Price makes a lower low, the oscillator a higher low
+    RegularBullish,
+    // Price makes a higher high, the oscillator a lower high
+    RegularBearish,
+    // Price makes a higher low, the oscillator a lower low
+    HiddenBullish,
+    // Price makes a lower high, the oscillator a higher high
+    HiddenBearish,
+}
+
+impl DivergenceKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DivergenceKind::RegularBullish => "regular bullish divergence",
+            DivergenceKind::RegularBearish => "regular bearish divergence",
+            DivergenceKind::HiddenBullish => "hidden bullish divergence",
+            DivergenceKind::HiddenBearish => "hidden bearish divergence",
+        }
+    }
+}
+
+// Two swings of the price and the matching swings of the oscillator that moved in different directions
+pub struct Divergence {
+    pub kind: DivergenceKind,
+    // (bar index, value) of the earlier and the later swing
+    pub price_start: (usize, f64),
+    pub price_end: (usize, f64),
+    pub oscillator_start: (usize, f64),
+    pub oscillator_end: (usize, f64),
+}
+
+// Lowest (for swing lows) or highest (for swing highs) oscillator value within `strength` bars of the price
+// swing, the oscillator rarely turns on exactly the same bar as the price
+fn oscillator_swing(oscillator: &[f64], swing: &Swing, strength: usize) -> Option<(usize, f64)> {
+    let window = swing.index.saturating_sub(strength)..(swing.index + strength + 1).min(oscillator.len());
+    if window.clone().any(|i| !oscillator[i].is_finite()) {
+        return None;
+    }
+    let extreme = match swing.kind {
+        SwingKind::Low => window.min_by(|&a, &b| oscillator[a].total_cmp(&oscillator[b])),
+        SwingKind::High => window.max_by(|&a, &b| oscillator[a].total_cmp(&oscillator[b])),
+    }?;
+    Some((extreme, oscillator[extreme]))
+}
+
+// Compares every swing low (high) of the price with the previous swing low (high) and the oscillator at both
+pub fn find_divergences(bars: &[Bar], oscillator: &[f64], strength: usize) -> Vec<Divergence> {
+    let swings = swing_points(bars, strength);
+    let mut divergences = Vec::new();
+    for kind in [SwingKind::Low, SwingKind::High] {
+        let same_kind: Vec<&Swing> = swings.iter().filter(|swing| swing.kind == kind).collect();
+        for pair in same_kind.windows(2) {
+            let (first, second) = (pair[0], pair[1]);
+            if second.index - first.index > MAX_SWING_GAP {
+                continue;
+            }
+            let (Some(oscillator_start), Some(oscillator_end)) = (oscillator_swing(oscillator, first, strength), oscillator_swing(oscillator, second, strength)) else {
+                continue;
+            };
+            let price_rises = second.price > first.price;
+            let oscillator_rises = oscillator_end.1 > oscillator_start.1;
+            if price_rises == oscillator_rises || second.price == first.price || oscillator_end.1 == oscillator_start.1 {
+                continue;
+            }
+            let divergence_kind = match (kind, price_rises) {
+                (SwingKind::Low, false) => DivergenceKind::RegularBullish,
+                (SwingKind::Low, true) => DivergenceKind::HiddenBullish,
+                (SwingKind::High, true) => DivergenceKind::RegularBearish,
+                (SwingKind::High, false) => DivergenceKind::HiddenBearish,
+            };
+            divergences.push(Divergence {
+                kind: divergence_kind,
+                price_start: (first.index, first.price),
+                price_end: (second.index, second.price),
+                oscillator_start,
+                oscillator_end,
+            });
+        }
+    }
+    divergences.sort_by_key(|divergence| divergence.price_end.0);
+    divergences
+}
+
+fn divergence_style(kind: DivergenceKind) -> (RGBColor, bool) {
+    // Bullish green and bearish red, hidden divergences dashed
+    match kind {
+        DivergenceKind::RegularBullish => (GREEN_700, false),
+        DivergenceKind::HiddenBullish => (GREEN_700, true),
+        DivergenceKind::RegularBearish => (RED_700, false),
+        DivergenceKind::HiddenBearish => (RED_700, true),
+    }
+}
+
+// Price chart on top of the oscillator chart with the same date axis, every divergence is connected on both
+pub fn divergence_chart(
+    source: DivergenceSource,
+    closing_prices: &[f64],
+    oscillator: &[f64],
+    divergences: &[Divergence],
+    ticker_name: &str,
+    currency: &str,
+    times: &[DateTime<Utc>],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file_prefix = match source {
+        DivergenceSource::Rsi => "rsi_divergence_",
+        DivergenceSource::Macd => "macd_divergence_",
+    };
+    let chart_file_name = file_prefix.to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT * 2)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let (price_area, oscillator_area) = root.split_vertically(WINDOW_HEIGHT * 6 / 5);
+    let (first_time, last_time) = (times[0], times[times.len() - 1]);
+
+    // Price panel
+    let price_values: Vec<f64> = divergences.iter().flat_map(|divergence| [divergence.price_start.1, divergence.price_end.1]).collect();
+    let (min_price, max_price) = finite_range(closing_prices.iter().chain(price_values.iter()));
+    let mut price_chart = ChartBuilder::on(&price_area)
+        .caption(format!("{} Divergences for {}", source.name(), ticker_name), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(first_time..last_time, min_price..max_price)?;
+
+    price_chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Close Price", currency))
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    price_chart.draw_series(LineSeries::new(
+        times.iter().zip(closing_prices.iter()).map(|(&time, &close)| (time, close)),
+        BLACK,
+    ))?
+        .label("Close")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK));
+
+    // Oscillator panel
+    let (min_value, max_value) = match source {
+        DivergenceSource::Rsi => (0.0, 100.0),
+        DivergenceSource::Macd => {
+            let (min_value, max_value) = finite_range(oscillator.iter());
+            (min_value.min(0.0), max_value.max(0.0))
+        }
+    };
+    let mut oscillator_chart = ChartBuilder::on(&oscillator_area)
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(first_time..last_time, min_value..max_value)?;
+
+    oscillator_chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(source.name())
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    match source {
+        DivergenceSource::Rsi => {
+            oscillator_chart.draw_series(LineSeries::new(
+                times.iter().zip(oscillator.iter()).filter(|(_, value)| value.is_finite()).map(|(&time, &value)| (time, value)),
+                BLUE,
+            ))?;
+        }
+        DivergenceSource::Macd => {
+            // Histogram bars a third of the bar spacing wide, as on the MACD chart
+            let bar_half_width = times.windows(2).map(|pair| pair[1] - pair[0]).min().unwrap_or(chrono::Duration::days(1)) / 6;
+            oscillator_chart.draw_series(
+                times.iter().zip(oscillator.iter()).filter(|(_, value)| value.is_finite()).map(|(&time, &value)| {
+                    let bar_color = if value >= 0.0 { GREEN.mix(0.6).filled() } else { RED.mix(0.6).filled() };
+                    Rectangle::new([(time - bar_half_width, 0.0), (time + bar_half_width, value)], bar_color)
+                }),
+            )?;
+        }
+    }
+
+    // Divergence lines, one legend entry per kind
+    for kind in [DivergenceKind::RegularBullish, DivergenceKind::RegularBearish, DivergenceKind::HiddenBullish, DivergenceKind::HiddenBearish] {
+        let of_kind: Vec<&Divergence> = divergences.iter().filter(|divergence| divergence.kind == kind).collect();
+        if of_kind.is_empty() {
+            continue;
+        }
+        let (color, dashed) = divergence_style(kind);
+        let style = color.stroke_width(2);
+        for divergence in &of_kind {
+            let price_line = vec![(times[divergence.price_start.0], divergence.price_start.1), (times[divergence.price_end.0], divergence.price_end.1)];
+            let oscillator_line = vec![(times[divergence.oscillator_start.0], divergence.oscillator_start.1), (times[divergence.oscillator_end.0], divergence.oscillator_end.1)];
+            if dashed {
+                price_chart.draw_series(DashedLineSeries::new(price_line, 6, 4, style))?;
+                oscillator_chart.draw_series(DashedLineSeries::new(oscillator_line, 6, 4, style))?;
+            } else {
+                price_chart.draw_series(LineSeries::new(price_line, style))?;
+                oscillator_chart.draw_series(LineSeries::new(oscillator_line, style))?;
+            }
+        }
+        // Empty series that only carries the legend entry
+        price_chart.draw_series(std::iter::empty::<PathElement<(DateTime<Utc>, f64)>>())?
+            .label(format!("{} ({})", kind.name(), of_kind.len()))
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], style));
+    }
+
+    price_chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Daily bars two wide above the given lows
+    fn bars(lows: &[f64]) -> Vec<Bar> {
+        lows.iter().enumerate().map(|(i, &low)| Bar::new(i as u64 * 86_400, low + 1.0, low + 2.0, low, low + 1.0, 0.0)).collect()
+    }
+
+    #[test]
+    fn lower_price_low_with_higher_oscillator_low_is_regular_bullish() {
+        // Swing lows at 8 and 7 against oscillator lows of 30 and 35
+        let divergences = find_divergences(&bars(&[10.0, 8.0, 9.0, 7.0, 9.0]), &[50.0, 30.0, 40.0, 35.0, 45.0], 1);
+        assert_eq!(divergences.len(), 1);
+        let divergence = &divergences[0];
+        assert_eq!(divergence.kind, DivergenceKind::RegularBullish);
+        assert_eq!((divergence.price_start, divergence.price_end), ((1, 8.0), (3, 7.0)));
+        assert_eq!((divergence.oscillator_start, divergence.oscillator_end), ((1, 30.0), (3, 35.0)));
+    }
+
+    #[test]
+    fn higher_price_low_with_lower_oscillator_low_is_hidden_bullish() {
+        let divergences = find_divergences(&bars(&[10.0, 7.0, 9.0, 8.0, 9.0]), &[50.0, 35.0, 40.0, 30.0, 45.0], 1);
+        assert_eq!(divergences.iter().map(|divergence| divergence.kind).collect::<Vec<DivergenceKind>>(), vec![DivergenceKind::HiddenBullish]);
+    }
+
+    #[test]
+    fn agreeing_or_missing_oscillator_is_no_divergence() {
+        let bars = bars(&[10.0, 8.0, 9.0, 7.0, 9.0]);
+        assert!(find_divergences(&bars, &[50.0, 30.0, 40.0, 25.0, 45.0], 1).is_empty());
+        // The oscillator is still warming up around the first swing
+        assert!(find_divergences(&bars, &[f64::NAN, f64::NAN, 40.0, 35.0, 45.0], 1).is_empty());
+    }
+}
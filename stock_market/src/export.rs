@@ -0,0 +1,50 @@
+use chrono::{DateTime, Utc};
+use serde_json::{json, Map, Value};
+use std::fs::File;
+
+// Collects the computed indicator series for a ticker and writes them out as a single JSON file.
+// Values that are not valid yet (indicator warm-up) are stored as NaN and exported as null.
+// Single price levels (support/resistance, pivots, ...) are stored next to the series.
+pub struct IndicatorExport {
+    ticker_name: String,
+    times: Vec<DateTime<Utc>>,
+    series: Map<String, Value>,
+    levels: Map<String, Value>,
+}
+
+impl IndicatorExport {
+    pub fn new(ticker_name: &str, times: &[DateTime<Utc>]) -> IndicatorExport {
+        IndicatorExport {
+            ticker_name: ticker_name.to_owned(),
+            times: times.to_vec(),
+            series: Map::new(),
+            levels: Map::new(),
+        }
+    }
+
+    pub fn add_series(&mut self, name: &str, values: &[f64]) {
+        let values: Vec<Value> = values.iter()
+            .map(|&value| if value.is_finite() { json!(value) } else { Value::Null })
+            .collect();
+        self.series.insert(name.to_owned(), Value::Array(values));
+    }
+
+    pub fn add_level(&mut self, name: &str, value: f64) {
+        self.levels.insert(name.to_owned(), json!(value));
+    }
+
+    // Writes indicators_<ticker>.json and returns the name of the file
+    pub fn write(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let dates: Vec<String> = self.times.iter().map(|time| time.format("%Y-%m-%d").to_string()).collect();
+        let document = json!({
+            "ticker": self.ticker_name,
+            "dates": dates,
+            "series": self.series,
+            "levels": self.levels,
+        });
+
+        let export_file_name = "indicators_".to_owned() + &self.ticker_name + ".json";
+        serde_json::to_writer_pretty(File::create(&export_file_name)?, &document)?;
+        Ok(export_file_name)
+    }
+}
@@ -0,0 +1,47 @@
+// Ratios measured back from the end of the swing towards its start
+pub const RETRACEMENT_RATIOS: [f64; 5] = [0.236, 0.382, 0.5, 0.618, 0.786];
+// Ratios of the swing projected from its start past its end
+pub const EXTENSION_RATIOS: [f64; 2] = [1.272, 1.618];
+
+// Fibonacci retracement and extension levels of the price swing from `start_price` to `end_price`.
+// For a rise the retracements lie below the end of the swing and the extensions above it, for a fall
+// the other way round. Returns (name, price) pairs such as ("retracement 61.8%", 102.3).
+pub fn fibonacci_levels(start_price: f64, end_price: f64) -> Vec<(String, f64)> {
+    let swing = end_price - start_price;
+    let retracements = RETRACEMENT_RATIOS.iter()
+        .map(|ratio| (format!("retracement {:.1}%", ratio * 100.0), end_price - ratio * swing));
+    let extensions = EXTENSION_RATIOS.iter()
+        .map(|ratio| (format!("extension {:.1}%", ratio * 100.0), start_price + ratio * swing));
+    retracements.chain(extensions).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(levels: &[(String, f64)], name: &str) -> f64 {
+        levels.iter().find(|(level_name, _)| level_name == name).map(|(_, price)| *price).unwrap()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn rise_retraces_down_and_extends_up() {
+        let levels = fibonacci_levels(100.0, 200.0);
+        assert_eq!(levels.len(), RETRACEMENT_RATIOS.len() + EXTENSION_RATIOS.len());
+        assert_close(level(&levels, "retracement 23.6%"), 176.4);
+        assert_close(level(&levels, "retracement 50.0%"), 150.0);
+        assert_close(level(&levels, "retracement 61.8%"), 138.2);
+        assert_close(level(&levels, "extension 161.8%"), 261.8);
+    }
+
+    #[test]
+    fn fall_retraces_up_and_extends_down() {
+        let levels = fibonacci_levels(200.0, 100.0);
+        assert_close(level(&levels, "retracement 38.2%"), 138.2);
+        assert_close(level(&levels, "retracement 78.6%"), 178.6);
+        assert_close(level(&levels, "extension 127.2%"), 72.8);
+    }
+}
@@ -0,0 +1,149 @@
+use chrono::{DateTime, Utc};
+use plotters::prelude::*;
+use plotters::style::full_palette::{GREEN_700, RED_700};
+use ta::indicators::{Maximum, Minimum};
+use ta::Next;
+
+use crate::bar::Bar;
+use crate::{axis_title, finite_range, mask_warmup, sma_warmup, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+// Ichimoku lines as they are plotted. Every series has `displacement` more values than there are bars:
+// Senkou Span A/B are shifted forward so the cloud reaches past the last bar, the Chikou Span (the close)
+// is shifted back. Tenkan-sen and Kijun-sen are not shifted and end with NaN like the Chikou Span.
+pub struct Ichimoku {
+    pub tenkan_sen: Vec<f64>,
+    pub kijun_sen: Vec<f64>,
+    pub senkou_span_a: Vec<f64>,
+    pub senkou_span_b: Vec<f64>,
+    pub chikou_span: Vec<f64>,
+}
+
+// Midpoint of the highest high and the lowest low of the last `period` bars
+fn midpoint_values(bars: &[Bar], period: usize) -> Vec<f64> {
+    let mut maximum = Maximum::new(period).unwrap();
+    let mut minimum = Minimum::new(period).unwrap();
+    let mut midpoints: Vec<f64> = bars.iter().map(|bar| (maximum.next(bar) + minimum.next(bar)) / 2.0).collect();
+    mask_warmup(&mut midpoints, sma_warmup(period));
+    midpoints
+}
+
+// Bars it takes before the Senkou Span B (the slowest line) is plotted
+pub fn ichimoku_warmup(senkou_b_period: usize, displacement: usize) -> usize {
+    sma_warmup(senkou_b_period) + displacement
+}
+
+pub fn ichimoku_values(bars: &[Bar], tenkan_period: usize, kijun_period: usize, senkou_b_period: usize, displacement: usize) -> Ichimoku {
+    let padding = vec![f64::NAN; displacement];
+    let tenkan_sen = midpoint_values(bars, tenkan_period);
+    let kijun_sen = midpoint_values(bars, kijun_period);
+    let senkou_span_a: Vec<f64> = tenkan_sen.iter().zip(kijun_sen.iter()).map(|(&tenkan, &kijun)| (tenkan + kijun) / 2.0).collect();
+    let senkou_span_b = midpoint_values(bars, senkou_b_period);
+    let closes: Vec<f64> = bars.iter().map(|bar| bar.close).collect();
+
+    Ichimoku {
+        tenkan_sen: [tenkan_sen, padding.clone()].concat(),
+        kijun_sen: [kijun_sen, padding.clone()].concat(),
+        senkou_span_a: [padding.clone(), senkou_span_a].concat(),
+        senkou_span_b: [padding.clone(), senkou_span_b].concat(),
+        chikou_span: [closes[displacement.min(closes.len())..].to_vec(), vec![f64::NAN; displacement.min(closes.len())], padding].concat(),
+    }
+}
+
+impl Ichimoku {
+    // The part of every series from `start` onwards, e.g. to drop the warm-up history
+    pub fn slice(&self, start: usize) -> Ichimoku {
+        Ichimoku {
+            tenkan_sen: self.tenkan_sen[start..].to_vec(),
+            kijun_sen: self.kijun_sen[start..].to_vec(),
+            senkou_span_a: self.senkou_span_a[start..].to_vec(),
+            senkou_span_b: self.senkou_span_b[start..].to_vec(),
+            chikou_span: self.chikou_span[start..].to_vec(),
+        }
+    }
+}
+
+// Ichimoku chart on an x-axis that runs past the last bar to `axis_times`, which continues `times` with the
+// dates the cloud is projected onto
+pub fn ichimoku(
+    ichimoku: &Ichimoku,
+    closing_prices: &[f64],
+    ticker_name: &str,
+    currency: &str,
+    times: &[DateTime<Utc>],
+    axis_times: &[DateTime<Utc>],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "ichimoku_".to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let lines = [
+        (&ichimoku.tenkan_sen, "Tenkan-sen", BLUE),
+        (&ichimoku.kijun_sen, "Kijun-sen", RED_700),
+        (&ichimoku.chikou_span, "Chikou Span", MAGENTA),
+        (&ichimoku.senkou_span_a, "Senkou Span A", GREEN_700),
+        (&ichimoku.senkou_span_b, "Senkou Span B", RED),
+    ];
+    let (min_value, max_value) = finite_range(closing_prices.iter().chain(lines.iter().flat_map(|(values, _, _)| values.iter())));
+    let (first_time, last_bar_time, last_time) = (axis_times[0], times[times.len() - 1], axis_times[axis_times.len() - 1]);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption("Ichimoku Kinko Hyo Chart for ".to_owned() + ticker_name, ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(first_time..last_time, min_value..max_value)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Price", currency))
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    // Kumo: the area between the two Senkou Spans, green while Span A is above Span B and red otherwise
+    let (span_a, span_b) = (&ichimoku.senkou_span_a, &ichimoku.senkou_span_b);
+    for (bullish, label, color) in [(true, "Kumo (bullish)", GREEN), (false, "Kumo (bearish)", RED)] {
+        let segments: Vec<usize> = (0..axis_times.len() - 1)
+            .filter(|&i| [span_a[i], span_b[i], span_a[i + 1], span_b[i + 1]].iter().all(|value| value.is_finite()))
+            .filter(|&i| (span_a[i] >= span_b[i]) == bullish)
+            .collect();
+        if segments.is_empty() {
+            continue;
+        }
+        chart.draw_series(segments.iter().map(|&i| Polygon::new(
+            vec![(axis_times[i], span_a[i]), (axis_times[i + 1], span_a[i + 1]), (axis_times[i + 1], span_b[i + 1]), (axis_times[i], span_b[i])],
+            color.mix(0.2).filled(),
+        )))?
+            .label(label)
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], color.mix(0.3).filled()));
+    }
+
+    // Everything right of this line is projected
+    chart.draw_series(DashedLineSeries::new(vec![(last_bar_time, min_value), (last_bar_time, max_value)], 5, 5, BLACK.mix(0.5).into()))?;
+
+    for (values, label, color) in lines {
+        chart.draw_series(LineSeries::new(
+            axis_times.iter().zip(values.iter()).filter(|(_, value)| value.is_finite()).map(|(&time, &value)| (time, value)),
+            color,
+        ))?
+            .label(label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+
+    chart.draw_series(LineSeries::new(
+        times.iter().zip(closing_prices.iter()).map(|(&time, &close)| (time, close)),
+        BLACK.stroke_width(2),
+    ))?
+        .label("Close")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK.stroke_width(2)));
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
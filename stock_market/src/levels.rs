@@ -0,0 +1,177 @@
+use clap::ValueEnum;
+
+use crate::bar::Bar;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SwingKind {
+    High,
+    Low,
+}
+
+// A local extreme of the price: the bar's high (or low) is beyond the highs (lows) of `strength` bars
+// on either side
+#[derive(Clone, Copy, Debug)]
+pub struct Swing {
+    pub index: usize,
+    pub price: f64,
+    pub kind: SwingKind,
+}
+
+pub fn swing_points(bars: &[Bar], strength: usize) -> Vec<Swing> {
+    let mut swings = Vec::new();
+    if bars.len() < 2 * strength + 1 {
+        return swings;
+    }
+    for i in strength..bars.len() - strength {
+        let (before, after) = (&bars[i - strength..i], &bars[i + 1..=i + strength]);
+        // Strictly beyond the bars before and not exceeded by the bars after, so a flat top counts once
+        if before.iter().all(|bar| bar.high < bars[i].high) && after.iter().all(|bar| bar.high <= bars[i].high) {
+            swings.push(Swing { index: i, price: bars[i].high, kind: SwingKind::High });
+        }
+        if before.iter().all(|bar| bar.low > bars[i].low) && after.iter().all(|bar| bar.low >= bars[i].low) {
+            swings.push(Swing { index: i, price: bars[i].low, kind: SwingKind::Low });
+        }
+    }
+    swings
+}
+
+// A horizontal level where the price turned at least twice
+pub struct PriceLevel {
+    pub price: f64,
+    pub touches: usize,
+    // Below the last close the level is support, above it resistance
+    pub support: bool,
+}
+
+// Clusters the swing prices into levels: sorted by price, a swing joins the current cluster while it lies
+// within `tolerance_percent` of the cluster's average. Clusters of a single swing are dropped.
+pub fn support_resistance_levels(swings: &[Swing], tolerance_percent: f64, last_close: f64) -> Vec<PriceLevel> {
+    let mut prices: Vec<f64> = swings.iter().map(|swing| swing.price).collect();
+    prices.sort_by(|a, b| a.total_cmp(b));
+
+    let mut clusters: Vec<Vec<f64>> = Vec::new();
+    for price in prices {
+        let joins_cluster = clusters.last().is_some_and(|cluster| {
+            let average = cluster.iter().sum::<f64>() / cluster.len() as f64;
+            (price - average).abs() <= average * tolerance_percent / 100.0
+        });
+        if joins_cluster {
+            clusters.last_mut().unwrap().push(price);
+        } else {
+            clusters.push(vec![price]);
+        }
+    }
+
+    clusters.into_iter()
+        .filter(|cluster| cluster.len() >= 2)
+        .map(|cluster| {
+            let price = cluster.iter().sum::<f64>() / cluster.len() as f64;
+            PriceLevel { price, touches: cluster.len(), support: price < last_close }
+        })
+        .collect()
+}
+
+// Pivot point formulas that can be selected with --pivots
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum PivotMethod {
+    /// Floor trader pivots: P = (H + L + C) / 3 with three support and resistance levels
+    Classic,
+    /// Supports and resistances at 38.2%, 61.8% and 100% of the range away from P
+    Fibonacci,
+    /// Four supports and resistances around the close at fractions of 1.1 times the range (no P level)
+    Camarilla,
+}
+
+impl PivotMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PivotMethod::Classic => "Classic",
+            PivotMethod::Fibonacci => "Fibonacci",
+            PivotMethod::Camarilla => "Camarilla",
+        }
+    }
+}
+
+// Pivot levels for the session after `bar`, from the resistances down to the supports
+pub fn pivot_points(bar: &Bar, method: PivotMethod) -> Vec<(&'static str, f64)> {
+    let (high, low, close) = (bar.high, bar.low, bar.close);
+    let pivot = (high + low + close) / 3.0;
+    let range = high - low;
+    match method {
+        PivotMethod::Classic => vec![
+            ("R3", high + 2.0 * (pivot - low)),
+            ("R2", pivot + range),
+            ("R1", 2.0 * pivot - low),
+            ("P", pivot),
+            ("S1", 2.0 * pivot - high),
+            ("S2", pivot - range),
+            ("S3", low - 2.0 * (high - pivot)),
+        ],
+        PivotMethod::Fibonacci => vec![
+            ("R3", pivot + range),
+            ("R2", pivot + 0.618 * range),
+            ("R1", pivot + 0.382 * range),
+            ("P", pivot),
+            ("S1", pivot - 0.382 * range),
+            ("S2", pivot - 0.618 * range),
+            ("S3", pivot - range),
+        ],
+        PivotMethod::Camarilla => vec![
+            ("R4", close + range * 1.1 / 2.0),
+            ("R3", close + range * 1.1 / 4.0),
+            ("R2", close + range * 1.1 / 6.0),
+            ("R1", close + range * 1.1 / 12.0),
+            ("S1", close - range * 1.1 / 12.0),
+            ("S2", close - range * 1.1 / 6.0),
+            ("S3", close - range * 1.1 / 4.0),
+            ("S4", close - range * 1.1 / 2.0),
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Daily bars from (high, low) pairs
+    fn bars(ranges: &[(f64, f64)]) -> Vec<Bar> {
+        ranges.iter().enumerate()
+            .map(|(i, &(high, low))| Bar::new(i as u64 * 86_400, (high + low) / 2.0, high, low, (high + low) / 2.0, 0.0))
+            .collect()
+    }
+
+    #[test]
+    fn swings_are_beyond_their_neighbours() {
+        let swings = swing_points(&bars(&[(10.0, 9.0), (12.0, 10.0), (11.0, 8.0), (11.0, 9.0), (14.0, 12.0), (13.0, 11.0)]), 1);
+        let found: Vec<(usize, f64, SwingKind)> = swings.iter().map(|swing| (swing.index, swing.price, swing.kind)).collect();
+        assert_eq!(found, vec![(1, 12.0, SwingKind::High), (2, 8.0, SwingKind::Low), (4, 14.0, SwingKind::High)]);
+    }
+
+    #[test]
+    fn flat_top_counts_once() {
+        let swings = swing_points(&bars(&[(10.0, 9.0), (12.0, 11.0), (12.0, 11.0), (10.0, 9.0)]), 1);
+        let highs: Vec<usize> = swings.iter().filter(|swing| swing.kind == SwingKind::High).map(|swing| swing.index).collect();
+        assert_eq!(highs, vec![1]);
+        // Too few bars for a single swing of strength 2
+        assert!(swing_points(&bars(&[(10.0, 9.0), (12.0, 11.0), (10.0, 9.0)]), 2).is_empty());
+    }
+
+    #[test]
+    fn nearby_swings_cluster_into_levels() {
+        let swing = |price: f64| Swing { index: 0, price, kind: SwingKind::High };
+        let swings: Vec<Swing> = [200.0, 100.0, 150.0, 202.0, 101.0, 199.0].into_iter().map(swing).collect();
+        let levels = support_resistance_levels(&swings, 2.0, 160.0);
+        // 150 turned the price only once and is dropped
+        assert_eq!(levels.len(), 2);
+        assert_eq!((levels[0].price, levels[0].touches, levels[0].support), (100.5, 2, true));
+        assert!((levels[1].price - 601.0 / 3.0).abs() < 1e-9);
+        assert_eq!((levels[1].touches, levels[1].support), (3, false));
+    }
+
+    #[test]
+    fn classic_pivots() {
+        let bar = Bar::new(0, 95.0, 110.0, 90.0, 100.0, 0.0);
+        let pivots = pivot_points(&bar, PivotMethod::Classic);
+        assert_eq!(pivots, vec![("R3", 130.0), ("R2", 120.0), ("R1", 110.0), ("P", 100.0), ("S1", 90.0), ("S2", 80.0), ("S3", 70.0)]);
+    }
+}
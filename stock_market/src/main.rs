@@ -1,5 +1,5 @@
-use chrono::{DateTime, Utc};
-use clap::Parser;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+use clap::{Parser, Subcommand, ValueEnum};
 use lazy_static::lazy_static;
 use log::LevelFilter;
 use log::{debug, error};
@@ -7,18 +7,102 @@ use log4rs::append::file::FileAppender;
 use log4rs::config::{Appender, Config, Root};
 use log4rs::encode::pattern::PatternEncoder;
 use plotters::prelude::*;
-use std::sync::Mutex;
+use plotters::style::full_palette::{BROWN, CYAN_800, DEEPORANGE, GREEN_700, ORANGE, PURPLE, RED_700, TEAL};
+use std::path::PathBuf;
 use ta::indicators::{BollingerBands, ExponentialMovingAverage, MovingAverageConvergenceDivergence as Macd, RelativeStrengthIndex, SimpleMovingAverage};
 use ta::Next;
+use yahoo_finance_api::time::OffsetDateTime;
 use yahoo_finance_api::{YResponse, YahooConnector, YahooError};
 
+mod backtest;
+mod bar;
+mod channels;
+mod chart_types;
+mod divergence;
+mod export;
+mod fibonacci;
+mod ichimoku;
+mod levels;
+mod momentum;
+mod monte_carlo;
+mod optimise;
+mod overlay;
+mod patterns;
+mod portfolio;
+mod rebalance;
+mod risk;
+mod signals;
+mod stats;
+mod strategies;
+mod sweep;
+mod trend;
+mod walk_forward;
+
+use backtest::BacktestArgs;
+use bar::Bar;
+use sweep::SweepArgs;
+use walk_forward::WalkForwardArgs;
+use channels::ChannelChart;
+use chart_types::ChartType;
+use divergence::DivergenceSource;
+use monte_carlo::MonteCarloArgs;
+use risk::RiskArgs;
+use rebalance::RebalanceArgs;
+use optimise::OptimiseArgs;
+use export::IndicatorExport;
+use levels::{PivotMethod, SwingKind};
+use patterns::Bias;
+use overlay::{draw_price_overlays, Overlay, PriceOverlay};
+use signals::{level_crossings, series_crossings, Cross, SignalReport};
+
 // Implements lazy singleton pattern for Yahoo Connector
 lazy_static! {
-    static ref CONNECTOR: Mutex<YahooConnector> = Mutex::new(YahooConnector::new().expect("Unable to Create a Yahoo Connector"));
+    static ref CONNECTOR: YahooConnector = YahooConnector::new().expect("Unable to Create a Yahoo Connector");
 }
 
 const WINDOW_WIDTH: u32 = 2000;
 const WINDOW_HEIGHT: u32 = 480;
+const GOLD: RGBColor = RGBColor(212, 175, 55);
+
+// Indicator charts that can be selected with --indicators
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Indicator {
+    /// Bollinger Bands
+    Bb,
+    /// Relative Strength Index
+    Rsi,
+    /// Moving Average Convergence Divergence
+    Macd,
+    /// Fast/Slow Exponential Moving Averages
+    Ema,
+    /// Fast/Slow Simple Moving Averages
+    Sma,
+    /// Stochastic oscillator %K/%D
+    Stochastic,
+    /// Williams %R
+    WilliamsR,
+    /// Commodity Channel Index
+    Cci,
+    /// Rate of Change
+    Roc,
+    /// Average Directional Index with +DI/-DI
+    Adx,
+    /// Parabolic SAR, drawn as dots on the price chart
+    Psar,
+    /// Aroon Up/Down and Aroon Oscillator
+    Aroon,
+    /// Keltner Channel
+    Keltner,
+    /// Donchian Channel
+    Donchian,
+    /// ATR stop bands (Chandelier Exit)
+    AtrStops,
+    /// Bollinger Bands inside the Keltner Channel
+    Squeeze,
+    /// Ichimoku Kinko Hyo with the cloud projected past the last bar
+    Ichimoku,
+}
+
 // Creating a parser for the CLI program that takes in the name of the stock
 #[derive(Parser, Debug)]
 #[command(
@@ -27,8 +111,8 @@ const WINDOW_HEIGHT: u32 = 480;
     long_about = "CLI Program to fetch stock using Yahoo finance. The CLI is build using the clap crate in rust"
 )]
 struct Stock {
-    /// Ticker name of a stock.
-    #[arg(short, long)]
+    /// Ticker name of a stock, several comma separated tickers with --scan
+    #[arg(short, long, required_unless_present = "portfolio", default_value = "", hide_default_value = true)]
     name: String,
     /// Interval of the stock, by default set to 1 day to retrieve daily stock prices
     #[arg(short, long, default_value = "1d")]
@@ -36,78 +120,222 @@ struct Stock {
     /// Range of date you are interested in getting the data for
     #[arg(short, long, default_value = "6mo")]
     range: String,
+    /// Write the computed indicator values to indicators_<ticker>.json
+    #[arg(short, long)]
+    export: bool,
+    /// Fetch extra bars before the requested range so indicators are valid from the first plotted bar
+    #[arg(long)]
+    warmup_history: bool,
+    /// RSI level above which the stock is considered overbought
+    #[arg(long, default_value_t = 70.0)]
+    rsi_overbought: f64,
+    /// RSI level below which the stock is considered oversold
+    #[arg(long, default_value_t = 30.0)]
+    rsi_oversold: f64,
+    /// Indicators to draw over the price chart, comma separated
+    #[arg(short, long, value_delimiter = ',')]
+    overlay: Vec<Overlay>,
+    /// Indicator charts to create besides the price chart, comma separated
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [Indicator::Bb, Indicator::Rsi, Indicator::Macd, Indicator::Ema, Indicator::Sma])]
+    indicators: Vec<Indicator>,
+    /// Look-back period of the Stochastic oscillator %K line
+    #[arg(long, default_value_t = 14)]
+    stochastic_period: usize,
+    /// Smoothing period of the Stochastic oscillator %D line
+    #[arg(long, default_value_t = 3)]
+    stochastic_smoothing: usize,
+    /// Look-back period of Williams %R
+    #[arg(long, default_value_t = 14)]
+    williams_period: usize,
+    /// Period of the Commodity Channel Index
+    #[arg(long, default_value_t = 20)]
+    cci_period: usize,
+    /// Number of bars the Rate of Change looks back
+    #[arg(long, default_value_t = 12)]
+    roc_period: usize,
+    /// Period of the Average Directional Index and the +DI/-DI lines
+    #[arg(long, default_value_t = 14)]
+    adx_period: usize,
+    /// Acceleration step of the Parabolic SAR
+    #[arg(long, default_value_t = 0.02)]
+    sar_step: f64,
+    /// Maximum acceleration of the Parabolic SAR
+    #[arg(long, default_value_t = 0.2)]
+    sar_max_step: f64,
+    /// Look-back period of Aroon
+    #[arg(long, default_value_t = 25)]
+    aroon_period: usize,
+    /// Period of the Keltner Channel EMA and ATR
+    #[arg(long, default_value_t = 20)]
+    keltner_period: usize,
+    /// Width of the Keltner Channel in ATRs, 1.5 is the usual setting for squeeze detection
+    #[arg(long, default_value_t = 1.5)]
+    keltner_multiplier: f64,
+    /// Look-back period of the Donchian Channel
+    #[arg(long, default_value_t = 20)]
+    donchian_period: usize,
+    /// Look-back period of the ATR stop bands
+    #[arg(long, default_value_t = 22)]
+    atr_stop_period: usize,
+    /// Distance of the ATR stop bands from the extreme price in ATRs
+    #[arg(long, default_value_t = 3.0)]
+    atr_stop_multiplier: f64,
+    /// Period of the Ichimoku Tenkan-sen (conversion line)
+    #[arg(long, default_value_t = 9)]
+    tenkan_period: usize,
+    /// Period of the Ichimoku Kijun-sen (base line), also the number of bars the cloud is shifted forward
+    #[arg(long, default_value_t = 26)]
+    kijun_period: usize,
+    /// Period of the Ichimoku Senkou Span B
+    #[arg(long, default_value_t = 52)]
+    senkou_b_period: usize,
+    /// Detect swing highs/lows and support/resistance levels and draw them on the price chart
+    #[arg(long)]
+    levels: bool,
+    /// Number of bars on either side a swing high/low has to exceed
+    #[arg(long, default_value_t = 5)]
+    swing_strength: usize,
+    /// Swings within this many percent of each other form one support/resistance level
+    #[arg(long, default_value_t = 1.5)]
+    level_tolerance: f64,
+    /// Pivot points to draw on the price chart, comma separated
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pivots: Vec<PivotMethod>,
+    /// Draw Fibonacci retracement and extension levels between the lowest and highest close of the range
+    #[arg(long)]
+    fibonacci: bool,
+    /// Start the Fibonacci swing at the close of this date (YYYY-MM-DD) instead, requires --fib-to
+    #[arg(long, requires = "fib_to")]
+    fib_from: Option<NaiveDate>,
+    /// End the Fibonacci swing at the close of this date (YYYY-MM-DD), requires --fib-from
+    #[arg(long, requires = "fib_from")]
+    fib_to: Option<NaiveDate>,
+    /// Detect candlestick patterns, mark them on the price chart and list them in the signal report
+    #[arg(long)]
+    patterns: bool,
+    /// Only list the candlestick patterns of the last --scan-bars bars of every ticker given with --name
+    #[arg(long)]
+    scan: bool,
+    /// Number of most recent bars the pattern scan looks at, 1 for the last session only
+    #[arg(long, default_value_t = 1)]
+    scan_bars: usize,
+    /// Oscillators to check for regular and hidden divergences with the price swings, comma separated
+    #[arg(long, value_enum, value_delimiter = ',')]
+    divergences: Vec<DivergenceSource>,
+    /// Alternative price charts to draw, comma separated
+    #[arg(long, value_enum, value_delimiter = ',')]
+    chart_types: Vec<ChartType>,
+    /// Renko box size in price units, the ATR of the last bar when not given
+    #[arg(long)]
+    renko_box: Option<f64>,
+    /// Point & Figure box size in price units, the ATR of the last bar when not given
+    #[arg(long)]
+    pnf_box: Option<f64>,
+    /// Number of boxes the price has to move against a Point & Figure column to start a new one
+    #[arg(long, default_value_t = 3)]
+    pnf_reversal: usize,
+    /// Period of the ATR used as the Renko and Point & Figure box size
+    #[arg(long, default_value_t = 14)]
+    box_atr_period: usize,
+    /// Report the holdings of this portfolio file (.toml or .csv) instead of a single ticker
+    #[arg(long)]
+    portfolio: Option<PathBuf>,
+    /// Directory to keep the prices of the portfolio, risk, rebalance and optimise tickers in, cached prices are not fetched again
+    #[arg(long)]
+    cache: Option<PathBuf>,
+    /// Hours the cached prices are used before they are fetched again, older prices are only used when fetching fails
+    #[arg(long, default_value_t = 24.0)]
+    cache_max_age: f64,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Replay the stock prices through a trading strategy and report its trades and performance
+    Backtest(BacktestArgs),
+    /// Backtest the strategy for every combination of the swept parameters and rank the results
+    Sweep(SweepArgs),
+    /// Optimise the swept parameters on rolling in-sample windows and test them on the following out-of-sample windows
+    WalkForward(WalkForwardArgs),
+    /// Resample the backtest's trades or daily returns and report the spread of the outcomes
+    MonteCarlo(MonteCarloArgs),
+    /// Value at Risk, Expected Shortfall, betas and return correlations of the --portfolio holdings
+    Risk(RiskArgs),
+    /// Replay the comma separated --name tickers held at target weights and rebalanced by a rule
+    Rebalance(RebalanceArgs),
+    /// Minimum-variance and maximum-Sharpe weights of the comma separated --name tickers and their efficient frontier
+    Optimise(OptimiseArgs),
 }
 
 // generate a stock price chart and save it as a PNG file
 // fn stock_picture_creator(heading: &str, prices: &[f64]) -> Result<(), Box<dyn std::error::Error>> {}
-// Sample stock price data: (timestamp, close, low, high, status)
-// whereever true candle stick needs to be there
+// Price chart: close line, volatile days with their high-low range and the selected overlays
+fn stock_picture_creator(file_prefix: &str, stock_data: &[Bar], overlays: &[PriceOverlay], ticker_name: &str, currency: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let times: Vec<DateTime<Utc>> = stock_data.iter().map(Bar::time).collect();
 
-// Convert UNIX timestamp to DateTime for x-axis
-fn stock_picture_creator(stock_data: &Vec<(u64, f64, f64, f64, bool)>, ticker_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let times: Vec<DateTime<Utc>> = stock_data.iter()
-        .map(|&(unix_timestamp, _, _, _, _)| DateTime::from_timestamp(unix_timestamp as i64, 0).unwrap())
-        .collect();
-
-    let high_prices: Vec<f64> = stock_data.iter().map(|&(_, _, _, high, _)| high).collect();
-    let low_prices: Vec<f64> = stock_data.iter().map(|&(_, _, low, _, _)| low).collect();
-    let close_prices: Vec<f64> = stock_data.iter().map(|&(_, close, _, _, _)| close).collect();
-    let variations: Vec<f64> = stock_data.iter()
-        .map(|&(_, _, low, high, _)| ((high - low) / low) * 100.0)
-        .collect();
+    let high_prices: Vec<f64> = stock_data.iter().map(|bar| bar.high).collect();
+    let low_prices: Vec<f64> = stock_data.iter().map(|bar| bar.low).collect();
+    let close_prices: Vec<f64> = stock_data.iter().map(|bar| bar.close).collect();
 
     // Create a drawing area
-    let stock_chart_file_name = "stock_chart_".to_owned() + ticker_name + ".png";
+    let stock_chart_file_name = file_prefix.to_owned() + ticker_name + ".png";
     let root = BitMapBackend::new(&stock_chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
     root.fill(&WHITE)?;
-    let y_max = close_prices.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
-    let y_min = close_prices.iter().fold(f64::INFINITY, |a, &b| a.min(b));
+    let overlay_values: Vec<f64> = overlays.iter().flat_map(|overlay| overlay.values()).collect();
+    let (y_min, y_max) = finite_range(close_prices.iter().chain(overlay_values.iter()));
     let mut chart = ChartBuilder::on(&root)
         .caption("Stock Prices Chart ".to_owned() + ticker_name, ("sans-serif", 30).into_font())
         .margin(10)
         .x_label_area_size(55)
-        .y_label_area_size(40)
+        .y_label_area_size(60)
         .build_cartesian_2d(times[0]..times[times.len() - 1], y_min..y_max)?;
 
     chart.configure_mesh()
         .x_labels(5)
         .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Close Price", currency))
         .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
         .draw()?;
 
+    draw_price_overlays(&mut chart, overlays, &times)?;
+
     chart.draw_series(LineSeries::new(
         times.iter().zip(close_prices.iter()).map(|(&time, &close)| (time, close)),
         &RED,
-    ))?;
+    ))?
+        .label("Close")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
 
     // circle points where variation is > 2% and plot low, high, and connect them with a line
+    let mut volatile_labelled = false;
     for (i, &time) in times.iter().enumerate() {
         let low = low_prices[i];
         let high = high_prices[i];
         let close = close_prices[i];
-        let variation = variations[i];
 
-        if variation > 2.0 {
+        if stock_data[i].volatile {
             // Draw a vertical line from low to high
             chart.draw_series(std::iter::once(PathElement::new(
                 vec![(time, low), (time, high)],
-                &BLUE, // Color for the high-low line
+                BLUE, // Color for the high-low line
             )))?;
 
             // Draw a flat line for the low price
             chart.draw_series(std::iter::once(PathElement::new(
                 vec![(time - chrono::Duration::seconds(3600), low), (time + chrono::Duration::seconds(3600), low)],
-                &BLUE, // Flat line for low price
+                BLUE, // Flat line for low price
             )))?;
 
             // Draw a flat line for the high price
             chart.draw_series(std::iter::once(PathElement::new(
                 vec![(time - chrono::Duration::seconds(3600), high), (time + chrono::Duration::seconds(3600), high)],
-                &BLUE, // Flat line for high price
+                BLUE, // Flat line for high price
             )))?;
 
             // Draw a hollow circle for the close price
-            chart.draw_series(std::iter::once(Circle::new(
+            let volatile_marker = chart.draw_series(std::iter::once(Circle::new(
                 (time, close),
                 5, // size of the marker
                 ShapeStyle {
@@ -116,8 +344,33 @@ fn stock_picture_creator(stock_data: &Vec<(u64, f64, f64, f64, bool)>, ticker_na
                     stroke_width: 1,
                 }, // Hollow circle for the close price
             )))?;
+            // Only the first volatile day goes into the legend
+            if !volatile_labelled {
+                volatile_marker
+                    .label("Volatile day (high-low > 2%)")
+                    .legend(|(x, y)| Circle::new((x + 10, y), 5, BLUE));
+                volatile_labelled = true;
+            }
         }
     }
+
+    // Annotate the highest and lowest close of the period
+    let (max_date, max_price, min_date, min_price) = fetch_min_max_closing_pricing_with_dates(stock_data);
+    for (date, price, name) in [(max_date, max_price, "Max"), (min_date, min_price, "Min")] {
+        let time = DateTime::from_timestamp(date as i64, 0).unwrap();
+        chart.draw_series(std::iter::once(TriangleMarker::new((time, price), 8, BLACK.filled())))?;
+        chart.draw_series(std::iter::once(Text::new(
+            format!("{} {:.2} on {}", name, price, time.format("%Y-%m-%d")),
+            (time, price),
+            ("sans-serif", 15).into_font(),
+        )))?;
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
     root.present()?;
 
     Ok(())
@@ -144,85 +397,709 @@ async fn main() {
     let stock_args = Stock::parse();
     debug!("Ticker Name set :{}",stock_args.name);
     debug!("Interval Set:{}",stock_args.interval);
+    if !(0.0..=100.0).contains(&stock_args.rsi_oversold) || !(0.0..=100.0).contains(&stock_args.rsi_overbought)
+        || stock_args.rsi_oversold >= stock_args.rsi_overbought {
+        println!("RSI levels must lie between 0 and 100 with the oversold level below the overbought level");
+        return;
+    }
+    if [stock_args.stochastic_period, stock_args.stochastic_smoothing, stock_args.williams_period, stock_args.cci_period, stock_args.roc_period, stock_args.adx_period, stock_args.aroon_period,
+        stock_args.keltner_period, stock_args.donchian_period, stock_args.atr_stop_period,
+        stock_args.tenkan_period, stock_args.kijun_period, stock_args.senkou_b_period, stock_args.swing_strength, stock_args.box_atr_period].contains(&0) {
+        println!("Indicator periods must be at least 1");
+        return;
+    }
+    if stock_args.level_tolerance < 0.0 {
+        println!("The support/resistance tolerance must not be negative");
+        return;
+    }
+    if stock_args.keltner_multiplier <= 0.0 || stock_args.atr_stop_multiplier <= 0.0 {
+        println!("Channel multipliers must be positive");
+        return;
+    }
+    if stock_args.sar_step <= 0.0 || stock_args.sar_max_step < stock_args.sar_step {
+        println!("The Parabolic SAR step must be positive and not larger than the maximum step");
+        return;
+    }
+    if stock_args.renko_box.is_some_and(|size| size <= 0.0) || stock_args.pnf_box.is_some_and(|size| size <= 0.0) || stock_args.pnf_reversal == 0 {
+        println!("Box sizes must be positive and the Point & Figure reversal at least 1 box");
+        return;
+    }
+    if stock_args.cache_max_age.is_nan() || stock_args.cache_max_age < 0.0 {
+        println!("The cache max age must not be negative");
+        return;
+    }
+    if stock_args.scan {
+        let tickers: Vec<&str> = stock_args.name.split(',').map(str::trim).filter(|ticker| !ticker.is_empty()).collect();
+        scan_patterns(&tickers, &stock_args.interval, &stock_args.range, stock_args.scan_bars).await;
+        return;
+    }
+    if let Some(portfolio_file) = &stock_args.portfolio {
+        match &stock_args.command {
+            Some(Command::Risk(risk_args)) => risk::run_command(&stock_args, portfolio_file, risk_args).await,
+            Some(_) => println!("Only the risk command works on a --portfolio, the other commands need a single ticker in --name"),
+            None => portfolio::run_command(&stock_args, portfolio_file).await,
+        }
+        return;
+    }
+    if let Some(command) = &stock_args.command {
+        match command {
+            Command::Backtest(backtest_args) => backtest::run_command(&stock_args, backtest_args).await,
+            Command::Sweep(sweep_args) => sweep::run_command(&stock_args, sweep_args).await,
+            Command::WalkForward(walk_forward_args) => walk_forward::run_command(&stock_args, walk_forward_args).await,
+            Command::MonteCarlo(monte_carlo_args) => monte_carlo::run_command(&stock_args, monte_carlo_args).await,
+            Command::Risk(_) => println!("The risk command needs the holdings of a --portfolio file"),
+            Command::Rebalance(rebalance_args) => rebalance::run_command(&stock_args, rebalance_args).await,
+            Command::Optimise(optimise_args) => optimise::run_command(&stock_args, optimise_args).await,
+        }
+        return;
+    }
+    let selected = |indicator: Indicator| stock_args.indicators.contains(&indicator);
     debug!("About to fetch Stock from Yahoo");
-    let mut closing_prices_vec: Vec<f64> = Vec::new();
     let response = fetch_stock(&stock_args.name, &stock_args.interval, &stock_args.range).await;
     match response {
         Ok(data) => {
             let quotes = data.quotes();
+            // Currency of the prices for the axis titles, Yahoo does not report it for every ticker
+            let currency = data.metadata().ok().and_then(|metadata| metadata.currency).unwrap_or_default();
             match quotes {
                 Ok(stock_quotes) => {
-                    let mut stock_prices: Vec<(u64, f64, f64, f64, bool)> = Vec::new();
-                    for quote in stock_quotes {
-                        let mut volatile: bool = false;
-                        let intra_day_high_low: f64 = (quote.high - quote.low) as f64;
-                        let threshold = 0.02 * (quote.close as f64);
-                        if intra_day_high_low > threshold {
-                            volatile = true;
-                        }
-                        let s = (quote.timestamp, quote.close as f64, quote.low as f64, quote.high as f64, volatile);
-                        stock_prices.push(s);
-                        closing_prices_vec.push(quote.close);
-                    }
+                    let stock_prices: Vec<Bar> = stock_quotes.iter().map(Bar::from_quote).collect();
                     let (max_date,max_price, min_date, min_price) = fetch_min_max_closing_pricing_with_dates(&stock_prices);
                     println!("Max closing Price For {} : {} on {}",&stock_args.name,max_price,DateTime::from_timestamp(max_date as i64, 0).unwrap());
                     println!("Min closing Price For {} : {} on {}",&stock_args.name,min_price,DateTime::from_timestamp(min_date as i64,0).unwrap());
 
-                    let times: Vec<DateTime<Utc>> = stock_prices.iter()
-                        .map(|&(unix_timestamp, _, _, _, _)| DateTime::from_timestamp(unix_timestamp as i64, 0).unwrap())
-                        .collect();
-                    match stock_picture_creator(&stock_prices, &stock_args.name) {
-                        Err(e) => {
-                            println!("Error Occured while creating the chart");
-                            error!("Error Occured while creating the chart:{:?}",e)
+                    let times: Vec<DateTime<Utc>> = stock_prices.iter().map(Bar::time).collect();
+
+                    // Longest warm-up among the indicators below, the periods must match the ones they are called with
+                    let warmup_bars = [
+                        sma_warmup(20), rsi_warmup(14), macd_warmup(26, 9), ema_warmup(50), sma_warmup(50),
+                        sma_warmup(stock_args.stochastic_period) + sma_warmup(stock_args.stochastic_smoothing),
+                        sma_warmup(stock_args.williams_period), sma_warmup(stock_args.cci_period), stock_args.roc_period,
+                        trend::adx_warmup(stock_args.adx_period), stock_args.aroon_period,
+                        sma_warmup(stock_args.keltner_period), sma_warmup(stock_args.donchian_period), sma_warmup(stock_args.atr_stop_period),
+                        ichimoku::ichimoku_warmup(stock_args.senkou_b_period, stock_args.kijun_period),
+                    ]
+                        .into_iter()
+                        .max()
+                        .unwrap_or(0);
+                    // Indicators are computed over the warm-up history followed by the requested range,
+                    // only the part from `offset` onwards lines up with `times`
+                    let mut indicator_bars: Vec<Bar> = Vec::new();
+                    if stock_args.warmup_history && !stock_prices.is_empty() {
+                        match fetch_warmup_history(&stock_args.name, &stock_args.interval, stock_prices[0].timestamp, warmup_bars).await {
+                            Ok(history) => {
+                                debug!("Fetched {} bars of warm-up history",history.len());
+                                indicator_bars = history;
+                            }
+                            Err(e) => {
+                                println!("Error while fetching warm-up history check log file for more details");
+                                error!("Error while fetching warm-up history:{:?}",e)
+                            }
+                        }
+                    }
+                    let offset = indicator_bars.len();
+                    indicator_bars.extend_from_slice(&stock_prices);
+                    let indicator_prices: Vec<f64> = indicator_bars.iter().map(|bar| bar.close).collect();
+                    let mut export = IndicatorExport::new(&stock_args.name, &times);
+                    let mut signal_report = SignalReport::new(&stock_args.name);
+                    let mut price_overlays: Vec<PriceOverlay> = Vec::new();
+
+                    // Swing highs/lows and the support/resistance levels they form
+                    if stock_args.levels {
+                        let swings = levels::swing_points(&stock_prices, stock_args.swing_strength);
+                        let last_close = stock_prices[stock_prices.len() - 1].close;
+                        let price_levels = levels::support_resistance_levels(&swings, stock_args.level_tolerance, last_close);
+                        let swing_count = |kind: SwingKind| swings.iter().filter(|swing| swing.kind == kind).count();
+                        println!("Support/resistance levels for {} ({} swing highs, {} swing lows):",
+                                 &stock_args.name, swing_count(SwingKind::High), swing_count(SwingKind::Low));
+                        // Nearest levels first on both sides of the close
+                        let (mut supports, mut resistances): (Vec<_>, Vec<_>) = price_levels.iter().partition(|level| level.support);
+                        supports.reverse();
+                        resistances.sort_by(|a, b| a.price.total_cmp(&b.price));
+                        for (name, side) in [("Resistance", &resistances), ("Support", &supports)] {
+                            for (n, level) in side.iter().enumerate() {
+                                println!("  {} {:.2} ({} touches)", name, level.price, level.touches);
+                                export.add_level(&format!("{}_{}", name.to_lowercase(), n + 1), level.price);
+                                price_overlays.push(PriceOverlay::Level {
+                                    label: name.to_owned(),
+                                    value: level.price,
+                                    color: if level.support { GREEN_700 } else { RED_700 },
+                                });
+                            }
+                        }
+                        for (kind, label, color) in [(SwingKind::High, "Swing highs", RED_700), (SwingKind::Low, "Swing lows", GREEN_700)] {
+                            let mut values = vec![f64::NAN; stock_prices.len()];
+                            for swing in swings.iter().filter(|swing| swing.kind == kind) {
+                                values[swing.index] = swing.price;
+                            }
+                            price_overlays.push(PriceOverlay::Dots { label: label.to_owned(), values, color });
+                        }
+                    }
+
+                    // Fibonacci retracement/extension between the extreme closes or the closes of the selected dates
+                    if stock_args.fibonacci || stock_args.fib_from.is_some() {
+                        let swing = match (stock_args.fib_from, stock_args.fib_to) {
+                            (Some(from), Some(to)) => {
+                                // First bar on or after the date, the market may have been closed on the day itself
+                                let bar_on = |date: NaiveDate| stock_prices.iter().position(|bar| bar.time().date_naive() >= date);
+                                match (bar_on(from), bar_on(to)) {
+                                    (Some(start), Some(end)) => Some((start, end)),
+                                    _ => None,
+                                }
+                            }
+                            _ => {
+                                let min_index = stock_prices.iter().position(|bar| bar.timestamp == min_date).unwrap();
+                                let max_index = stock_prices.iter().position(|bar| bar.timestamp == max_date).unwrap();
+                                Some((min_index.min(max_index), min_index.max(max_index)))
+                            }
+                        };
+                        match swing {
+                            Some((start, end)) => {
+                                let (start_price, end_price) = (stock_prices[start].close, stock_prices[end].close);
+                                println!("Fibonacci levels for {} from {:.2} on {} to {:.2} on {}:", &stock_args.name,
+                                         start_price, times[start].format("%Y-%m-%d"), end_price, times[end].format("%Y-%m-%d"));
+                                for (name, value) in fibonacci::fibonacci_levels(start_price, end_price) {
+                                    println!("  {:<18} {:.2}", name, value);
+                                    export.add_level(&format!("fibonacci_{}", name.replace(' ', "_").trim_end_matches('%')), value);
+                                    price_overlays.push(PriceOverlay::Level { label: format!("Fib {}", name), value, color: ORANGE });
+                                }
+                                let mut swing_line = vec![f64::NAN; stock_prices.len()];
+                                swing_line[start] = start_price;
+                                swing_line[end] = end_price;
+                                price_overlays.push(PriceOverlay::Line { label: "Fibonacci swing".to_owned(), values: swing_line, color: ORANGE });
+                            }
+                            None => println!("No bars found for the Fibonacci dates, the dates must lie within the requested range"),
+                        }
+                    }
+
+                    // Candlestick patterns, marked next to the bar that completes them
+                    if stock_args.patterns {
+                        let candlestick_patterns = patterns::candlestick_patterns(&stock_prices);
+                        let mut bullish = vec![f64::NAN; stock_prices.len()];
+                        let mut bearish = vec![f64::NAN; stock_prices.len()];
+                        let mut neutral = vec![f64::NAN; stock_prices.len()];
+                        for pattern in &candlestick_patterns {
+                            let bar = &stock_prices[pattern.index];
+                            signal_report.add(times[pattern.index], "Candlestick", &format!("{} ({})", pattern.name, pattern.bias.name()), bar.close, bar.close);
+                            match pattern.bias {
+                                Bias::Bullish => bullish[pattern.index] = bar.low,
+                                Bias::Bearish => bearish[pattern.index] = bar.high,
+                                Bias::Neutral => neutral[pattern.index] = bar.close,
+                            }
+                        }
+                        price_overlays.push(PriceOverlay::Triangles { label: "Bullish pattern".to_owned(), values: bullish, pointing_up: true, color: GREEN_700 });
+                        price_overlays.push(PriceOverlay::Triangles { label: "Bearish pattern".to_owned(), values: bearish, pointing_up: false, color: RED_700 });
+                        price_overlays.push(PriceOverlay::Dots { label: "Doji".to_owned(), values: neutral, color: BLACK });
+                    }
+
+                    // Pivot points for the session after the last bar
+                    for &method in &stock_args.pivots {
+                        let last_bar = &stock_prices[stock_prices.len() - 1];
+                        println!("{} pivot points for {} from the bar of {}:", method.name(), &stock_args.name, last_bar.time().format("%Y-%m-%d"));
+                        for (name, value) in levels::pivot_points(last_bar, method) {
+                            println!("  {:<3} {:.2}", name, value);
+                            export.add_level(&format!("{}_pivot_{}", method.name().to_lowercase(), name.to_lowercase()), value);
+                            price_overlays.push(PriceOverlay::Level {
+                                label: format!("{} {}", method.name(), name),
+                                value,
+                                color: match method {
+                                    PivotMethod::Classic => BLACK,
+                                    PivotMethod::Fibonacci => PURPLE,
+                                    PivotMethod::Camarilla => BROWN,
+                                },
+                            });
                         }
-                        _ => ()
                     }
 
                     //Bollinger Bands
-                    match bollinger_bands(closing_prices_vec.clone(), 20, 2.0,&stock_args.name,times.clone()) {
-                        Ok(_) => {debug!("Bollinger Bands Created")}
-                        Err(e) => {println!("Error while creating bolLinger bands check log file for more details");
-                            error!("Error while creating Bollinger Bands:{:?}",e)
+                    let (average, upper_bands, lower_bands) = bollinger_bands_values(&indicator_prices, 20, 2.0);
+                    if selected(Indicator::Bb) {
+                        export.add_series("bollinger_average_20", &average[offset..]);
+                        export.add_series("bollinger_upper_20", &upper_bands[offset..]);
+                        export.add_series("bollinger_lower_20", &lower_bands[offset..]);
+                        match bollinger_bands(&average[offset..], &upper_bands[offset..], &lower_bands[offset..], &stock_args.name, &currency, &times) {
+                            Ok(_) => {debug!("Bollinger Bands Created")}
+                            Err(e) => {println!("Error while creating bolLinger bands check log file for more details");
+                                error!("Error while creating Bollinger Bands:{:?}",e)
+                            }
                         }
                     }
 
                     //RSI
-                    match rsi(closing_prices_vec.clone(), 14, &stock_args.name,times.clone()) {
-                        Ok(_) => {debug!("RSI Chart Created Successfully")}
-                        Err(e) => {
-                            println!("Error While creating RSI chart check log file for more details");
-                            error!("Error while creating RSI Chart :{:?}",e);}
+                    if selected(Indicator::Rsi) {
+                        let rsi_line = rsi_values(&indicator_prices, 14);
+                        export.add_series("rsi_14", &rsi_line[offset..]);
+                        let overbought_crossings = level_crossings(&rsi_line[offset..], stock_args.rsi_overbought);
+                        let oversold_crossings = level_crossings(&rsi_line[offset..], stock_args.rsi_oversold);
+                        for &(i, cross) in &overbought_crossings {
+                            let event = match cross {
+                                Cross::Above => format!("crossed above {} (overbought)", stock_args.rsi_overbought),
+                                Cross::Below => format!("crossed below {}", stock_args.rsi_overbought),
+                            };
+                            signal_report.add(times[i], "RSI", &event, stock_prices[i].close, rsi_line[offset + i]);
+                        }
+                        for &(i, cross) in &oversold_crossings {
+                            let event = match cross {
+                                Cross::Below => format!("crossed below {} (oversold)", stock_args.rsi_oversold),
+                                Cross::Above => format!("crossed above {}", stock_args.rsi_oversold),
+                            };
+                            signal_report.add(times[i], "RSI", &event, stock_prices[i].close, rsi_line[offset + i]);
+                        }
+                        match rsi(&rsi_line[offset..], stock_args.rsi_overbought, stock_args.rsi_oversold, &overbought_crossings, &oversold_crossings, &stock_args.name, &times) {
+                            Ok(_) => {debug!("RSI Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error While creating RSI chart check log file for more details");
+                                error!("Error while creating RSI Chart :{:?}",e);}
+                        }
                     }
 
                     //MACD
-                    match macd(closing_prices_vec.clone(), 12, 26, 9,&stock_args.name) {
-                        Ok(_) => {
-                            debug!("MACD Chart Created Successfully")
+                    if selected(Indicator::Macd) {
+                        let (macd_line, signal_line, histogram) = macd_values(&indicator_prices, 12, 26, 9);
+                        export.add_series("macd", &macd_line[offset..]);
+                        export.add_series("macd_signal", &signal_line[offset..]);
+                        export.add_series("macd_histogram", &histogram[offset..]);
+                        let signal_crossings = series_crossings(&macd_line[offset..], &signal_line[offset..]);
+                        let zero_crossings = level_crossings(&macd_line[offset..], 0.0);
+                        for &(i, cross) in &signal_crossings {
+                            let event = match cross {
+                                Cross::Above => "bullish signal crossover",
+                                Cross::Below => "bearish signal crossover",
+                            };
+                            signal_report.add(times[i], "MACD", event, stock_prices[i].close, macd_line[offset + i]);
+                        }
+                        for &(i, cross) in &zero_crossings {
+                            let event = match cross {
+                                Cross::Above => "crossed above zero",
+                                Cross::Below => "crossed below zero",
+                            };
+                            signal_report.add(times[i], "MACD", event, stock_prices[i].close, macd_line[offset + i]);
                         }
-                        Err(e) => {
-                            println!("Error while creating MACD Chart check log file for details");
-                            error!("Error while creating MACD chart: {:?}",e)
+                        match macd((&macd_line[offset..], &signal_line[offset..], &histogram[offset..]), &signal_crossings, &zero_crossings, &stock_args.name, &currency, &times) {
+                            Ok(_) => {
+                                debug!("MACD Chart Created Successfully")
+                            }
+                            Err(e) => {
+                                println!("Error while creating MACD Chart check log file for details");
+                                error!("Error while creating MACD chart: {:?}",e)
+                            }
+                        }
+                    }
+
+                    // Divergences between the price swings (found with --swing-strength) and the oscillator swings
+                    for &source in &stock_args.divergences {
+                        let oscillator = match source {
+                            DivergenceSource::Rsi => rsi_values(&indicator_prices, 14),
+                            DivergenceSource::Macd => macd_values(&indicator_prices, 12, 26, 9).2,
+                        };
+                        let oscillator = &oscillator[offset..];
+                        let divergences = divergence::find_divergences(&stock_prices, oscillator, stock_args.swing_strength);
+                        let indicator = format!("{} divergence", source.name());
+                        for found in &divergences {
+                            let (start, end) = (found.price_start.0, found.price_end.0);
+                            let event = format!("{} since {}", found.kind.name(), times[start].format("%Y-%m-%d"));
+                            signal_report.add(times[end], &indicator, &event, stock_prices[end].close, found.oscillator_end.1);
+                        }
+                        match divergence::divergence_chart(source, &indicator_prices[offset..], oscillator, &divergences, &stock_args.name, &currency, &times) {
+                            Ok(_) => {debug!("{} Divergence Chart Created Successfully", source.name())}
+                            Err(e) => {
+                                println!("Error while creating {} divergence chart check log file for more details", source.name());
+                                error!("Error while creating {} Divergence Chart:{:?}", source.name(), e)
+                            }
                         }
                     }
 
                     // Fast/Slow Exponential Moving Averages
-                    match exponential_moving_averages(closing_prices_vec.clone(), 20, 50,&stock_args.name,times.clone()) {
-                        Ok(_) => {
-                            debug!("Graph for Exponential Moving Averages Created")
+                    let fast_ema_values = ema_values(&indicator_prices, 20);
+                    let slow_ema_values = ema_values(&indicator_prices, 50);
+                    if selected(Indicator::Ema) {
+                        export.add_series("ema_20", &fast_ema_values[offset..]);
+                        export.add_series("ema_50", &slow_ema_values[offset..]);
+                        let ema_crossings = series_crossings(&fast_ema_values[offset..], &slow_ema_values[offset..]);
+                        for &(i, cross) in &ema_crossings {
+                            signal_report.add(times[i], "EMA 20/50", crossover_name(cross), stock_prices[i].close, fast_ema_values[offset + i]);
                         }
-                        Err(e) => {
-                            println!("Error while creating Exponential Moving Averages Graph check log file for more details");
-                            error!("Error while creating Exponential Moving Averages Graph:{:?}",e);
+                        match exponential_moving_averages(&fast_ema_values[offset..], &slow_ema_values[offset..], &ema_crossings, &stock_args.name, &currency, &times) {
+                            Ok(_) => {
+                                debug!("Graph for Exponential Moving Averages Created")
+                            }
+                            Err(e) => {
+                                println!("Error while creating Exponential Moving Averages Graph check log file for more details");
+                                error!("Error while creating Exponential Moving Averages Graph:{:?}",e);
+                            }
+                        };
+                    }
+
+                    //Fast/Slow Simple Moving Averages
+                    let fast_sma_values = sma_values(&indicator_prices, 20);
+                    let slow_sma_values = sma_values(&indicator_prices, 50);
+                    if selected(Indicator::Sma) {
+                        export.add_series("sma_20", &fast_sma_values[offset..]);
+                        export.add_series("sma_50", &slow_sma_values[offset..]);
+                        let sma_crossings = series_crossings(&fast_sma_values[offset..], &slow_sma_values[offset..]);
+                        for &(i, cross) in &sma_crossings {
+                            signal_report.add(times[i], "SMA 20/50", crossover_name(cross), stock_prices[i].close, fast_sma_values[offset + i]);
+                        }
+                        match simple_moving_averages(&fast_sma_values[offset..], &slow_sma_values[offset..], &sma_crossings, &stock_args.name, &currency, &times) {
+                            Ok(_) => {debug!("Graph for simple moving averages created")}
+                            Err(e) => {
+                                println!("Error while creating simple moving averages graph check log file for more details");
+                                debug!("Error while creating simple moving Average {:?}",e)}
+                        }
+                    }
+
+                    // Stochastic oscillator
+                    if selected(Indicator::Stochastic) {
+                        let (percent_k, percent_d) = momentum::stochastic_values(&indicator_bars, stock_args.stochastic_period, stock_args.stochastic_smoothing);
+                        export.add_series(&format!("stochastic_k_{}", stock_args.stochastic_period), &percent_k[offset..]);
+                        export.add_series(&format!("stochastic_d_{}", stock_args.stochastic_smoothing), &percent_d[offset..]);
+                        match momentum::stochastic(&percent_k[offset..], &percent_d[offset..], &stock_args.name, &times) {
+                            Ok(_) => {debug!("Stochastic Oscillator Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error while creating Stochastic Oscillator chart check log file for more details");
+                                error!("Error while creating Stochastic Oscillator chart:{:?}",e)
+                            }
+                        }
+                    }
+
+                    // Williams %R
+                    if selected(Indicator::WilliamsR) {
+                        let williams_r = momentum::williams_r_values(&indicator_bars, stock_args.williams_period);
+                        export.add_series(&format!("williams_r_{}", stock_args.williams_period), &williams_r[offset..]);
+                        match momentum::williams_r(&williams_r[offset..], &stock_args.name, &times) {
+                            Ok(_) => {debug!("Williams %R Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error while creating Williams %R chart check log file for more details");
+                                error!("Error while creating Williams %R chart:{:?}",e)
+                            }
+                        }
+                    }
+
+                    // Commodity Channel Index
+                    if selected(Indicator::Cci) {
+                        let cci_line = momentum::cci_values(&indicator_bars, stock_args.cci_period);
+                        export.add_series(&format!("cci_{}", stock_args.cci_period), &cci_line[offset..]);
+                        match momentum::cci(&cci_line[offset..], &stock_args.name, &times) {
+                            Ok(_) => {debug!("CCI Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error while creating CCI chart check log file for more details");
+                                error!("Error while creating CCI chart:{:?}",e)
+                            }
+                        }
+                    }
+
+                    // Rate of Change
+                    if selected(Indicator::Roc) {
+                        let roc_line = momentum::roc_values(&indicator_prices, stock_args.roc_period);
+                        export.add_series(&format!("roc_{}", stock_args.roc_period), &roc_line[offset..]);
+                        match momentum::roc(&roc_line[offset..], &stock_args.name, &times) {
+                            Ok(_) => {debug!("Rate of Change Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error while creating Rate of Change chart check log file for more details");
+                                error!("Error while creating Rate of Change chart:{:?}",e)
+                            }
+                        }
+                    }
+
+                    // Average Directional Index with +DI/-DI
+                    if selected(Indicator::Adx) {
+                        let (adx_line, plus_di, minus_di) = trend::adx_values(&indicator_bars, stock_args.adx_period);
+                        export.add_series(&format!("adx_{}", stock_args.adx_period), &adx_line[offset..]);
+                        export.add_series(&format!("plus_di_{}", stock_args.adx_period), &plus_di[offset..]);
+                        export.add_series(&format!("minus_di_{}", stock_args.adx_period), &minus_di[offset..]);
+                        for (i, cross) in series_crossings(&plus_di[offset..], &minus_di[offset..]) {
+                            let event = match cross {
+                                Cross::Above => "+DI crossed above -DI",
+                                Cross::Below => "+DI crossed below -DI",
+                            };
+                            signal_report.add(times[i], "DMI", event, stock_prices[i].close, adx_line[offset + i]);
+                        }
+                        match trend::adx(&adx_line[offset..], &plus_di[offset..], &minus_di[offset..], &stock_args.name, &times) {
+                            Ok(_) => {debug!("ADX Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error while creating ADX chart check log file for more details");
+                                error!("Error while creating ADX chart:{:?}",e)
+                            }
+                        }
+                    }
+
+                    // Parabolic SAR, drawn on the price chart
+                    if selected(Indicator::Psar) {
+                        let sar = trend::parabolic_sar_values(&indicator_bars, stock_args.sar_step, stock_args.sar_max_step);
+                        export.add_series("parabolic_sar", &sar[offset..]);
+                        // The SAR flips to the other side whenever the close crosses it
+                        let closes: Vec<f64> = stock_prices.iter().map(|bar| bar.close).collect();
+                        for (i, cross) in series_crossings(&closes, &sar[offset..]) {
+                            let event = match cross {
+                                Cross::Above => "flipped below price (uptrend)",
+                                Cross::Below => "flipped above price (downtrend)",
+                            };
+                            signal_report.add(times[i], "PSAR", event, stock_prices[i].close, sar[offset + i]);
+                        }
+                        price_overlays.push(PriceOverlay::Dots {
+                            label: format!("Parabolic SAR ({}, {})", stock_args.sar_step, stock_args.sar_max_step),
+                            values: sar[offset..].to_vec(),
+                            color: MAGENTA,
+                        });
+                    }
+
+                    // Aroon
+                    if selected(Indicator::Aroon) {
+                        let (aroon_up, aroon_down, aroon_oscillator) = trend::aroon_values(&indicator_bars, stock_args.aroon_period);
+                        export.add_series(&format!("aroon_up_{}", stock_args.aroon_period), &aroon_up[offset..]);
+                        export.add_series(&format!("aroon_down_{}", stock_args.aroon_period), &aroon_down[offset..]);
+                        export.add_series(&format!("aroon_oscillator_{}", stock_args.aroon_period), &aroon_oscillator[offset..]);
+                        for (i, cross) in series_crossings(&aroon_up[offset..], &aroon_down[offset..]) {
+                            let event = match cross {
+                                Cross::Above => "up crossed above down",
+                                Cross::Below => "up crossed below down",
+                            };
+                            signal_report.add(times[i], "Aroon", event, stock_prices[i].close, aroon_oscillator[offset + i]);
+                        }
+                        match trend::aroon(&aroon_up[offset..], &aroon_down[offset..], &aroon_oscillator[offset..], &stock_args.name, &times) {
+                            Ok(_) => {debug!("Aroon Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error while creating Aroon chart check log file for more details");
+                                error!("Error while creating Aroon chart:{:?}",e)
+                            }
                         }
+                    }
+
+                    // Keltner Channel
+                    let (keltner_average, keltner_upper, keltner_lower) = channels::keltner_values(&indicator_bars, stock_args.keltner_period, stock_args.keltner_multiplier);
+                    let keltner_channel = PriceOverlay::Band {
+                        label: format!("Keltner Channel ({}, {})", stock_args.keltner_period, stock_args.keltner_multiplier),
+                        upper: keltner_upper[offset..].to_vec(),
+                        middle: Some(keltner_average[offset..].to_vec()),
+                        lower: keltner_lower[offset..].to_vec(),
+                        color: GREEN_700,
                     };
+                    if selected(Indicator::Keltner) {
+                        export.add_series(&format!("keltner_average_{}", stock_args.keltner_period), &keltner_average[offset..]);
+                        export.add_series(&format!("keltner_upper_{}", stock_args.keltner_period), &keltner_upper[offset..]);
+                        export.add_series(&format!("keltner_lower_{}", stock_args.keltner_period), &keltner_lower[offset..]);
+                        let keltner_chart = ChannelChart {
+                            file_prefix: "keltner_",
+                            caption: "Keltner Channel Chart for ",
+                            channels: vec![keltner_channel.clone()],
+                            squeeze: None,
+                        };
+                        match channels::channel_chart(keltner_chart, &indicator_prices[offset..], &stock_args.name, &currency, &times) {
+                            Ok(_) => {debug!("Keltner Channel Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error while creating Keltner Channel chart check log file for more details");
+                                error!("Error while creating Keltner Channel chart:{:?}",e)
+                            }
+                        }
+                    }
 
-                    //Fast/Slow Simple Moving Averages
-                    match simple_moving_averages(closing_prices_vec.clone(), 20, 50, &stock_args.name,times.clone()) {
-                        Ok(_) => {debug!("Graph for simple moving averages created")}
-                        Err(e) => {
-                            println!("Error while creating simple moving averages graph check log file for more details");
-                            debug!("Error while creating simple moving Average {:?}",e)}
+                    // Donchian Channel
+                    let (donchian_middle, donchian_upper, donchian_lower) = channels::donchian_values(&indicator_bars, stock_args.donchian_period);
+                    let donchian_channel = PriceOverlay::Band {
+                        label: format!("Donchian Channel ({})", stock_args.donchian_period),
+                        upper: donchian_upper[offset..].to_vec(),
+                        middle: Some(donchian_middle[offset..].to_vec()),
+                        lower: donchian_lower[offset..].to_vec(),
+                        color: DEEPORANGE,
+                    };
+                    if selected(Indicator::Donchian) {
+                        export.add_series(&format!("donchian_middle_{}", stock_args.donchian_period), &donchian_middle[offset..]);
+                        export.add_series(&format!("donchian_upper_{}", stock_args.donchian_period), &donchian_upper[offset..]);
+                        export.add_series(&format!("donchian_lower_{}", stock_args.donchian_period), &donchian_lower[offset..]);
+                        let donchian_chart = ChannelChart {
+                            file_prefix: "donchian_",
+                            caption: "Donchian Channel Chart for ",
+                            channels: vec![donchian_channel.clone()],
+                            squeeze: None,
+                        };
+                        match channels::channel_chart(donchian_chart, &indicator_prices[offset..], &stock_args.name, &currency, &times) {
+                            Ok(_) => {debug!("Donchian Channel Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error while creating Donchian Channel chart check log file for more details");
+                                error!("Error while creating Donchian Channel chart:{:?}",e)
+                            }
+                        }
+                    }
+
+                    // ATR stop bands
+                    let (long_stop, short_stop) = channels::atr_stop_values(&indicator_bars, stock_args.atr_stop_period, stock_args.atr_stop_multiplier);
+                    let atr_stops = vec![
+                        PriceOverlay::Line { label: "Long stop (ATR)".to_owned(), values: long_stop[offset..].to_vec(), color: CYAN_800 },
+                        PriceOverlay::Line { label: "Short stop (ATR)".to_owned(), values: short_stop[offset..].to_vec(), color: RED_700 },
+                    ];
+                    if selected(Indicator::AtrStops) {
+                        export.add_series(&format!("atr_long_stop_{}", stock_args.atr_stop_period), &long_stop[offset..]);
+                        export.add_series(&format!("atr_short_stop_{}", stock_args.atr_stop_period), &short_stop[offset..]);
+                        let atr_stop_chart = ChannelChart {
+                            file_prefix: "atr_stops_",
+                            caption: "ATR Stop Bands Chart for ",
+                            channels: atr_stops.clone(),
+                            squeeze: None,
+                        };
+                        match channels::channel_chart(atr_stop_chart, &indicator_prices[offset..], &stock_args.name, &currency, &times) {
+                            Ok(_) => {debug!("ATR Stop Bands Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error while creating ATR stop bands chart check log file for more details");
+                                error!("Error while creating ATR stop bands chart:{:?}",e)
+                            }
+                        }
+                    }
+
+                    // Squeeze: Bollinger Bands inside the Keltner Channel
+                    if selected(Indicator::Squeeze) {
+                        let squeeze = channels::squeeze_values(&upper_bands[offset..], &lower_bands[offset..], &keltner_upper[offset..], &keltner_lower[offset..]);
+                        export.add_series("squeeze", &squeeze);
+                        for (i, cross) in level_crossings(&squeeze, 0.5) {
+                            let event = match cross {
+                                Cross::Above => "squeeze on",
+                                Cross::Below => "squeeze fired",
+                            };
+                            signal_report.add(times[i], "Squeeze", event, stock_prices[i].close, upper_bands[offset + i] - lower_bands[offset + i]);
+                        }
+                        let bollinger_band = PriceOverlay::Band {
+                            label: "Bollinger Bands (20, 2)".to_owned(),
+                            upper: upper_bands[offset..].to_vec(),
+                            middle: None,
+                            lower: lower_bands[offset..].to_vec(),
+                            color: BLUE,
+                        };
+                        let squeeze_chart = ChannelChart {
+                            file_prefix: "squeeze_",
+                            caption: "Bollinger/Keltner Squeeze Chart for ",
+                            channels: vec![keltner_channel.clone(), bollinger_band],
+                            squeeze: Some(&squeeze),
+                        };
+                        match channels::channel_chart(squeeze_chart, &indicator_prices[offset..], &stock_args.name, &currency, &times) {
+                            Ok(_) => {debug!("Squeeze Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error while creating squeeze chart check log file for more details");
+                                error!("Error while creating squeeze chart:{:?}",e)
+                            }
+                        }
+                    }
+
+                    // Ichimoku Kinko Hyo
+                    if selected(Indicator::Ichimoku) {
+                        let displacement = stock_args.kijun_period;
+                        let ichimoku_lines = ichimoku::ichimoku_values(&indicator_bars, stock_args.tenkan_period, stock_args.kijun_period, stock_args.senkou_b_period, displacement)
+                            .slice(offset);
+                        // Only the values that fall on a fetched bar are exported, the projected cloud is drawn on the chart
+                        let bars_shown = times.len();
+                        export.add_series(&format!("ichimoku_tenkan_sen_{}", stock_args.tenkan_period), &ichimoku_lines.tenkan_sen[..bars_shown]);
+                        export.add_series(&format!("ichimoku_kijun_sen_{}", stock_args.kijun_period), &ichimoku_lines.kijun_sen[..bars_shown]);
+                        export.add_series("ichimoku_senkou_span_a", &ichimoku_lines.senkou_span_a[..bars_shown]);
+                        export.add_series(&format!("ichimoku_senkou_span_b_{}", stock_args.senkou_b_period), &ichimoku_lines.senkou_span_b[..bars_shown]);
+                        export.add_series("ichimoku_chikou_span", &ichimoku_lines.chikou_span[..bars_shown]);
+
+                        for (i, cross) in series_crossings(&ichimoku_lines.tenkan_sen[..bars_shown], &ichimoku_lines.kijun_sen[..bars_shown]) {
+                            let event = match cross {
+                                Cross::Above => "tenkan crossed above kijun",
+                                Cross::Below => "tenkan crossed below kijun",
+                            };
+                            signal_report.add(times[i], "Ichimoku", event, stock_prices[i].close, ichimoku_lines.tenkan_sen[i]);
+                        }
+                        let (cloud_top, cloud_bottom): (Vec<f64>, Vec<f64>) = (0..bars_shown)
+                            .map(|i| {
+                                let (span_a, span_b) = (ichimoku_lines.senkou_span_a[i], ichimoku_lines.senkou_span_b[i]);
+                                if span_a.is_finite() && span_b.is_finite() { (span_a.max(span_b), span_a.min(span_b)) } else { (f64::NAN, f64::NAN) }
+                            })
+                            .unzip();
+                        let closing_prices = &indicator_prices[offset..];
+                        for (i, cross) in series_crossings(closing_prices, &cloud_top) {
+                            if cross == Cross::Above {
+                                signal_report.add(times[i], "Ichimoku", "close broke above the cloud", stock_prices[i].close, cloud_top[i]);
+                            }
+                        }
+                        for (i, cross) in series_crossings(closing_prices, &cloud_bottom) {
+                            if cross == Cross::Below {
+                                signal_report.add(times[i], "Ichimoku", "close fell below the cloud", stock_prices[i].close, cloud_bottom[i]);
+                            }
+                        }
+
+                        let axis_times = [times.clone(), future_times(&times, &stock_args.interval, displacement)].concat();
+                        match ichimoku::ichimoku(&ichimoku_lines, closing_prices, &stock_args.name, &currency, &times, &axis_times) {
+                            Ok(_) => {debug!("Ichimoku Chart Created Successfully")}
+                            Err(e) => {
+                                println!("Error while creating Ichimoku chart check log file for more details");
+                                error!("Error while creating Ichimoku chart:{:?}",e)
+                            }
+                        }
+                    }
+
+                    // Price chart, drawn last so the requested indicators can be overlaid on it
+                    if stock_args.overlay.contains(&Overlay::Bb) {
+                        price_overlays.push(PriceOverlay::Band {
+                            label: "Bollinger Bands (20, 2)".to_owned(),
+                            upper: upper_bands[offset..].to_vec(),
+                            middle: Some(average[offset..].to_vec()),
+                            lower: lower_bands[offset..].to_vec(),
+                            color: BLUE,
+                        });
+                    }
+                    if stock_args.overlay.contains(&Overlay::Sma) {
+                        price_overlays.push(PriceOverlay::Line { label: "SMA 20".to_owned(), values: fast_sma_values[offset..].to_vec(), color: ORANGE });
+                        price_overlays.push(PriceOverlay::Line { label: "SMA 50".to_owned(), values: slow_sma_values[offset..].to_vec(), color: BROWN });
+                    }
+                    if stock_args.overlay.contains(&Overlay::Ema) {
+                        price_overlays.push(PriceOverlay::Line { label: "EMA 20".to_owned(), values: fast_ema_values[offset..].to_vec(), color: PURPLE });
+                        price_overlays.push(PriceOverlay::Line { label: "EMA 50".to_owned(), values: slow_ema_values[offset..].to_vec(), color: TEAL });
+                    }
+                    if stock_args.overlay.contains(&Overlay::Keltner) {
+                        price_overlays.push(keltner_channel);
+                    }
+                    if stock_args.overlay.contains(&Overlay::Donchian) {
+                        price_overlays.push(donchian_channel);
+                    }
+                    if stock_args.overlay.contains(&Overlay::AtrStops) {
+                        price_overlays.extend(atr_stops);
+                    }
+                    if let Err(e) = stock_picture_creator("stock_chart_", &stock_prices, &price_overlays, &stock_args.name, &currency) {
+                        println!("Error Occured while creating the chart");
+                        error!("Error Occured while creating the chart:{:?}",e)
+                    }
+
+                    // Heikin-Ashi, Renko and Point & Figure charts
+                    for &chart_type in &stock_args.chart_types {
+                        let chart = match chart_type {
+                            ChartType::HeikinAshi => chart_types::heikin_ashi(&chart_types::heikin_ashi_values(&stock_prices), &stock_args.name, &currency),
+                            ChartType::Renko => {
+                                let box_size = stock_args.renko_box.unwrap_or_else(|| chart_types::atr_box_size(&stock_prices, stock_args.box_atr_period));
+                                chart_types::check_box_size(&stock_prices, box_size)
+                                    .and_then(|_| chart_types::renko(&chart_types::renko_values(&stock_prices, box_size), box_size, &stock_args.name, &currency, &times))
+                            }
+                            ChartType::PointFigure => {
+                                let box_size = stock_args.pnf_box.unwrap_or_else(|| chart_types::atr_box_size(&stock_prices, stock_args.box_atr_period));
+                                chart_types::check_box_size(&stock_prices, box_size).and_then(|_| {
+                                    let columns = chart_types::point_figure_values(&stock_prices, box_size, stock_args.pnf_reversal);
+                                    chart_types::point_figure(&columns, box_size, stock_args.pnf_reversal, &stock_args.name, &currency, &times)
+                                })
+                            }
+                        };
+                        match chart {
+                            Ok(_) => {debug!("{:?} Chart Created Successfully", chart_type)}
+                            Err(e) => {
+                                println!("Error while creating {:?} chart check log file for more details", chart_type);
+                                error!("Error while creating {:?} Chart:{:?}", chart_type, e)
+                            }
+                        }
+                    }
+
+                    signal_report.print();
+                    if stock_args.export {
+                        match export.write() {
+                            Ok(export_file_name) => println!("Indicator values written to {}", export_file_name),
+                            Err(e) => {
+                                println!("Error while exporting indicator values check log file for more details");
+                                error!("Error while exporting indicator values:{:?}",e)
+                            }
+                        }
+                        match signal_report.write() {
+                            Ok(report_file_name) => println!("Signal report written to {}", report_file_name),
+                            Err(e) => {
+                                println!("Error while writing the signal report check log file for more details");
+                                error!("Error while writing the signal report:{:?}",e)
+                            }
+                        }
                     }
                 }
                 Err(e) => {
@@ -238,30 +1115,142 @@ async fn main() {
     }
 }
 
-fn fetch_min_max_closing_pricing_with_dates(stock_prices: &Vec<(u64, f64, f64, f64, bool)>) -> (u64,f64,u64,f64) {
+fn fetch_min_max_closing_pricing_with_dates(stock_prices: &[Bar]) -> (u64,f64,u64,f64) {
     let mut min = f64::INFINITY;
     let mut max = f64::NEG_INFINITY;
     let mut min_date = 0;
     let mut max_date = 0;
     for stock_price in stock_prices{
 
-        if stock_price.1 < min {
-            min = stock_price.1;
-            min_date = stock_price.0;
+        if stock_price.close < min {
+            min = stock_price.close;
+            min_date = stock_price.timestamp;
         }
-        if stock_price.1 > max {
-            max = stock_price.1;
-            max_date = stock_price.0;
+        if stock_price.close > max {
+            max = stock_price.close;
+            max_date = stock_price.timestamp;
         }
     }
     (max_date,max,min_date,min)
 }
 
+// Fetches every ticker and prints the candlestick patterns completed on its last `bars` bars
+async fn scan_patterns(tickers: &[&str], interval: &str, range: &str, bars: usize) {
+    println!("Candlestick patterns on the last {} bar(s) of {} tickers", bars, tickers.len());
+    for ticker in tickers {
+        match fetch_stock(ticker, interval, range).await.and_then(|response| response.quotes()) {
+            Ok(quotes) => {
+                let stock_prices: Vec<Bar> = quotes.iter().map(Bar::from_quote).collect();
+                for index in stock_prices.len().saturating_sub(bars)..stock_prices.len() {
+                    for pattern in patterns::patterns_at(&stock_prices, index) {
+                        println!("  {:<8} {} {:<22} {}", ticker, stock_prices[index].time().format("%Y-%m-%d"), pattern.name, pattern.bias.name());
+                    }
+                }
+            }
+            Err(e) => {
+                println!("Error while scanning {} check log file for more details", ticker);
+                error!("Error while scanning {} for candlestick patterns:{}", ticker, e)
+            }
+        }
+    }
+}
+
 async fn fetch_stock(stock_name: &str, interval: &str, range: &str) -> Result<YResponse, YahooError> {
-    CONNECTOR.lock().unwrap().get_quote_range(stock_name, interval, range).await
+    CONNECTOR.get_quote_range(stock_name, interval, range).await
+}
+
+// Fetches up to `bars` stock prices right before `first_timestamp`, used to warm up the indicators
+// so they are already valid on the first bar of the requested range
+async fn fetch_warmup_history(stock_name: &str, interval: &str, first_timestamp: u64, bars: usize) -> Result<Vec<Bar>, Box<dyn std::error::Error>> {
+    let bar_seconds = interval_seconds(interval);
+    // Markets are closed at night, on weekends and on holidays, so look back well past `bars` bars of wall-clock time
+    let gap_factor = if bar_seconds < 86_400 { 6 } else { 2 };
+    let start = OffsetDateTime::from_unix_timestamp(first_timestamp as i64 - bar_seconds * bars as i64 * gap_factor)?;
+    let end = OffsetDateTime::from_unix_timestamp(first_timestamp as i64)?;
+    let response = CONNECTOR.get_quote_history_interval(stock_name, start, end, interval).await?;
+
+    let history: Vec<Bar> = response.quotes()?.iter()
+        .filter(|quote| quote.timestamp < first_timestamp)
+        .map(Bar::from_quote)
+        .collect();
+    Ok(history[history.len().saturating_sub(bars)..].to_vec())
+}
+
+// Dates of the `bars` bars following the last one in `times`, used for charts that project values into
+// the future. Weekends are skipped for daily bars since markets are closed then.
+fn future_times(times: &[DateTime<Utc>], interval: &str, bars: usize) -> Vec<DateTime<Utc>> {
+    let bar_seconds = interval_seconds(interval);
+    let mut future = Vec::with_capacity(bars);
+    let mut time = times[times.len() - 1];
+    while future.len() < bars {
+        time += Duration::seconds(bar_seconds);
+        if bar_seconds == 86_400 && matches!(time.weekday(), Weekday::Sat | Weekday::Sun) {
+            continue;
+        }
+        future.push(time);
+    }
+    future
+}
+
+// Approximate length of one bar in seconds for the Yahoo intervals (1m, 1h, 1d, 1wk, 1mo, ...)
+fn interval_seconds(interval: &str) -> i64 {
+    let unit_start = interval.find(|c: char| !c.is_ascii_digit()).unwrap_or(interval.len());
+    let count: i64 = interval[..unit_start].parse().unwrap_or(1);
+    let unit_seconds = match &interval[unit_start..] {
+        "m" => 60,
+        "h" => 3_600,
+        "wk" => 7 * 86_400,
+        "mo" => 30 * 86_400,
+        _ => 86_400,
+    };
+    count * unit_seconds
+}
+
+// Number of leading bars each indicator needs before its output means anything.
+// `ta` emits a value from the very first bar, these values are replaced by NaN with `mask_warmup`.
+fn sma_warmup(period: usize) -> usize {
+    period.saturating_sub(1)
+}
+
+fn ema_warmup(period: usize) -> usize {
+    period.saturating_sub(1)
+}
+
+fn rsi_warmup(period: usize) -> usize {
+    period
+}
+
+// The MACD line needs the slow EMA, the signal line and histogram additionally need the signal EMA
+fn macd_warmup(slow_period: usize, signal_period: usize) -> usize {
+    ema_warmup(slow_period) + ema_warmup(signal_period)
+}
+
+fn mask_warmup(values: &mut [f64], warmup: usize) {
+    values.iter_mut().take(warmup).for_each(|value| *value = f64::NAN);
 }
 
-fn bollinger_bands(closing_prices: Vec<f64>, period: usize, multiplier: f64, ticker_name: &str, times: Vec<DateTime<Utc>>) -> Result<(), Box<dyn std::error::Error>> {
+// Axis title for a price denominated axis, the currency is left out when Yahoo did not report one
+fn axis_title(name: &str, currency: &str) -> String {
+    if currency.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{} ({})", name, currency)
+    }
+}
+
+// Min and max of the valid values, so masked warm-up points do not stretch the axis
+fn finite_range<'a>(values: impl IntoIterator<Item = &'a f64>) -> (f64, f64) {
+    let (min_value, max_value) = values.into_iter()
+        .filter(|value| value.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), &value| (min.min(value), max.max(value)));
+    if min_value > max_value {
+        (0.0, 1.0)
+    } else {
+        (min_value, max_value)
+    }
+}
+
+fn bollinger_bands_values(closing_prices: &[f64], period: usize, multiplier: f64) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
     let mut average = Vec::new();
     let mut upper_bands = Vec::new();
     let mut lower_bands = Vec::new();
@@ -276,227 +1265,370 @@ fn bollinger_bands(closing_prices: Vec<f64>, period: usize, multiplier: f64, tic
             lower_bands.push(output.lower);
         });
 
+    mask_warmup(&mut average, sma_warmup(period));
+    mask_warmup(&mut upper_bands, sma_warmup(period));
+    mask_warmup(&mut lower_bands, sma_warmup(period));
+    (average, upper_bands, lower_bands)
+}
+
+fn bollinger_bands(average: &[f64], upper_bands: &[f64], lower_bands: &[f64], ticker_name: &str, currency: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
     let bollinger_bands_file_name = "bollinger_bands_".to_owned() + ticker_name + ".png";
     let root = BitMapBackend::new(&bollinger_bands_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
     root.fill(&WHITE)?;
 
     // Find the minimum and maximum values in the vector, handling negative values
-    let min_value = lower_bands.iter().fold(f64::INFINITY, |a, &b| a.min(b));
-    let max_value = upper_bands.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+    let (min_value, max_value) = finite_range(lower_bands.iter().chain(upper_bands.iter()));
 
     // Set the chart area, handling the range from min_value to max_value
     let mut chart = ChartBuilder::on(&root)
         .caption("Bollinger Bands Graph For ".to_owned() + ticker_name, ("sans-serif", 30).into_font())
         .margin(10)
         .x_label_area_size(55)
-        .y_label_area_size(40)
+        .y_label_area_size(60)
         .build_cartesian_2d(times[0]..times[times.len() - 1], min_value..max_value)?;
 
     chart.configure_mesh()
         .x_labels(5)
         .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Price", currency))
         .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
         .draw()?;
 
     chart.draw_series(LineSeries::new(
-        times.iter().zip(average.iter()).map(|(&time, &avg)| (time, avg)),
+        times.iter().zip(average.iter()).filter(|(_, avg)| avg.is_finite()).map(|(&time, &avg)| (time, avg)),
         &RED,
-    ))?;
+    ))?
+        .label("Middle band (SMA)")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
 
     chart.draw_series(LineSeries::new(
-        times.iter().zip(upper_bands.iter()).map(|(&time, &upper)| (time, upper)),
+        times.iter().zip(upper_bands.iter()).filter(|(_, upper)| upper.is_finite()).map(|(&time, &upper)| (time, upper)),
         &BLUE,
-    ))?;
+    ))?
+        .label("Upper band")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE));
 
     chart.draw_series(LineSeries::new(
-        times.iter().zip(lower_bands.iter()).map(|(&time, &lower)| (time, lower)),
+        times.iter().zip(lower_bands.iter()).filter(|(_, lower)| lower.is_finite()).map(|(&time, &lower)| (time, lower)),
         &BLACK,
-    ))?;
+    ))?
+        .label("Lower band")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK));
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
 
     root.present()?;
     Ok(())
 }
 
-fn rsi(closing_prices: Vec<f64>, period: usize, ticker_name: &str, times: Vec<DateTime<Utc>>) -> Result<(), Box<dyn std::error::Error>> {
+fn rsi_values(closing_prices: &[f64], period: usize) -> Vec<f64> {
     let mut rsi = RelativeStrengthIndex::new(period).unwrap();
     let mut vector: Vec<f64> = Vec::new();
 
-    for price in closing_prices {
+    for &price in closing_prices {
         let rsi_value = rsi.next(price);
         vector.push(rsi_value);
     }
 
+    mask_warmup(&mut vector, rsi_warmup(period));
+    vector
+}
+
+// RSI on a fixed 0-100 axis with shaded overbought/oversold zones. Crossings into a zone are drawn
+// as filled circles, crossings out of it as hollow circles.
+fn rsi(
+    vector: &[f64],
+    overbought: f64,
+    oversold: f64,
+    overbought_crossings: &[(usize, Cross)],
+    oversold_crossings: &[(usize, Cross)],
+    ticker_name: &str,
+    times: &[DateTime<Utc>],
+) -> Result<(), Box<dyn std::error::Error>> {
     let rsi_file_name = "rsi".to_owned() + ticker_name + ".png";
     let root = BitMapBackend::new(&rsi_file_name, (WINDOW_WIDTH, 480)).into_drawing_area();
     root.fill(&WHITE)?;
 
-    // Find the minimum and maximum values in the vector, handling negative values
-    //let min_value = vector.iter().fold(f64::INFINITY, |a, &b| a.min(b));
+    // RSI is bounded, so the axis always spans the full 0-100 range
     let min_value = 0.0;
-    let max_value = vector.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+    let max_value = 100.0;
+    let (first_time, last_time) = (times[0], times[times.len() - 1]);
 
-    // Set the chart area, handling the range from min_value to max_value
     let mut chart = ChartBuilder::on(&root)
         .caption("RSI Chart for ".to_owned() + ticker_name, ("sans-serif", 30).into_font())
         .margin(10)
         .x_label_area_size(55)
-        .y_label_area_size(40)
-        .build_cartesian_2d(times[0]..times[times.len() - 1], min_value..max_value)?;
+        .y_label_area_size(60)
+        .build_cartesian_2d(first_time..last_time, min_value..max_value)?;
 
     chart.configure_mesh()
         .x_labels(5)
         .y_labels(5)
+        .x_desc("Date")
+        .y_desc("RSI")
         .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
         .draw()?;
 
+    // Shaded overbought and oversold zones with their level lines
+    chart.draw_series(std::iter::once(Rectangle::new(
+        [(first_time, overbought), (last_time, max_value)],
+        RED.mix(0.1).filled(),
+    )))?
+        .label(format!("Overbought (> {})", overbought))
+        .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], RED.mix(0.3).filled()));
+    chart.draw_series(std::iter::once(Rectangle::new(
+        [(first_time, min_value), (last_time, oversold)],
+        GREEN.mix(0.1).filled(),
+    )))?
+        .label(format!("Oversold (< {})", oversold))
+        .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], GREEN.mix(0.3).filled()));
+    chart.draw_series(LineSeries::new(vec![(first_time, overbought), (last_time, overbought)], RED.mix(0.6)))?;
+    chart.draw_series(LineSeries::new(vec![(first_time, oversold), (last_time, oversold)], GREEN.mix(0.6)))?;
+
     chart.draw_series(LineSeries::new(
-        times.iter().zip(vector.iter()).map(|(&time, &price)| (time, price)),
+        times.iter().zip(vector.iter()).filter(|(_, price)| price.is_finite()).map(|(&time, &price)| (time, price)),
         &RED,
-    ))?;
-    //chart.configure_series_labels().border_style(&BLACK).draw()?;
+    ))?
+        .label("RSI")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+
+    for &(i, cross) in overbought_crossings {
+        let entering = cross == Cross::Above;
+        chart.draw_series(std::iter::once(Circle::new(
+            (times[i], vector[i]),
+            5,
+            ShapeStyle { color: RED.to_rgba(), filled: entering, stroke_width: 2 },
+        )))?;
+    }
+    for &(i, cross) in oversold_crossings {
+        let entering = cross == Cross::Below;
+        chart.draw_series(std::iter::once(Circle::new(
+            (times[i], vector[i]),
+            5,
+            ShapeStyle { color: GREEN.to_rgba(), filled: entering, stroke_width: 2 },
+        )))?;
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
 
     root.present()?;
     Ok(())
 }
 
-fn macd(
-    closing_prices: Vec<f64>,
-    fast_period: usize,
-    slow_period: usize,
-    signal_period: usize,
-    ticker_name: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
+fn macd_values(closing_prices: &[f64], fast_period: usize, slow_period: usize, signal_period: usize) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
     let mut macd = Macd::new(fast_period, slow_period, signal_period).unwrap();
 
     let mut macd_line: Vec<f64> = Vec::new();
     let mut signal_line: Vec<f64> = Vec::new();
     let mut histogram: Vec<f64> = Vec::new();
 
-    for price in closing_prices {
+    for &price in closing_prices {
         let macd_result = macd.next(price);
         macd_line.push(macd_result.macd);
         signal_line.push(macd_result.signal);
         histogram.push(macd_result.histogram);
     }
 
+    mask_warmup(&mut macd_line, ema_warmup(slow_period));
+    mask_warmup(&mut signal_line, macd_warmup(slow_period, signal_period));
+    mask_warmup(&mut histogram, macd_warmup(slow_period, signal_period));
+    (macd_line, signal_line, histogram)
+}
+
+// MACD line, signal line and histogram on the shared date axis. Bullish signal crossovers are marked
+// with green triangles, bearish ones with red triangles and zero-line crossings with black circles.
+fn macd(
+    (macd_line, signal_line, histogram): (&[f64], &[f64], &[f64]),
+    signal_crossings: &[(usize, Cross)],
+    zero_crossings: &[(usize, Cross)],
+    ticker_name: &str,
+    currency: &str,
+    times: &[DateTime<Utc>],
+) -> Result<(), Box<dyn std::error::Error>> {
     let macd_chart_file_name = "macd_".to_owned()+ticker_name+".png";
     let root = BitMapBackend::new(&macd_chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
     root.fill(&WHITE)?;
 
-    // Find the minimum and maximum values in the MACD and Signal line
-    let min_value = macd_line.iter().chain(macd_line.iter()).cloned().fold(f64::INFINITY, f64::min);
-    let max_value = macd_line.iter().chain(macd_line.iter()).cloned().fold(f64::NEG_INFINITY, f64::max);
+    // Find the minimum and maximum values over the MACD line, Signal line and histogram
+    let (min_value, max_value) = finite_range(macd_line.iter().chain(signal_line.iter()).chain(histogram.iter()));
+    let (first_time, last_time) = (times[0], times[times.len() - 1]);
 
     // Set the chart area, handling the range from min_value to max_value
     let mut chart = ChartBuilder::on(&root)
-        .caption("MACD Graph For ".to_owned() + ticker_name, ("sans-serif", 50).into_font())
-        .margin(20)
-        .x_label_area_size(30)
-        .y_label_area_size(30)
-        .build_cartesian_2d(0..signal_line.len(), min_value..max_value)?;
+        .caption("MACD Graph For ".to_owned() + ticker_name, ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(first_time..last_time, min_value.min(0.0)..max_value.max(0.0))?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("MACD", currency))
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
 
-    // Configure the mesh (grid) and labels
-    chart.configure_mesh().draw()?;
+    // Draw the histogram, with positive values in green and negative values in red. Bars are a third
+    // of the closest spacing between two bars wide so they never overlap.
+    let bar_half_width = times.windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .min()
+        .unwrap_or(chrono::Duration::days(1)) / 6;
+    chart.draw_series(
+        times.iter().zip(histogram.iter()).filter(|(_, value)| value.is_finite()).map(|(&time, &hist_value)| {
+            let bar_color = if hist_value >= 0.0 { GREEN.filled() } else { RED.filled() };
+            Rectangle::new([(time - bar_half_width, 0.0), (time + bar_half_width, hist_value)], bar_color)
+        }),
+    )?
+        .label("Histogram")
+        .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], GREEN.filled()));
+
+    // Zero line
+    chart.draw_series(LineSeries::new(vec![(first_time, 0.0), (last_time, 0.0)], BLACK.mix(0.4)))?;
 
     // Draw the MACD line in black
     chart.draw_series(LineSeries::new(
-        macd_line.iter().enumerate().map(|(i, &y)| (i, y)),
+        times.iter().zip(macd_line.iter()).filter(|(_, y)| y.is_finite()).map(|(&time, &y)| (time, y)),
         &BLACK,
-    ))?;
+    ))?
+        .label("MACD")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK));
 
     // Draw the Signal line in red
     chart.draw_series(LineSeries::new(
-        signal_line.iter().enumerate().map(|(i, &y)| (i, y)),
+        times.iter().zip(signal_line.iter()).filter(|(_, y)| y.is_finite()).map(|(&time, &y)| (time, y)),
         &RED,
-    ))?;
-
-    // Draw the histogram, with positive values in green and negative values in red
-    for (i, &hist_value) in histogram.iter().enumerate() {
-        let bar_color = if hist_value >= 0.0 { GREEN.filled() } else { RED.filled() };
-        chart.draw_series(Histogram::vertical(&chart)
-                              .style(bar_color)
-                              .data(std::iter::once((i, hist_value))),
-        )?;
-    }
+    ))?
+        .label("Signal")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
+
+    chart.draw_series(signal_crossings.iter().filter(|&&(_, cross)| cross == Cross::Above)
+        .map(|&(i, _)| TriangleMarker::new((times[i], macd_line[i]), 7, GREEN.filled())))?
+        .label("Bullish crossover")
+        .legend(|(x, y)| TriangleMarker::new((x + 10, y), 7, GREEN.filled()));
+    chart.draw_series(signal_crossings.iter().filter(|&&(_, cross)| cross == Cross::Below)
+        .map(|&(i, _)| TriangleMarker::new((times[i], macd_line[i]), 7, RED.filled())))?
+        .label("Bearish crossover")
+        .legend(|(x, y)| TriangleMarker::new((x + 10, y), 7, RED.filled()));
+    chart.draw_series(zero_crossings.iter()
+        .map(|&(i, _)| Circle::new((times[i], 0.0), 5, BLACK.stroke_width(2))))?
+        .label("Zero-line crossing")
+        .legend(|(x, y)| Circle::new((x + 10, y), 5, BLACK.stroke_width(2)));
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
 
     root.present()?;
     Ok(())
 }
 
-
-fn exponential_moving_averages(closing_prices_vec: Vec<f64>, fast_period: usize, slow_period: usize, ticker_name:&str, times: Vec<DateTime<Utc>>) -> Result<(), Box<dyn std::error::Error>> {
-    let mut fast_ema = ExponentialMovingAverage::new(fast_period).unwrap(); // Fast EMA
-    let mut slow_ema = ExponentialMovingAverage::new(slow_period).unwrap(); // Slow EMA
-
-    let mut fast_ema_values = Vec::new();
-    let mut slow_ema_values = Vec::new();
-
-    for price in closing_prices_vec {
-        let fast_value = fast_ema.next(price);
-        let slow_value = slow_ema.next(price);
-
-        fast_ema_values.push(fast_value);
-        slow_ema_values.push(slow_value);
+// A fast average crossing above the slow one is a golden cross, crossing below it a death cross
+fn crossover_name(cross: Cross) -> &'static str {
+    match cross {
+        Cross::Above => "golden cross",
+        Cross::Below => "death cross",
     }
+}
 
+fn ema_values(closing_prices_vec: &[f64], period: usize) -> Vec<f64> {
+    let mut ema = ExponentialMovingAverage::new(period).unwrap();
+    let mut ema_values: Vec<f64> = closing_prices_vec.iter().map(|&price| ema.next(price)).collect();
+    mask_warmup(&mut ema_values, ema_warmup(period));
+    ema_values
+}
+
+fn exponential_moving_averages(fast_ema_values: &[f64], slow_ema_values: &[f64], crossings: &[(usize, Cross)], ticker_name:&str, currency: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
     let ema_chart_filename = "ema_".to_owned()+ticker_name+".png";
     let root = BitMapBackend::new(&ema_chart_filename, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
     root.fill(&WHITE)?;
 
     // Find the minimum and maximum values in the vector, handling negative values
-    let min_value = fast_ema_values.iter().chain(slow_ema_values.iter()).fold(f64::INFINITY, |a, &b| a.min(b));
-    let max_value = fast_ema_values.iter().chain(slow_ema_values.iter()).fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+    let (min_value, max_value) = finite_range(fast_ema_values.iter().chain(slow_ema_values.iter()));
 
     // Set the chart area, handling the range from min_value to max_value
     let mut chart = ChartBuilder::on(&root)
         .caption("Exponential Moving Averages for ".to_owned() + ticker_name, ("sans-serif", 30).into_font())
         .margin(10)
         .x_label_area_size(55)
-        .y_label_area_size(40)
+        .y_label_area_size(60)
         .build_cartesian_2d(times[0]..times[times.len() - 1], min_value..max_value)?;
 
     chart.configure_mesh()
         .x_labels(5)
         .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Price", currency))
         .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
         .draw()?;
 
     chart.draw_series(LineSeries::new(
-        times.iter().zip(fast_ema_values.iter()).map(|(&time, &price)| (time, price)),
+        times.iter().zip(fast_ema_values.iter()).filter(|(_, price)| price.is_finite()).map(|(&time, &price)| (time, price)),
         &RED,
-    ))?;
+    ))?
+        .label("Fast EMA")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
 
     chart.draw_series(LineSeries::new(
-        times.iter().zip(slow_ema_values.iter()).map(|(&time, &price)| (time, price)),
+        times.iter().zip(slow_ema_values.iter()).filter(|(_, price)| price.is_finite()).map(|(&time, &price)| (time, price)),
         &BLACK,
-    ))?;
+    ))?
+        .label("Slow EMA")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK));
+
+    // Golden crosses as gold triangles, death crosses as black crosses, both labelled
+    chart.draw_series(crossings.iter().filter(|&&(_, cross)| cross == Cross::Above)
+        .map(|&(i, _)| TriangleMarker::new((times[i], fast_ema_values[i]), 8, GOLD.filled())))?
+        .label("Golden cross")
+        .legend(|(x, y)| TriangleMarker::new((x + 10, y), 8, GOLD.filled()));
+    chart.draw_series(crossings.iter().filter(|&&(_, cross)| cross == Cross::Below)
+        .map(|&(i, _)| plotters::element::Cross::new((times[i], fast_ema_values[i]), 6, BLACK.stroke_width(2))))?
+        .label("Death cross")
+        .legend(|(x, y)| plotters::element::Cross::new((x + 10, y), 6, BLACK.stroke_width(2)));
+    for &(i, cross) in crossings {
+        chart.draw_series(std::iter::once(Text::new(
+            crossover_name(cross),
+            (times[i], fast_ema_values[i]),
+            ("sans-serif", 15).into_font(),
+        )))?;
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
 
     root.present()?;
     Ok(())
 }
 
-fn simple_moving_averages(closing_prices_vec: Vec<f64>, fast_period: usize, slow_period: usize, ticker_name:&str, times: Vec<DateTime<Utc>>) -> Result<(), Box<dyn std::error::Error>> {
-    let mut fast_sma = SimpleMovingAverage::new(fast_period).unwrap(); // Fast SMA
-    let mut slow_sma = SimpleMovingAverage::new(slow_period).unwrap(); // Slow EMA
-
-    let mut fast_sma_values = Vec::new();
-    let mut slow_sma_values = Vec::new();
-
-    for price in closing_prices_vec {
-        let fast_value = fast_sma.next(price);
-        let slow_value = slow_sma.next(price);
-
-        fast_sma_values.push(fast_value);
-        slow_sma_values.push(slow_value);
-    }
+fn sma_values(closing_prices_vec: &[f64], period: usize) -> Vec<f64> {
+    let mut sma = SimpleMovingAverage::new(period).unwrap();
+    let mut sma_values: Vec<f64> = closing_prices_vec.iter().map(|&price| sma.next(price)).collect();
+    mask_warmup(&mut sma_values, sma_warmup(period));
+    sma_values
+}
 
+fn simple_moving_averages(fast_sma_values: &[f64], slow_sma_values: &[f64], crossings: &[(usize, Cross)], ticker_name:&str, currency: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
     let sma_chart_file_name = "sma_".to_owned() + ticker_name + ".png";
     let root = BitMapBackend::new(&sma_chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
     root.fill(&WHITE)?;
 
     // Find the minimum and maximum values in the vector, handling negative values
-    let min_value = fast_sma_values.iter().chain(slow_sma_values.iter()).fold(f64::INFINITY, |a, &b| a.min(b));
-    let max_value = fast_sma_values.iter().chain(slow_sma_values.iter()).fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+    let (min_value, max_value) = finite_range(fast_sma_values.iter().chain(slow_sma_values.iter()));
 
     // Set the chart area, handling the range from min_value to max_value
     // Set the chart area, handling the range from min_value to max_value
@@ -504,27 +1636,85 @@ fn simple_moving_averages(closing_prices_vec: Vec<f64>, fast_period: usize, slow
         .caption("Simple Moving Averages Chart for ".to_owned() + ticker_name, ("sans-serif", 30).into_font())
         .margin(10)
         .x_label_area_size(55)
-        .y_label_area_size(40)
+        .y_label_area_size(60)
         .build_cartesian_2d(times[0]..times[times.len() - 1], min_value..max_value)?;
 
     chart.configure_mesh()
         .x_labels(5)
         .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Price", currency))
         .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
         .draw()?;
 
     chart.draw_series(LineSeries::new(
-        times.iter().zip(fast_sma_values.iter()).map(|(&time, &price)| (time, price)),
+        times.iter().zip(fast_sma_values.iter()).filter(|(_, price)| price.is_finite()).map(|(&time, &price)| (time, price)),
         &RED,
-    ))?;
+    ))?
+        .label("Fast SMA")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], RED));
 
     chart.draw_series(LineSeries::new(
-        times.iter().zip(slow_sma_values.iter()).map(|(&time, &price)| (time, price)),
+        times.iter().zip(slow_sma_values.iter()).filter(|(_, price)| price.is_finite()).map(|(&time, &price)| (time, price)),
         &BLACK,
-    ))?;
+    ))?
+        .label("Slow SMA")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK));
+
+    // Golden crosses as gold triangles, death crosses as black crosses, both labelled
+    chart.draw_series(crossings.iter().filter(|&&(_, cross)| cross == Cross::Above)
+        .map(|&(i, _)| TriangleMarker::new((times[i], fast_sma_values[i]), 8, GOLD.filled())))?
+        .label("Golden cross")
+        .legend(|(x, y)| TriangleMarker::new((x + 10, y), 8, GOLD.filled()));
+    chart.draw_series(crossings.iter().filter(|&&(_, cross)| cross == Cross::Below)
+        .map(|&(i, _)| plotters::element::Cross::new((times[i], fast_sma_values[i]), 6, BLACK.stroke_width(2))))?
+        .label("Death cross")
+        .legend(|(x, y)| plotters::element::Cross::new((x + 10, y), 6, BLACK.stroke_width(2)));
+    for &(i, cross) in crossings {
+        chart.draw_series(std::iter::once(Text::new(
+            crossover_name(cross),
+            (times[i], fast_sma_values[i]),
+            ("sans-serif", 15).into_font(),
+        )))?;
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
 
     root.present()?;
     Ok(())
 }
 
  
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mask_warmup_hides_the_leading_values() {
+        let mut values = vec![1.0, 2.0, 3.0, 4.0];
+        mask_warmup(&mut values, 2);
+        assert!(values[0].is_nan() && values[1].is_nan());
+        assert_eq!(values[2..], [3.0, 4.0]);
+        // A warm-up longer than the series masks all of it
+        let mut short = vec![1.0, 2.0];
+        mask_warmup(&mut short, 5);
+        assert!(short.iter().all(|value| value.is_nan()));
+        let mut none = vec![1.0];
+        mask_warmup(&mut none, 0);
+        assert_eq!(none, vec![1.0]);
+    }
+
+    #[test]
+    fn sma_warmup_masks_the_partial_averages() {
+        let mut sma = SimpleMovingAverage::new(3).unwrap();
+        let mut values: Vec<f64> = [3.0, 6.0, 9.0, 12.0].iter().map(|&price| sma.next(price)).collect();
+        mask_warmup(&mut values, sma_warmup(3));
+        assert!(values[0].is_nan() && values[1].is_nan());
+        assert_eq!(values[2..], [6.0, 9.0]);
+    }
+}
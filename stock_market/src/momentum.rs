@@ -0,0 +1,186 @@
+use chrono::{DateTime, Utc};
+use plotters::prelude::*;
+use ta::indicators::{CommodityChannelIndex, FastStochastic, RateOfChange, SimpleMovingAverage};
+use ta::Next;
+
+use crate::bar::Bar;
+use crate::{finite_range, mask_warmup, sma_warmup, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+// Stochastic oscillator %K (position of the close in the high-low range of the last `period` bars)
+// and %D (simple moving average of %K over `smoothing` bars)
+pub fn stochastic_values(bars: &[Bar], period: usize, smoothing: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut fast_stochastic = FastStochastic::new(period).unwrap();
+    let mut percent_k: Vec<f64> = bars.iter().map(|bar| fast_stochastic.next(bar)).collect();
+    mask_warmup(&mut percent_k, sma_warmup(period));
+
+    // %D only starts once %K is valid, otherwise the warm-up values would leak into the average
+    let mut sma = SimpleMovingAverage::new(smoothing).unwrap();
+    let mut percent_d: Vec<f64> = percent_k.iter()
+        .map(|&k| if k.is_finite() { sma.next(k) } else { f64::NAN })
+        .collect();
+    mask_warmup(&mut percent_d, sma_warmup(period) + sma_warmup(smoothing));
+    (percent_k, percent_d)
+}
+
+// Williams %R is the stochastic %K shifted down to the -100..0 range
+pub fn williams_r_values(bars: &[Bar], period: usize) -> Vec<f64> {
+    let mut fast_stochastic = FastStochastic::new(period).unwrap();
+    let mut williams_r: Vec<f64> = bars.iter().map(|bar| fast_stochastic.next(bar) - 100.0).collect();
+    mask_warmup(&mut williams_r, sma_warmup(period));
+    williams_r
+}
+
+pub fn cci_values(bars: &[Bar], period: usize) -> Vec<f64> {
+    let mut cci = CommodityChannelIndex::new(period).unwrap();
+    let mut cci_values: Vec<f64> = bars.iter().map(|bar| cci.next(bar)).collect();
+    mask_warmup(&mut cci_values, sma_warmup(period));
+    cci_values
+}
+
+// Rate of change in percent against the close `period` bars earlier
+pub fn roc_values(closing_prices: &[f64], period: usize) -> Vec<f64> {
+    let mut roc = RateOfChange::new(period).unwrap();
+    let mut roc_values: Vec<f64> = closing_prices.iter().map(|&price| roc.next(price)).collect();
+    mask_warmup(&mut roc_values, period);
+    roc_values
+}
+
+// Layout of an oscillator chart drawn in the style of the RSI chart: the lines on a date axis with the
+// zone above the upper level shaded red and the zone below the lower level shaded green. Oscillators
+// without such zones get plain reference lines instead.
+pub struct OscillatorChart<'a> {
+    pub file_prefix: &'a str,
+    pub caption: &'a str,
+    pub y_desc: &'a str,
+    pub lines: Vec<(&'a str, &'a [f64], RGBColor)>,
+    // Fixed y-range for bounded oscillators, unbounded ones are scaled to their values
+    pub fixed_range: Option<(f64, f64)>,
+    // Upper and lower level
+    pub zones: Option<(f64, f64)>,
+    // Reference lines (e.g. the zero line) drawn when there are no zones
+    pub levels: &'a [f64],
+}
+
+pub fn oscillator_chart(oscillator: OscillatorChart, ticker_name: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = oscillator.file_prefix.to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let (min_value, max_value) = oscillator.fixed_range.unwrap_or_else(|| {
+        let (min_value, max_value) = finite_range(oscillator.lines.iter().flat_map(|(_, values, _)| values.iter()));
+        // Keep both levels (or the reference lines) in view
+        let levels: Vec<f64> = match oscillator.zones {
+            Some((upper_level, lower_level)) => vec![upper_level, lower_level],
+            None => oscillator.levels.to_vec(),
+        };
+        levels.iter().fold((min_value, max_value), |(min_value, max_value), &level| (min_value.min(level), max_value.max(level)))
+    });
+    let (first_time, last_time) = (times[0], times[times.len() - 1]);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(oscillator.caption.to_owned() + ticker_name, ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(first_time..last_time, min_value..max_value)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(oscillator.y_desc)
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    match oscillator.zones {
+        Some((upper_level, lower_level)) => {
+            chart.draw_series(std::iter::once(Rectangle::new(
+                [(first_time, upper_level), (last_time, max_value)],
+                RED.mix(0.1).filled(),
+            )))?
+                .label(format!("Above {}", upper_level))
+                .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], RED.mix(0.3).filled()));
+            chart.draw_series(std::iter::once(Rectangle::new(
+                [(first_time, min_value), (last_time, lower_level)],
+                GREEN.mix(0.1).filled(),
+            )))?
+                .label(format!("Below {}", lower_level))
+                .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], GREEN.mix(0.3).filled()));
+            for level in [upper_level, lower_level] {
+                chart.draw_series(LineSeries::new(vec![(first_time, level), (last_time, level)], BLACK.mix(0.4)))?;
+            }
+        }
+        None => {
+            for &level in oscillator.levels {
+                chart.draw_series(LineSeries::new(vec![(first_time, level), (last_time, level)], BLACK.mix(0.4)))?;
+            }
+        }
+    }
+
+    for (label, values, color) in &oscillator.lines {
+        let color = *color;
+        chart.draw_series(LineSeries::new(
+            times.iter().zip(values.iter()).filter(|(_, value)| value.is_finite()).map(|(&time, &value)| (time, value)),
+            &color,
+        ))?
+            .label(*label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+pub fn stochastic(percent_k: &[f64], percent_d: &[f64], ticker_name: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
+    oscillator_chart(OscillatorChart {
+        file_prefix: "stochastic_",
+        caption: "Stochastic Oscillator Chart for ",
+        y_desc: "Stochastic %",
+        lines: vec![("%K", percent_k, RED), ("%D", percent_d, BLUE)],
+        fixed_range: Some((0.0, 100.0)),
+        zones: Some((80.0, 20.0)),
+        levels: &[],
+    }, ticker_name, times)
+}
+
+pub fn williams_r(williams_r: &[f64], ticker_name: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
+    oscillator_chart(OscillatorChart {
+        file_prefix: "williams_r_",
+        caption: "Williams %R Chart for ",
+        y_desc: "Williams %R",
+        lines: vec![("Williams %R", williams_r, RED)],
+        fixed_range: Some((-100.0, 0.0)),
+        zones: Some((-20.0, -80.0)),
+        levels: &[],
+    }, ticker_name, times)
+}
+
+pub fn cci(cci_values: &[f64], ticker_name: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
+    oscillator_chart(OscillatorChart {
+        file_prefix: "cci_",
+        caption: "Commodity Channel Index Chart for ",
+        y_desc: "CCI",
+        lines: vec![("CCI", cci_values, RED)],
+        fixed_range: None,
+        zones: Some((100.0, -100.0)),
+        levels: &[],
+    }, ticker_name, times)
+}
+
+pub fn roc(roc_values: &[f64], ticker_name: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
+    oscillator_chart(OscillatorChart {
+        file_prefix: "roc_",
+        caption: "Rate of Change Chart for ",
+        y_desc: "Rate of Change (%)",
+        lines: vec![("ROC", roc_values, RED)],
+        fixed_range: None,
+        zones: None,
+        levels: &[0.0],
+    }, ticker_name, times)
+}
@@ -0,0 +1,287 @@
+use chrono::{DateTime, Utc};
+use clap::{Args, ValueEnum};
+use log::{debug, error};
+use plotters::prelude::*;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::backtest::{backtest_config, drawdowns, fetch_backtest_bars, returns, run_backtest, strategy_params, Backtest, BacktestArgs, Sizing};
+use crate::stats::{percentile, sorted};
+use crate::strategies::build_strategy;
+use crate::{axis_title, finite_range, Stock, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+#[derive(Args, Debug)]
+pub struct MonteCarloArgs {
+    #[command(flatten)]
+    pub backtest: BacktestArgs,
+    /// What every simulation draws from the backtest
+    #[arg(long, value_enum, default_value_t = Resampling::Shuffle)]
+    pub resampling: Resampling,
+    /// Number of simulated equity paths
+    #[arg(long, default_value_t = 5000)]
+    pub simulations: usize,
+    /// Seed of the random number generator, the same seed gives the same simulations
+    #[arg(long, default_value_t = 42)]
+    pub seed: u64,
+    /// Confidence level of the reported intervals in percent
+    #[arg(long, default_value_t = 95.0)]
+    pub confidence: f64,
+    /// Loss in percent of the starting capital that counts as ruin
+    #[arg(long, default_value_t = 50.0)]
+    pub ruin: f64,
+}
+
+// What the simulations draw from the backtest
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Resampling {
+    /// Shuffle the order of the trades, the final equity stays the same and only the path to it changes
+    Shuffle,
+    /// Draw as many trades as the backtest made, with replacement
+    Resample,
+    /// Draw as many daily returns as the backtest had bars, with replacement
+    Daily,
+}
+
+impl Resampling {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resampling::Shuffle => "shuffled trades",
+            Resampling::Resample => "resampled trades",
+            Resampling::Daily => "bootstrapped daily returns",
+        }
+    }
+}
+
+// Returns of the backtest's trades relative to the equity right before each one was opened. Compounding them in
+// order gives the equity after every trade.
+pub fn trade_returns(result: &Backtest) -> Vec<f64> {
+    result.trades.iter().map(|trade| {
+        let before = if trade.entry_index > result.start { result.equity[trade.entry_index - result.start - 1] } else { result.capital };
+        trade.pnl / before
+    }).collect()
+}
+
+// Equity after every step, starting with the capital. Steps are returns that compound when the positions are
+// sized by the equity, and P&L amounts that add up when they are a fixed amount of cash or shares.
+fn accumulate(capital: f64, compounding: bool, steps: impl Iterator<Item = f64>) -> Vec<f64> {
+    let mut equity = capital;
+    std::iter::once(capital).chain(steps.map(|step| {
+        if compounding {
+            equity *= 1.0 + step;
+        } else {
+            equity += step;
+        }
+        equity
+    })).collect()
+}
+
+pub struct MonteCarlo {
+    pub resampling: Resampling,
+    pub capital: f64,
+    pub seed: u64,
+    // Final equity and max drawdown of the backtest as it ran, marked at every close
+    pub final_equity: f64,
+    pub max_drawdown: f64,
+    // Step by step path of the backtest itself and one simulated path per simulation, all starting with the
+    // capital. Trade paths only see the equity between trades, not the drawdowns while a trade is open.
+    pub actual: Vec<f64>,
+    pub paths: Vec<Vec<f64>>,
+}
+
+// Runs the simulations with a ChaCha generator seeded by `seed`, so the same seed always gives the same paths,
+// also across releases of rand
+pub fn simulate(result: &Backtest, sizing: Sizing, resampling: Resampling, simulations: usize, seed: u64) -> MonteCarlo {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let compounding = sizing == Sizing::Equity;
+    let equity = [&[result.capital], result.equity.as_slice()].concat();
+    let mut samples = match (resampling, compounding) {
+        (Resampling::Daily, true) => returns(&equity),
+        (Resampling::Daily, false) => equity.windows(2).map(|pair| pair[1] - pair[0]).collect(),
+        (_, true) => trade_returns(result),
+        (_, false) => result.trades.iter().map(|trade| trade.pnl).collect(),
+    };
+    let actual = accumulate(result.capital, compounding, samples.iter().copied());
+    let paths = (0..simulations).map(|_| {
+        if resampling == Resampling::Shuffle {
+            samples.shuffle(&mut rng);
+            accumulate(result.capital, compounding, samples.iter().copied())
+        } else {
+            let drawn: Vec<f64> = (0..samples.len()).map(|_| samples[rng.random_range(0..samples.len())]).collect();
+            accumulate(result.capital, compounding, drawn.into_iter())
+        }
+    }).collect();
+    let metrics = result.metrics();
+    MonteCarlo { resampling, capital: result.capital, seed, final_equity: metrics.final_equity, max_drawdown: metrics.max_drawdown, actual, paths }
+}
+
+fn max_drawdown(path: &[f64]) -> f64 {
+    -drawdowns(path).into_iter().fold(0.0, f64::min)
+}
+
+impl MonteCarlo {
+    // Prints the confidence intervals of the final equity and the max drawdown next to the backtest's own values,
+    // and the share of the paths that fell to `1 - ruin` of the capital at some point
+    pub fn print(&self, confidence: f64, ruin: f64) {
+        let tail = (1.0 - confidence) / 2.0;
+        let final_equity = sorted(self.paths.iter().map(|path| path[path.len() - 1]));
+        let max_drawdowns = sorted(self.paths.iter().map(|path| max_drawdown(path)));
+        let ruined = self.paths.iter().filter(|path| path.iter().any(|&value| value <= self.capital * (1.0 - ruin))).count();
+        let losing = final_equity.iter().filter(|&&value| value < self.capital).count();
+
+        println!("Monte Carlo of {}: {} simulations, seed {}", self.resampling.name(), self.paths.len(), self.seed);
+        println!("  {:<14} {:>12} {:>12} {:>12} {:>12}", "", "Backtest", format!("{:.1}%", tail * 100.0), "Median", format!("{:.1}%", (1.0 - tail) * 100.0));
+        println!("  {:<14} {:>12.2} {:>12.2} {:>12.2} {:>12.2}", "Final equity", self.final_equity,
+                 percentile(&final_equity, tail), percentile(&final_equity, 0.5), percentile(&final_equity, 1.0 - tail));
+        println!("  {:<14} {:>11.2}% {:>11.2}% {:>11.2}% {:>11.2}%", "Max drawdown", self.max_drawdown * 100.0,
+                 percentile(&max_drawdowns, tail) * 100.0, percentile(&max_drawdowns, 0.5) * 100.0, percentile(&max_drawdowns, 1.0 - tail) * 100.0);
+        println!("Probability of a loss         {:.2}%", losing as f64 / self.paths.len() as f64 * 100.0);
+        println!("Probability of ruin           {:.2}% (losing {:.0}% of the capital)", ruined as f64 / self.paths.len() as f64 * 100.0, ruin * 100.0);
+    }
+}
+
+// Fan chart of the simulated equity: the confidence interval and the interquartile range of every step shaded,
+// the median path and the backtest's own path on top. Steps are trades, or bars for the daily bootstrap.
+pub fn fan_chart(
+    monte_carlo: &MonteCarlo,
+    confidence: f64,
+    ticker_name: &str,
+    currency: &str,
+    times: &[DateTime<Utc>],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "monte_carlo_".to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let steps = monte_carlo.actual.len();
+    if steps < 2 {
+        return Err("the backtest made no trades to simulate".into());
+    }
+    let tail = (1.0 - confidence) / 2.0;
+    let columns: Vec<Vec<f64>> = (0..steps).map(|step| sorted(monte_carlo.paths.iter().map(|path| path[step]))).collect();
+    let band = |fraction: f64| -> Vec<(f64, f64)> { columns.iter().enumerate().map(|(step, column)| (step as f64, percentile(column, fraction))).collect() };
+    let (lower, upper) = (band(tail), band(1.0 - tail));
+    let (min_value, max_value) = finite_range(lower.iter().chain(upper.iter()).map(|(_, value)| value).chain(monte_carlo.actual.iter()));
+
+    let daily = monte_carlo.resampling == Resampling::Daily;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Monte Carlo of {} for {} ({} simulations)", monte_carlo.resampling.name(), ticker_name, monte_carlo.paths.len()), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(0.0..(steps - 1) as f64, min_value..max_value)?;
+
+    // The first step of the daily bootstrap is the capital before the first bar
+    let x_label = |x: &f64| if daily {
+        times[(x.round() as usize).saturating_sub(1).min(times.len() - 1)].format("%Y-%m-%d").to_string()
+    } else {
+        format!("{}", x.round())
+    };
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc(if daily { "Date" } else { "Trade" })
+        .y_desc(axis_title("Equity", currency))
+        .x_label_formatter(&x_label)
+        .draw()?;
+
+    let (quartile_low, quartile_high) = (band(0.25), band(0.75));
+    for ((low, high), label, opacity) in [((&lower, &upper), format!("{:.0}% interval", confidence * 100.0), 0.15), ((&quartile_low, &quartile_high), "Interquartile range".to_owned(), 0.3)] {
+        chart.draw_series(std::iter::once(Polygon::new(high.iter().chain(low.iter().rev()).copied().collect::<Vec<(f64, f64)>>(), BLUE.mix(opacity).filled())))?
+            .label(label)
+            .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], BLUE.mix(opacity).filled()));
+    }
+    chart.draw_series(LineSeries::new(band(0.5), BLUE.stroke_width(2)))?
+        .label("Median")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE.stroke_width(2)));
+    chart.draw_series(LineSeries::new(monte_carlo.actual.iter().enumerate().map(|(step, &value)| (step as f64, value)), BLACK.stroke_width(2)))?
+        .label("Backtest")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLACK.stroke_width(2)));
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+// Backtests the strategy and runs the Monte Carlo simulations on its trades or daily returns
+pub async fn run_command(stock_args: &Stock, monte_carlo_args: &MonteCarloArgs) {
+    if let Err(e) = monte_carlo_args.backtest.check() {
+        println!("{}", e);
+        return;
+    }
+    if monte_carlo_args.simulations == 0 {
+        println!("At least one simulation is needed");
+        return;
+    }
+    if monte_carlo_args.confidence <= 0.0 || monte_carlo_args.confidence >= 100.0 || monte_carlo_args.ruin <= 0.0 || monte_carlo_args.ruin > 100.0 {
+        println!("The confidence level must be between 0 and 100 percent and the ruin level above 0 and at most 100 percent");
+        return;
+    }
+    let backtest_args = &monte_carlo_args.backtest;
+    let params = strategy_params(stock_args, backtest_args);
+    let Some((bars, start, currency)) = fetch_backtest_bars(stock_args, params.warmup(backtest_args.strategy)).await else {
+        return;
+    };
+    let config = backtest_config(backtest_args);
+    let mut strategy = build_strategy(backtest_args.strategy, &params, &bars);
+    let result = run_backtest(&bars, start, strategy.as_mut(), &config);
+    if result.trades.is_empty() && monte_carlo_args.resampling != Resampling::Daily {
+        println!("{} made no trades on {}, try --resampling daily", result.strategy_name, stock_args.name);
+        return;
+    }
+    println!("{} on {}", result.strategy_name, stock_args.name);
+
+    let (confidence, ruin) = (monte_carlo_args.confidence / 100.0, monte_carlo_args.ruin / 100.0);
+    let monte_carlo = simulate(&result, backtest_args.sizing, monte_carlo_args.resampling, monte_carlo_args.simulations, monte_carlo_args.seed);
+    monte_carlo.print(confidence, ruin);
+    match fan_chart(&monte_carlo, confidence, &stock_args.name, &currency, &result.times) {
+        Ok(_) => {debug!("Monte Carlo Chart Created Successfully")}
+        Err(e) => {
+            println!("Error while creating the Monte Carlo chart check log file for more details");
+            error!("Error while creating the Monte Carlo Chart:{:?}",e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backtest::{FillPrice, Trade};
+
+    // Three closed trades on four bars, the equity marked after each one
+    fn backtest() -> Backtest {
+        let trade = |index: usize, pnl: f64| Trade { entry_index: index, exit_index: index + 1, quantity: 1.0, entry_price: 1.0, exit_price: 1.0, pnl, open: false };
+        Backtest {
+            strategy_name: "Test".to_owned(),
+            capital: 1000.0,
+            fill: FillPrice::Close,
+            start: 0,
+            times: (0..4).map(|i| DateTime::from_timestamp(i * 86_400, 0).unwrap()).collect(),
+            equity: vec![1000.0, 1100.0, 900.0, 950.0],
+            positions: vec![0.0; 4],
+            trades: vec![trade(0, 100.0), trade(1, -200.0), trade(2, 50.0)],
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_paths() {
+        let first = simulate(&backtest(), Sizing::Equity, Resampling::Resample, 20, 7);
+        let second = simulate(&backtest(), Sizing::Equity, Resampling::Resample, 20, 7);
+        assert_eq!(first.paths, second.paths);
+        assert_ne!(first.paths, simulate(&backtest(), Sizing::Equity, Resampling::Resample, 20, 8).paths);
+    }
+
+    #[test]
+    fn fixed_size_trades_add_up() {
+        let monte_carlo = simulate(&backtest(), Sizing::Shares, Resampling::Shuffle, 50, 1);
+        assert_eq!(monte_carlo.actual, vec![1000.0, 1100.0, 900.0, 950.0]);
+        assert!(monte_carlo.paths.iter().all(|path| path[path.len() - 1] == 950.0));
+        // The backtest's own values come from its marked equity
+        assert_eq!(monte_carlo.final_equity, 950.0);
+        assert!((monte_carlo.max_drawdown - 200.0 / 1100.0).abs() < 1e-12);
+    }
+}
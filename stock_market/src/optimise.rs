@@ -0,0 +1,337 @@
+use chrono::{DateTime, Utc};
+use clap::Args;
+use log::{debug, error};
+use plotters::prelude::*;
+use plotters::style::full_palette::{GREEN_700, RED_700};
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+
+use crate::backtest::{returns, years_between};
+use crate::stats::{covariance, mean};
+use crate::portfolio;
+use crate::{finite_range, Stock, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+#[derive(Args, Debug)]
+pub struct OptimiseArgs {
+    /// Largest weight of a single ticker in percent, no cap when not given
+    #[arg(long)]
+    pub max_weight: Option<f64>,
+    /// Annual risk-free rate in percent the Sharpe ratios are measured against
+    #[arg(long, default_value_t = 0.0)]
+    pub risk_free: f64,
+}
+
+// Mean-variance optimisation of long-only portfolios. Returns and covariances are annualised with the number of
+// bars per year in the history.
+
+pub struct Estimates {
+    pub returns: Vec<f64>,
+    pub covariance: Vec<Vec<f64>>,
+    // Annual risk-free rate the Sharpe ratios are measured against
+    pub risk_free: f64,
+}
+
+// Weights of a portfolio with its expected annual return and volatility
+#[derive(Clone)]
+pub struct Allocation {
+    pub weights: Vec<f64>,
+    pub expected_return: f64,
+    pub volatility: f64,
+}
+
+pub fn estimate(closes: &[Vec<f64>], times: &[DateTime<Utc>], risk_free: f64) -> Estimates {
+    let asset_returns: Vec<Vec<f64>> = closes.iter().map(|closes| returns(closes)).collect();
+    let bars = asset_returns[0].len() as f64;
+    let bars_per_year = bars / years_between(times);
+    let covariance = asset_returns.iter()
+        .map(|a| asset_returns.iter().map(|b| covariance(a, b) * bars_per_year).collect())
+        .collect();
+    Estimates { returns: asset_returns.iter().map(|returns| mean(returns) * bars_per_year).collect(), covariance, risk_free }
+}
+
+impl Estimates {
+    fn expected_return(&self, weights: &[f64]) -> f64 {
+        weights.iter().zip(self.returns.iter()).map(|(weight, r)| weight * r).sum()
+    }
+
+    // Covariance matrix times the weights
+    fn covariance_times(&self, weights: &[f64]) -> Vec<f64> {
+        self.covariance.iter().map(|row| row.iter().zip(weights.iter()).map(|(c, w)| c * w).sum()).collect()
+    }
+
+    fn variance(&self, weights: &[f64]) -> f64 {
+        self.covariance_times(weights).iter().zip(weights.iter()).map(|(c, w)| c * w).sum()
+    }
+
+    pub fn allocation(&self, weights: Vec<f64>) -> Allocation {
+        Allocation { expected_return: self.expected_return(&weights), volatility: self.variance(&weights).sqrt(), weights }
+    }
+
+    pub fn sharpe(&self, allocation: &Allocation) -> f64 {
+        (allocation.expected_return - self.risk_free) / allocation.volatility
+    }
+}
+
+// Closest weights to `values` that are between 0 and `cap` and add up to 1: every value is shifted by the same
+// amount and clamped, the shift is found by bisection
+fn project(values: &[f64], cap: f64) -> Vec<f64> {
+    let clamped = |shift: f64| values.iter().map(move |value| (value - shift).clamp(0.0, cap));
+    let mut low = values.iter().copied().fold(f64::INFINITY, f64::min) - cap;
+    let mut high = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    for _ in 0..100 {
+        let middle = (low + high) / 2.0;
+        if clamped(middle).sum::<f64>() > 1.0 {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    clamped((low + high) / 2.0).collect()
+}
+
+// Projected gradient ascent with a step that doubles after every improvement and halves until it improves
+fn maximise(start: Vec<f64>, cap: f64, objective: impl Fn(&[f64]) -> f64, gradient: impl Fn(&[f64]) -> Vec<f64>) -> Vec<f64> {
+    let mut weights = project(&start, cap);
+    let mut value = objective(&weights);
+    let mut step = 1.0;
+    for _ in 0..10_000 {
+        let direction = gradient(&weights);
+        let (candidate, candidate_value) = loop {
+            let moved: Vec<f64> = weights.iter().zip(direction.iter()).map(|(w, d)| w + step * d).collect();
+            let candidate = project(&moved, cap);
+            let candidate_value = objective(&candidate);
+            if candidate_value > value || step < 1e-12 {
+                break (candidate, candidate_value);
+            }
+            step /= 2.0;
+        };
+        if candidate_value <= value + 1e-15 {
+            break;
+        }
+        weights = candidate;
+        value = candidate_value;
+        step *= 2.0;
+    }
+    weights
+}
+
+pub fn minimum_variance(estimates: &Estimates, cap: f64) -> Allocation {
+    let start = vec![1.0 / estimates.returns.len() as f64; estimates.returns.len()];
+    let weights = maximise(start, cap, |w| -estimates.variance(w), |w| estimates.covariance_times(w).iter().map(|c| -2.0 * c).collect());
+    estimates.allocation(weights)
+}
+
+// Efficient portfolios from the minimum variance one up to the highest return, each maximising the expected
+// return minus `aversion` / 2 times the variance for a range of risk aversions
+pub fn efficient_frontier(estimates: &Estimates, cap: f64) -> Vec<Allocation> {
+    let start = vec![1.0 / estimates.returns.len() as f64; estimates.returns.len()];
+    let mut frontier: Vec<Allocation> = (0..=60).map(|i| {
+        let aversion = 10f64.powf(4.0 - i as f64 / 10.0);
+        let weights = maximise(start.clone(), cap,
+                               |w| estimates.expected_return(w) - aversion / 2.0 * estimates.variance(w),
+                               |w| estimates.returns.iter().zip(estimates.covariance_times(w)).map(|(r, c)| r - aversion * c).collect());
+        estimates.allocation(weights)
+    }).collect();
+    frontier.push(minimum_variance(estimates, cap));
+    frontier.sort_by(|a, b| a.volatility.total_cmp(&b.volatility));
+    frontier.dedup_by(|a, b| (a.volatility - b.volatility).abs() < 1e-6);
+    frontier
+}
+
+// Portfolio with the highest Sharpe ratio, started from the best point of the frontier
+pub fn maximum_sharpe(estimates: &Estimates, cap: f64, frontier: &[Allocation]) -> Allocation {
+    let start = frontier.iter().max_by(|a, b| estimates.sharpe(a).total_cmp(&estimates.sharpe(b))).map(|allocation| allocation.weights.clone()).unwrap_or_default();
+    let sharpe = |w: &[f64]| (estimates.expected_return(w) - estimates.risk_free) / estimates.variance(w).sqrt();
+    let weights = maximise(start, cap, sharpe, |w| {
+        let (excess, volatility) = (estimates.expected_return(w) - estimates.risk_free, estimates.variance(w).sqrt());
+        estimates.returns.iter().zip(estimates.covariance_times(w)).map(|(r, c)| r / volatility - excess * c / volatility.powi(3)).collect()
+    });
+    estimates.allocation(weights)
+}
+
+// Prints the estimates of every asset and the weights of the optimal portfolios
+pub fn print(symbols: &[&str], estimates: &Estimates, portfolios: &[(&str, &Allocation)]) {
+    let names: Vec<String> = portfolios.iter().map(|(name, _)| format!("{:>18}", name)).collect();
+    println!("  {:<12} {:>16} {:>12} {}", "", "Expected return", "Volatility", names.join(""));
+    for (i, symbol) in symbols.iter().enumerate() {
+        let weights: Vec<String> = portfolios.iter().map(|(_, allocation)| format!("{:>17.2}%", allocation.weights[i] * 100.0)).collect();
+        println!("  {:<12} {:>15.2}% {:>11.2}% {}", symbol, estimates.returns[i] * 100.0, estimates.covariance[i][i].sqrt() * 100.0, weights.join(""));
+    }
+    let row = |label: &str, value: &dyn Fn(&Allocation) -> String| {
+        let values: Vec<String> = portfolios.iter().map(|(_, allocation)| format!("{:>18}", value(allocation))).collect();
+        println!("  {:<42} {}", label, values.join(""));
+    };
+    row("Expected return", &|allocation| format!("{:.2}%", allocation.expected_return * 100.0));
+    row("Volatility", &|allocation| format!("{:.2}%", allocation.volatility * 100.0));
+    row("Sharpe ratio", &|allocation| format!("{:.2}", estimates.sharpe(allocation)));
+}
+
+// Efficient frontier in volatility and expected return, with every asset and the two optimal portfolios marked
+pub fn frontier_chart(
+    symbols: &[&str],
+    estimates: &Estimates,
+    frontier: &[Allocation],
+    minimum: &Allocation,
+    sharpe: &Allocation,
+    portfolio_name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "efficient_frontier_".to_owned() + portfolio_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT * 2)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let assets: Vec<(f64, f64)> = (0..symbols.len()).map(|i| (estimates.covariance[i][i].sqrt() * 100.0, estimates.returns[i] * 100.0)).collect();
+    let points: Vec<(f64, f64)> = frontier.iter().map(|allocation| (allocation.volatility * 100.0, allocation.expected_return * 100.0)).collect();
+    let (min_x, max_x) = finite_range(assets.iter().chain(points.iter()).map(|(x, _)| x));
+    let (min_y, max_y) = finite_range(assets.iter().chain(points.iter()).map(|(_, y)| y));
+    // Some room around the outer points for their markers and labels
+    let (pad_x, pad_y) = ((max_x - min_x).max(1.0) * 0.08, (max_y - min_y).max(1.0) * 0.08);
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Efficient Frontier of {}", portfolio_name), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d((min_x - pad_x).max(0.0)..max_x + pad_x, min_y - pad_y..max_y + pad_y)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Volatility (% per year)")
+        .y_desc("Expected return (% per year)")
+        .draw()?;
+
+    chart.draw_series(LineSeries::new(points.iter().copied(), BLUE.stroke_width(2)))?
+        .label("Efficient frontier")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], BLUE.stroke_width(2)));
+    chart.draw_series(assets.iter().map(|&point| Circle::new(point, 5, BLACK.filled())))?
+        .label("Asset")
+        .legend(|(x, y)| Circle::new((x + 10, y), 5, BLACK.filled()));
+    chart.draw_series(symbols.iter().zip(assets.iter()).map(|(symbol, &point)| {
+        Text::new(symbol.to_string(), point, ("sans-serif", 15).into_font().color(&BLACK).pos(Pos::new(HPos::Left, VPos::Bottom)))
+    }))?;
+    for (allocation, label, color) in [(minimum, "Minimum variance", GREEN_700), (sharpe, "Maximum Sharpe", RED_700)] {
+        let point = (allocation.volatility * 100.0, allocation.expected_return * 100.0);
+        chart.draw_series(std::iter::once(TriangleMarker::new(point, 9, color.filled())))?
+            .label(label)
+            .legend(move |(x, y)| TriangleMarker::new((x + 10, y), 6, color.filled()));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::LowerRight)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+pub async fn run_command(stock_args: &Stock, optimise_args: &OptimiseArgs) {
+    let symbols: Vec<&str> = stock_args.name.split(',').map(str::trim).filter(|ticker| !ticker.is_empty()).collect();
+    if symbols.len() < 2 {
+        println!("Give at least two comma separated tickers to optimise");
+        return;
+    }
+    let cap = optimise_args.max_weight.unwrap_or(100.0) / 100.0;
+    if cap <= 0.0 || cap > 1.0 {
+        println!("The largest weight must be above 0% and at most 100%");
+        return;
+    }
+    if cap * (symbols.len() as f64) < 1.0 - 1e-9 {
+        println!("A largest weight of {:.1}% cannot add up to 100% over {} tickers", cap * 100.0, symbols.len());
+        return;
+    }
+    let histories = portfolio::fetch_histories(stock_args, &symbols).await;
+    if let Some(missing) = symbols.iter().find(|symbol| !histories.contains_key(**symbol)) {
+        println!("Cannot optimise without the prices of {}", missing);
+        return;
+    }
+    let (dates, closes) = portfolio::aligned_closes(&symbols, &histories);
+    if dates.len() < 3 {
+        println!("The tickers share too few trading days to estimate their returns");
+        return;
+    }
+    let times: Vec<DateTime<Utc>> = dates.iter().map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc()).collect();
+    let estimates = estimate(&closes, &times, optimise_args.risk_free / 100.0);
+    let frontier = efficient_frontier(&estimates, cap);
+    let minimum = minimum_variance(&estimates, cap);
+    let sharpe = maximum_sharpe(&estimates, cap, &frontier);
+
+    let limit = optimise_args.max_weight.map(|weight| format!(", at most {:.1}% each", weight)).unwrap_or_default();
+    println!("Long-only optimisation of {} from {} to {}{}", symbols.join(", "), dates[0], dates[dates.len() - 1], limit);
+    print(&symbols, &estimates, &[("Minimum variance", &minimum), ("Maximum Sharpe", &sharpe)]);
+
+    match frontier_chart(&symbols, &estimates, &frontier, &minimum, &sharpe, &symbols.join("_")) {
+        Ok(_) => {debug!("Efficient Frontier Chart Created Successfully")}
+        Err(e) => {
+            println!("Error while creating the efficient frontier chart check log file for more details");
+            error!("Error while creating the Efficient Frontier Chart:{:?}",e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_weights(weights: &[f64], cap: f64) {
+        assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-9, "{:?} do not add up to 1", weights);
+        assert!(weights.iter().all(|&weight| (-1e-12..=cap + 1e-12).contains(&weight)), "{:?} outside [0, {}]", weights, cap);
+    }
+
+    // Volatilities of 20% and 30% with a correlation of 0.1
+    fn two_assets(returns: [f64; 2]) -> Estimates {
+        Estimates { returns: returns.to_vec(), covariance: vec![vec![0.04, 0.006], vec![0.006, 0.09]], risk_free: 0.0 }
+    }
+
+    #[test]
+    fn projection_stays_on_the_capped_simplex() {
+        for (values, cap) in [(vec![0.2, 0.3, 0.5], 1.0), (vec![5.0, -3.0, 0.1, 0.0], 1.0), (vec![0.9, 0.05, 0.05], 0.4), (vec![-1.0, -2.0, -3.0], 0.5)] {
+            assert_weights(&project(&values, cap), cap);
+        }
+        // Weights already on the simplex are kept, the others are shifted by the same amount
+        let kept = project(&[0.2, 0.3, 0.5], 1.0);
+        assert!(kept.iter().zip([0.2, 0.3, 0.5]).all(|(a, b)| (a - b).abs() < 1e-9));
+        let shifted = project(&[1.0, 0.5, -1.0], 1.0);
+        assert!((shifted[0] - 0.75).abs() < 1e-9 && (shifted[1] - 0.25).abs() < 1e-9 && shifted[2] == 0.0);
+    }
+
+    #[test]
+    fn two_asset_minimum_variance() {
+        // (0.09 - 0.006) / (0.04 + 0.09 - 2 * 0.006) in the first asset
+        let minimum = minimum_variance(&two_assets([0.1, 0.2]), 1.0);
+        assert_weights(&minimum.weights, 1.0);
+        assert!((minimum.weights[0] - 0.084 / 0.118).abs() < 1e-6, "{:?}", minimum.weights);
+        // Capped at 60% the rest goes to the second asset
+        let capped = minimum_variance(&two_assets([0.1, 0.2]), 0.6);
+        assert_weights(&capped.weights, 0.6);
+        assert!((capped.weights[0] - 0.6).abs() < 1e-6, "{:?}", capped.weights);
+    }
+
+    #[test]
+    fn two_asset_maximum_sharpe() {
+        // Uncorrelated assets: the weights are proportional to the excess returns over the variances,
+        // 0.1 / 0.04 = 2.5 against 0.2 / 0.09 = 2.222...
+        let estimates = Estimates { covariance: vec![vec![0.04, 0.0], vec![0.0, 0.09]], ..two_assets([0.1, 0.2]) };
+        let frontier = efficient_frontier(&estimates, 1.0);
+        let sharpe = maximum_sharpe(&estimates, 1.0, &frontier);
+        assert_weights(&sharpe.weights, 1.0);
+        assert!((sharpe.weights[0] - 2.5 / (2.5 + 2.0 / 0.9)).abs() < 1e-4, "{:?}", sharpe.weights);
+        assert!(frontier.iter().all(|allocation| estimates.sharpe(allocation) <= estimates.sharpe(&sharpe) + 1e-9));
+    }
+
+    #[test]
+    fn frontier_is_capped_and_rises_with_the_volatility() {
+        let estimates = Estimates {
+            returns: vec![0.05, 0.1, 0.15, 0.2],
+            covariance: vec![vec![0.01, 0.0, 0.0, 0.0], vec![0.0, 0.04, 0.01, 0.0], vec![0.0, 0.01, 0.09, 0.0], vec![0.0, 0.0, 0.0, 0.16]],
+            risk_free: 0.0,
+        };
+        let frontier = efficient_frontier(&estimates, 0.4);
+        assert!(frontier.len() > 2);
+        for allocation in &frontier {
+            assert_weights(&allocation.weights, 0.4);
+        }
+        assert!(frontier.windows(2).all(|pair| pair[1].volatility > pair[0].volatility && pair[1].expected_return >= pair[0].expected_return - 1e-9));
+        // The highest return with at most 40% each is 40% of the best two and 20% of the third
+        assert!((frontier[frontier.len() - 1].expected_return - (0.4 * 0.2 + 0.4 * 0.15 + 0.2 * 0.1)).abs() < 1e-6);
+    }
+}
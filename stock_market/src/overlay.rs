@@ -0,0 +1,136 @@
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use plotters::coord::types::RangedCoordf64;
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+
+// Indicators that can be drawn over the price chart with --overlay
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Overlay {
+    /// Bollinger Bands (20, 2)
+    Bb,
+    /// Fast/Slow Simple Moving Averages (20, 50)
+    Sma,
+    /// Fast/Slow Exponential Moving Averages (20, 50)
+    Ema,
+    /// Keltner Channel (--keltner-period, --keltner-multiplier)
+    Keltner,
+    /// Donchian Channel (--donchian-period)
+    Donchian,
+    /// ATR stop bands (--atr-stop-period, --atr-stop-multiplier)
+    AtrStops,
+}
+
+// A series drawn on top of the price chart. Values line up with the chart's times, NaN values are skipped.
+#[derive(Clone)]
+pub enum PriceOverlay {
+    // A single line such as a moving average
+    Line { label: String, values: Vec<f64>, color: RGBColor },
+    // A channel with the area between `lower` and `upper` shaded, `middle` is drawn as a line when present
+    Band { label: String, upper: Vec<f64>, middle: Option<Vec<f64>>, lower: Vec<f64>, color: RGBColor },
+    // One dot per bar, e.g. the Parabolic SAR stops
+    Dots { label: String, values: Vec<f64>, color: RGBColor },
+    // Small triangles next to single bars, pointing up below the price or down above it
+    Triangles { label: String, values: Vec<f64>, pointing_up: bool, color: RGBColor },
+    // A horizontal price level across the whole chart, named by a text label on the line instead of the legend
+    Level { label: String, value: f64, color: RGBColor },
+}
+
+impl PriceOverlay {
+    // Every value of the overlay, used to widen the y-axis so the overlay fits on the chart
+    pub fn values(&self) -> Vec<f64> {
+        match self {
+            PriceOverlay::Line { values, .. } | PriceOverlay::Dots { values, .. } | PriceOverlay::Triangles { values, .. } => values.clone(),
+            PriceOverlay::Band { upper, lower, .. } => upper.iter().chain(lower.iter()).cloned().collect(),
+            PriceOverlay::Level { value, .. } => vec![*value],
+        }
+    }
+}
+
+pub type DateChart<'a, DB> = ChartContext<'a, DB, Cartesian2d<RangedDateTime<DateTime<Utc>>, RangedCoordf64>>;
+
+// Draws the overlays onto a price chart and registers each of them in the chart legend
+pub fn draw_price_overlays<'a, DB: DrawingBackend + 'a>(
+    chart: &mut DateChart<'a, DB>,
+    overlays: &[PriceOverlay],
+    times: &[DateTime<Utc>],
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    for overlay in overlays {
+        match overlay {
+            PriceOverlay::Line { label, values, color } => {
+                let color = *color;
+                chart.draw_series(LineSeries::new(
+                    times.iter().zip(values.iter()).filter(|(_, value)| value.is_finite()).map(|(&time, &value)| (time, value)),
+                    color.stroke_width(2),
+                ))?
+                    .label(label.as_str())
+                    .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+            }
+            PriceOverlay::Band { label, upper, middle, lower, color } => {
+                let color = *color;
+                // Shade the band with a polygon running along the upper line and back along the lower line
+                let valid: Vec<usize> = (0..times.len())
+                    .filter(|&i| upper[i].is_finite() && lower[i].is_finite())
+                    .collect();
+                let outline: Vec<(DateTime<Utc>, f64)> = valid.iter().map(|&i| (times[i], upper[i]))
+                    .chain(valid.iter().rev().map(|&i| (times[i], lower[i])))
+                    .collect();
+                chart.draw_series(std::iter::once(Polygon::new(outline, color.mix(0.15).filled())))?
+                    .label(label.as_str())
+                    .legend(move |(x, y)| Rectangle::new([(x, y - 5), (x + 20, y + 5)], color.mix(0.3).filled()));
+
+                for line in [Some(upper), middle.as_ref(), Some(lower)].into_iter().flatten() {
+                    chart.draw_series(LineSeries::new(
+                        times.iter().zip(line.iter()).filter(|(_, value)| value.is_finite()).map(|(&time, &value)| (time, value)),
+                        color,
+                    ))?;
+                }
+            }
+            PriceOverlay::Dots { label, values, color } => {
+                let color = *color;
+                chart.draw_series(
+                    times.iter().zip(values.iter()).filter(|(_, value)| value.is_finite())
+                        .map(|(&time, &value)| Circle::new((time, value), 2, color.filled())),
+                )?
+                    .label(label.as_str())
+                    .legend(move |(x, y)| Circle::new((x + 10, y), 3, color.filled()));
+            }
+            PriceOverlay::Triangles { label, values, pointing_up, color } => {
+                let color = *color;
+                // Pixel offsets from the value, the y axis of the backend points down
+                let triangle: Vec<(i32, i32)> = if *pointing_up {
+                    vec![(0, 4), (-5, 12), (5, 12)]
+                } else {
+                    vec![(0, -4), (-5, -12), (5, -12)]
+                };
+                let legend_triangle = triangle.clone();
+                chart.draw_series(
+                    times.iter().zip(values.iter()).filter(|(_, value)| value.is_finite())
+                        .map(|(&time, &value)| EmptyElement::at((time, value)) + Polygon::new(triangle.clone(), color.filled())),
+                )?
+                    .label(label.as_str())
+                    .legend(move |(x, y)| {
+                        // Centre the triangle on the legend row
+                        let offset = if legend_triangle[0].1 > 0 { -8 } else { 8 };
+                        Polygon::new(legend_triangle.iter().map(|&(dx, dy)| (x + 10 + dx, y + dy + offset)).collect::<Vec<_>>(), color.filled())
+                    });
+            }
+            PriceOverlay::Level { label, value, color } => {
+                let (first_time, last_time) = (times[0], times[times.len() - 1]);
+                chart.draw_series(DashedLineSeries::new(vec![(first_time, *value), (last_time, *value)], 8, 4, color.mix(0.8).into()))?;
+                // Labels go at the right end where they least cover the price line's early history
+                chart.draw_series(std::iter::once(
+                    Text::new(
+                        format!("{} {:.2}", label, value),
+                        (last_time, *value),
+                        ("sans-serif", 12).into_font().color(color).pos(Pos::new(HPos::Right, VPos::Bottom)),
+                    ),
+                ))?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,215 @@
+use crate::bar::Bar;
+
+// Bars looked back to decide whether a pattern appears after a rise or a fall
+const TREND_BARS: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bias {
+    Bullish,
+    Bearish,
+    Neutral,
+}
+
+impl Bias {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Bias::Bullish => "bullish",
+            Bias::Bearish => "bearish",
+            Bias::Neutral => "neutral",
+        }
+    }
+}
+
+// A candlestick pattern completed on the bar at `index`
+#[derive(Clone, Copy, Debug)]
+pub struct Pattern {
+    pub index: usize,
+    pub name: &'static str,
+    pub bias: Bias,
+}
+
+fn body(bar: &Bar) -> f64 {
+    (bar.close - bar.open).abs()
+}
+
+fn range(bar: &Bar) -> f64 {
+    bar.high - bar.low
+}
+
+fn upper_shadow(bar: &Bar) -> f64 {
+    bar.high - bar.open.max(bar.close)
+}
+
+fn lower_shadow(bar: &Bar) -> f64 {
+    bar.open.min(bar.close) - bar.low
+}
+
+fn is_bullish(bar: &Bar) -> bool {
+    bar.close > bar.open
+}
+
+fn is_bearish(bar: &Bar) -> bool {
+    bar.close < bar.open
+}
+
+// A body that makes up most of the bar's range
+fn is_long(bar: &Bar) -> bool {
+    body(bar) > 0.6 * range(bar)
+}
+
+fn body_midpoint(bar: &Bar) -> f64 {
+    (bar.open + bar.close) / 2.0
+}
+
+// Direction of the closes leading up to (but not including) the bar at `index`
+fn prior_trend(bars: &[Bar], index: usize) -> Option<Bias> {
+    if index < TREND_BARS + 1 {
+        return None;
+    }
+    let (start, end) = (bars[index - 1 - TREND_BARS].close, bars[index - 1].close);
+    if end > start {
+        Some(Bias::Bullish)
+    } else if end < start {
+        Some(Bias::Bearish)
+    } else {
+        None
+    }
+}
+
+// Patterns completed on the bar at `index`, only using that bar and the ones before it
+pub fn patterns_at(bars: &[Bar], index: usize) -> Vec<Pattern> {
+    let mut patterns = Vec::new();
+    let mut found = |name: &'static str, bias: Bias| patterns.push(Pattern { index, name, bias });
+    let bar = &bars[index];
+    if range(bar) <= 0.0 {
+        return Vec::new();
+    }
+    let trend = prior_trend(bars, index);
+
+    // Single bar patterns
+    if body(bar) <= 0.1 * range(bar) {
+        found("doji", Bias::Neutral);
+    } else if lower_shadow(bar) >= 2.0 * body(bar) && upper_shadow(bar) <= 0.5 * body(bar) {
+        match trend {
+            Some(Bias::Bearish) => found("hammer", Bias::Bullish),
+            Some(Bias::Bullish) => found("hanging man", Bias::Bearish),
+            _ => {}
+        }
+    } else if upper_shadow(bar) >= 2.0 * body(bar) && lower_shadow(bar) <= 0.5 * body(bar) {
+        match trend {
+            Some(Bias::Bearish) => found("inverted hammer", Bias::Bullish),
+            Some(Bias::Bullish) => found("shooting star", Bias::Bearish),
+            _ => {}
+        }
+    }
+
+    // Two bar patterns
+    if index >= 1 {
+        let previous = &bars[index - 1];
+        if is_bearish(previous) && is_bullish(bar) && bar.open <= previous.close && bar.close >= previous.open && body(bar) > body(previous) {
+            found("bullish engulfing", Bias::Bullish);
+        }
+        if is_bullish(previous) && is_bearish(bar) && bar.open >= previous.close && bar.close <= previous.open && body(bar) > body(previous) {
+            found("bearish engulfing", Bias::Bearish);
+        }
+        if is_long(previous) && is_bearish(previous) && is_bullish(bar) && bar.open > previous.close && bar.close < previous.open {
+            found("bullish harami", Bias::Bullish);
+        }
+        if is_long(previous) && is_bullish(previous) && is_bearish(bar) && bar.open < previous.close && bar.close > previous.open {
+            found("bearish harami", Bias::Bearish);
+        }
+        if is_long(previous) && is_bearish(previous) && is_bullish(bar) && bar.open < previous.low
+            && bar.close > body_midpoint(previous) && bar.close < previous.open {
+            found("piercing line", Bias::Bullish);
+        }
+        if is_long(previous) && is_bullish(previous) && is_bearish(bar) && bar.open > previous.high
+            && bar.close < body_midpoint(previous) && bar.close > previous.open {
+            found("dark cloud cover", Bias::Bearish);
+        }
+    }
+
+    // Three bar patterns
+    if index >= 2 {
+        let (first, middle) = (&bars[index - 2], &bars[index - 1]);
+        let small_middle = body(middle) < 0.3 * body(first);
+        if is_long(first) && is_bearish(first) && small_middle && middle.open.max(middle.close) < first.close
+            && is_bullish(bar) && bar.close > body_midpoint(first) {
+            found("morning star", Bias::Bullish);
+        }
+        if is_long(first) && is_bullish(first) && small_middle && middle.open.min(middle.close) > first.close
+            && is_bearish(bar) && bar.close < body_midpoint(first) {
+            found("evening star", Bias::Bearish);
+        }
+
+        let three = [first, middle, bar];
+        // Each candle opens inside the previous body and closes near its high (low) beyond the previous close
+        let soldiers = three.iter().all(|bar| is_bullish(bar) && upper_shadow(bar) < 0.3 * body(bar))
+            && three.windows(2).all(|pair| pair[1].open > pair[0].open && pair[1].open < pair[0].close && pair[1].close > pair[0].close);
+        if soldiers {
+            found("three white soldiers", Bias::Bullish);
+        }
+        let crows = three.iter().all(|bar| is_bearish(bar) && lower_shadow(bar) < 0.3 * body(bar))
+            && three.windows(2).all(|pair| pair[1].open < pair[0].open && pair[1].open > pair[0].close && pair[1].close < pair[0].close);
+        if crows {
+            found("three black crows", Bias::Bearish);
+        }
+    }
+    patterns
+}
+
+pub fn candlestick_patterns(bars: &[Bar]) -> Vec<Pattern> {
+    (0..bars.len()).flat_map(|index| patterns_at(bars, index)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle(index: u64, open: f64, high: f64, low: f64, close: f64) -> Bar {
+        Bar::new(index * 86_400, open, high, low, close, 0.0)
+    }
+
+    fn names(patterns: &[Pattern]) -> Vec<&'static str> {
+        patterns.iter().map(|pattern| pattern.name).collect()
+    }
+
+    // Six bars closing one lower (or higher) each day, each with a body of 0.5
+    fn trend(falling: bool) -> Vec<Bar> {
+        (0..6).map(|i| {
+            let close = if falling { 20.0 - i as f64 } else { 10.0 + i as f64 };
+            let open = if falling { close + 0.5 } else { close - 0.5 };
+            candle(i, open, open.max(close) + 0.1, open.min(close) - 0.1, close)
+        }).collect()
+    }
+
+    #[test]
+    fn engulfing_pairs() {
+        let bullish = [candle(0, 10.0, 10.2, 8.8, 9.0), candle(1, 8.9, 10.4, 8.8, 10.3)];
+        assert_eq!(names(&patterns_at(&bullish, 1)), vec!["bullish engulfing"]);
+        assert_eq!(patterns_at(&bullish, 1)[0].bias, Bias::Bullish);
+        let bearish = [candle(0, 9.0, 10.2, 8.8, 10.0), candle(1, 10.1, 10.2, 8.6, 8.7)];
+        assert_eq!(names(&patterns_at(&bearish, 1)), vec!["bearish engulfing"]);
+    }
+
+    #[test]
+    fn hammer_shape_depends_on_the_prior_trend() {
+        // Small body at the top of the range with a long lower shadow
+        let mut falling = trend(true);
+        falling.push(candle(6, 14.8, 15.05, 14.0, 15.0));
+        assert_eq!(names(&patterns_at(&falling, 6)), vec!["hammer"]);
+        let mut rising = trend(false);
+        rising.push(candle(6, 15.8, 16.05, 15.0, 16.0));
+        assert_eq!(names(&patterns_at(&rising, 6)), vec!["hanging man"]);
+        // Without enough bars before it there is no trend and no pattern
+        assert!(patterns_at(&falling[5..], 1).is_empty());
+    }
+
+    #[test]
+    fn doji_and_flat_bars() {
+        let doji = [candle(0, 10.0, 11.0, 9.0, 10.05)];
+        assert_eq!(names(&patterns_at(&doji, 0)), vec!["doji"]);
+        assert_eq!(patterns_at(&doji, 0)[0].bias, Bias::Neutral);
+        let flat = [candle(0, 10.0, 10.0, 10.0, 10.0)];
+        assert!(patterns_at(&flat, 0).is_empty());
+    }
+}
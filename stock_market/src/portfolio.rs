@@ -0,0 +1,450 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use log::{debug, error};
+use plotters::prelude::*;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::bar::Bar;
+use crate::{axis_title, fetch_stock, finite_range, interval_seconds, Stock, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+// One lot of a portfolio, the same symbol can be bought in several lots
+#[derive(Clone, Debug)]
+pub struct Holding {
+    pub symbol: String,
+    pub quantity: f64,
+    // Price paid per share
+    pub cost_basis: f64,
+    pub purchase_date: NaiveDate,
+}
+
+// A [[holding]] table of the TOML file. The purchase date may be a TOML date or a string
+#[derive(Deserialize)]
+struct TomlHolding {
+    symbol: String,
+    quantity: f64,
+    cost_basis: f64,
+    purchase_date: toml::Value,
+}
+
+#[derive(Deserialize)]
+struct TomlPortfolio {
+    holding: Vec<TomlHolding>,
+}
+
+fn parse_date(text: &str) -> Result<NaiveDate, Box<dyn std::error::Error>> {
+    NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d").map_err(|e| format!("invalid purchase date {:?}: {}", text, e).into())
+}
+
+// Reads the holdings from a .toml file with one [[holding]] table per lot or from a .csv file with the header
+// symbol,quantity,cost_basis,purchase_date
+pub fn read_holdings(path: &Path) -> Result<Vec<Holding>, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(path)?;
+    let holdings = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => {
+            let portfolio: TomlPortfolio = toml::from_str(&text)?;
+            portfolio.holding.into_iter().map(|holding| {
+                let purchase_date = match &holding.purchase_date {
+                    toml::Value::String(date) => parse_date(date)?,
+                    toml::Value::Datetime(date) => parse_date(&date.to_string())?,
+                    other => return Err(format!("invalid purchase date {} of {}", other, holding.symbol).into()),
+                };
+                Ok(Holding { symbol: holding.symbol, quantity: holding.quantity, cost_basis: holding.cost_basis, purchase_date })
+            }).collect::<Result<Vec<Holding>, Box<dyn std::error::Error>>>()?
+        }
+        Some("csv") => {
+            let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+            let header: Vec<&str> = lines.next().unwrap_or_default().split(',').map(str::trim).collect();
+            if header != ["symbol", "quantity", "cost_basis", "purchase_date"] {
+                return Err("the CSV header must be symbol,quantity,cost_basis,purchase_date".into());
+            }
+            lines.map(|line| {
+                let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+                let [symbol, quantity, cost_basis, purchase_date] = fields[..] else {
+                    return Err(format!("expected 4 fields in {:?}", line).into());
+                };
+                Ok(Holding {
+                    symbol: symbol.to_owned(),
+                    quantity: quantity.parse().map_err(|e| format!("invalid quantity {:?}: {}", quantity, e))?,
+                    cost_basis: cost_basis.parse().map_err(|e| format!("invalid cost basis {:?}: {}", cost_basis, e))?,
+                    purchase_date: parse_date(purchase_date)?,
+                })
+            }).collect::<Result<Vec<Holding>, Box<dyn std::error::Error>>>()?
+        }
+        _ => return Err("the portfolio file must be a .toml or a .csv file".into()),
+    };
+    if holdings.is_empty() {
+        return Err("the portfolio file has no holdings".into());
+    }
+    Ok(holdings)
+}
+
+// Price history of one symbol
+pub struct History {
+    pub bars: Vec<Bar>,
+    pub currency: String,
+}
+
+impl History {
+    // Reads a history written by `write`
+    pub fn read(path: &Path) -> Result<History, Box<dyn std::error::Error>> {
+        let document: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        let currency = document["currency"].as_str().unwrap_or_default().to_owned();
+        let bars = document["bars"].as_array().ok_or("no bars in the cached prices")?.iter().map(|bar| {
+            // Missing prices were written as null
+            let field = |i: usize| bar[i].as_f64().unwrap_or(f64::NAN);
+            Bar::new(bar[0].as_u64().unwrap_or_default(), field(1), field(2), field(3), field(4), field(5))
+        }).collect();
+        Ok(History { bars, currency })
+    }
+
+    // Writes the bars as [timestamp, open, high, low, close, volume] arrays
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let value = |price: f64| if price.is_finite() { json!(price) } else { Value::Null };
+        let bars: Vec<Value> = self.bars.iter()
+            .map(|bar| json!([bar.timestamp, value(bar.open), value(bar.high), value(bar.low), value(bar.close), value(bar.volume)]))
+            .collect();
+        std::fs::write(path, serde_json::to_string(&json!({ "currency": self.currency, "bars": bars }))?)?;
+        Ok(())
+    }
+}
+
+// A holding valued at the last close of its symbol
+pub struct Position {
+    pub holding: Holding,
+    pub currency: String,
+    pub price: f64,
+    // Last close of the day before the last close, the bar before it when the bars are longer than a day
+    pub previous_close: f64,
+}
+
+impl Position {
+    pub fn value(&self) -> f64 {
+        self.holding.quantity * self.price
+    }
+
+    pub fn cost(&self) -> f64 {
+        self.holding.quantity * self.holding.cost_basis
+    }
+
+    pub fn pnl(&self) -> f64 {
+        self.value() - self.cost()
+    }
+
+    // Change of the value since the previous day's close
+    pub fn day_change(&self) -> f64 {
+        self.holding.quantity * (self.price - self.previous_close)
+    }
+}
+
+pub struct Portfolio {
+    pub positions: Vec<Position>,
+    // Value and cost basis of the holdings bought by then, one point per day on which any symbol traded
+    pub dates: Vec<NaiveDate>,
+    pub values: Vec<f64>,
+    pub costs: Vec<f64>,
+}
+
+// Closes of a symbol by day, the last bar of the day when the interval is shorter
+fn daily_closes(bars: &[Bar]) -> BTreeMap<NaiveDate, f64> {
+    bars.iter().filter(|bar| bar.close.is_finite()).map(|bar| (bar.time().date_naive(), bar.close)).collect()
+}
+
+// Closes of the symbols on the days all of them traded, one vector per symbol in the order of `symbols`
+pub fn aligned_closes(symbols: &[&str], histories: &HashMap<String, History>) -> (Vec<NaiveDate>, Vec<Vec<f64>>) {
+    let closes: Vec<BTreeMap<NaiveDate, f64>> = symbols.iter().map(|symbol| histories.get(*symbol).map(|history| daily_closes(&history.bars)).unwrap_or_default()).collect();
+    let dates: Vec<NaiveDate> = match closes.first() {
+        Some(first) => first.keys().copied().filter(|date| closes.iter().all(|closes| closes.contains_key(date))).collect(),
+        None => Vec::new(),
+    };
+    let aligned = closes.iter().map(|closes| dates.iter().map(|date| closes[date]).collect()).collect();
+    (dates, aligned)
+}
+
+impl Portfolio {
+    // Values the holdings whose symbol has a price history. Before its purchase date a holding does not count
+    // towards the portfolio value, on days a symbol did not trade its previous close is used.
+    pub fn new(holdings: &[Holding], histories: &HashMap<String, History>) -> Portfolio {
+        let positions: Vec<Position> = holdings.iter().filter_map(|holding| {
+            let history = histories.get(&holding.symbol)?;
+            // One close per day, so intraday bars compare with the previous day and not the previous bar
+            let closes: Vec<f64> = daily_closes(&history.bars).into_values().collect();
+            let price = *closes.last()?;
+            let previous_close = closes.len().checked_sub(2).map_or(price, |i| closes[i]);
+            Some(Position { holding: holding.clone(), currency: history.currency.clone(), price, previous_close })
+        }).collect();
+
+        let closes: HashMap<&str, BTreeMap<NaiveDate, f64>> = histories.iter().map(|(symbol, history)| (symbol.as_str(), daily_closes(&history.bars))).collect();
+        let mut dates: Vec<NaiveDate> = closes.values().flat_map(|closes| closes.keys().copied()).collect();
+        dates.sort();
+        dates.dedup();
+        let (mut values, mut costs) = (Vec::with_capacity(dates.len()), Vec::with_capacity(dates.len()));
+        for date in &dates {
+            let (mut value, mut cost) = (0.0, 0.0);
+            for position in positions.iter().filter(|position| position.holding.purchase_date <= *date) {
+                if let Some((_, close)) = closes[position.holding.symbol.as_str()].range(..=date).next_back() {
+                    value += position.holding.quantity * close;
+                    cost += position.cost();
+                }
+            }
+            values.push(value);
+            costs.push(cost);
+        }
+        Portfolio { positions, dates, values, costs }
+    }
+
+    pub fn value(&self) -> f64 {
+        self.positions.iter().map(Position::value).sum()
+    }
+
+    // Market value of every symbol, largest first, lots of the same symbol added up
+    pub fn allocation(&self) -> Vec<(String, f64)> {
+        let mut allocation: Vec<(String, f64)> = Vec::new();
+        for position in &self.positions {
+            match allocation.iter_mut().find(|(symbol, _)| *symbol == position.holding.symbol) {
+                Some((_, value)) => *value += position.value(),
+                None => allocation.push((position.holding.symbol.clone(), position.value())),
+            }
+        }
+        allocation.sort_by(|a, b| b.1.total_cmp(&a.1));
+        allocation
+    }
+
+    // Currencies of the positions, sorted and without duplicates. Values in different currencies cannot be
+    // added up without exchange rates, so the totals and weights need a single one.
+    pub fn currencies(&self) -> Vec<&str> {
+        let mut currencies: Vec<&str> = self.positions.iter().map(|position| position.currency.as_str()).collect();
+        currencies.sort();
+        currencies.dedup();
+        currencies
+    }
+
+    // Prints every lot and the totals, `change_label` heads the change since the previous close
+    pub fn print(&self, change_label: &str) {
+        let total = self.value();
+        // Gifted or spun-off lots have no cost basis to take a percentage of
+        let change_percent = |change: f64, base: f64| if base == 0.0 {
+            format!("{:+.2}", change)
+        } else {
+            format!("{:+.2} ({:+.2}%)", change, change / base * 100.0)
+        };
+        println!("  {:<10} {:<10} {:>10} {:>11} {:>11} {:>13} {:>8} {:>22} {:>24}",
+                 "Symbol", "Purchased", "Quantity", "Cost basis", "Price", "Value", "Weight", change_label, "Unrealised P&L");
+        for position in &self.positions {
+            let holding = &position.holding;
+            println!("  {:<10} {:<10} {:>10.2} {:>11.2} {:>11.2} {:>9.2} {:<3} {:>7.2}% {:>22} {:>24}",
+                     holding.symbol, holding.purchase_date, holding.quantity, holding.cost_basis, position.price, position.value(), position.currency,
+                     position.value() / total * 100.0, change_percent(position.day_change(), position.value() - position.day_change()),
+                     change_percent(position.pnl(), position.cost()));
+        }
+        let day_change: f64 = self.positions.iter().map(Position::day_change).sum();
+        let cost: f64 = self.positions.iter().map(Position::cost).sum();
+        println!("  {:<10} {:<10} {:>10} {:>11} {:>11} {:>9.2} {:<3} {:>7.2}% {:>22} {:>24}", "Total", "", "", "", "", total, "", 100.0,
+                 change_percent(day_change, total - day_change), change_percent(total - cost, cost));
+    }
+}
+
+// Allocation pie chart, one slice per symbol
+pub fn allocation_chart(portfolio: &Portfolio, portfolio_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "portfolio_allocation_".to_owned() + portfolio_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let root = root.titled(&format!("Allocation of {}", portfolio_name), ("sans-serif", 30).into_font())?;
+    let allocation = portfolio.allocation();
+    let total = portfolio.value();
+    let sizes: Vec<f64> = allocation.iter().map(|(_, value)| *value).collect();
+    let labels: Vec<String> = allocation.iter().map(|(symbol, value)| format!("{} {:.1}%", symbol, value / total * 100.0)).collect();
+    let colors: Vec<RGBColor> = (0..allocation.len()).map(|i| {
+        let (r, g, b) = Palette99::pick(i).rgb();
+        RGBColor(r, g, b)
+    }).collect();
+
+    let (width, height) = root.dim_in_pixel();
+    let center = (width as i32 / 2, height as i32 / 2);
+    let radius = height as f64 * 0.38;
+    let mut pie = Pie::new(&center, &radius, &sizes, &colors, &labels);
+    pie.start_angle(-90.0);
+    pie.label_style(("sans-serif", 18).into_font().color(&BLACK));
+    pie.label_offset(radius * 0.15);
+    root.draw(&pie)?;
+
+    root.present()?;
+    Ok(())
+}
+
+// Portfolio value over time against the cost basis of the holdings bought by then
+pub fn value_chart(portfolio: &Portfolio, portfolio_name: &str, currency: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "portfolio_value_".to_owned() + portfolio_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    if portfolio.dates.len() < 2 {
+        return Err("not enough prices to draw the portfolio value".into());
+    }
+    let times: Vec<DateTime<Utc>> = portfolio.dates.iter().map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc()).collect();
+    let (min_value, max_value) = finite_range(portfolio.values.iter().chain(portfolio.costs.iter()));
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Value of {}", portfolio_name), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(times[0]..times[times.len() - 1], min_value..max_value)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Value", currency))
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    for (values, label, color) in [(&portfolio.costs, "Cost basis", BLACK), (&portfolio.values, "Market value", BLUE)] {
+        chart.draw_series(LineSeries::new(
+            times.iter().zip(values.iter()).map(|(&time, &value)| (time, value)),
+            color.stroke_width(2),
+        ))?
+            .label(label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+// Fetches the price history of every symbol once, symbols that could not be fetched are reported and left out.
+// With --cache the prices are read from <cache>/<symbol>_<interval>_<range>.json when that file is at most
+// --cache-max-age hours old and written to it after fetching them. Older cached prices are used when fetching
+// fails, so later runs also work offline.
+pub async fn fetch_histories(stock_args: &Stock, symbols: &[&str]) -> HashMap<String, History> {
+    let mut histories = HashMap::new();
+    if let Some(cache) = &stock_args.cache {
+        if let Err(e) = std::fs::create_dir_all(cache) {
+            error!("Error while creating the cache directory {}:{:?}", cache.display(), e)
+        }
+    }
+    for symbol in symbols {
+        if histories.contains_key(*symbol) {
+            continue;
+        }
+        let cache_file = stock_args.cache.as_ref().map(|cache| cache.join(format!("{}_{}_{}.json", symbol, stock_args.interval, stock_args.range)));
+        // Hours since the cached prices were written, None when nothing is cached
+        let cache_age = cache_file.as_ref()
+            .and_then(|cache_file| std::fs::metadata(cache_file).and_then(|metadata| metadata.modified()).ok())
+            .map(|modified| modified.elapsed().unwrap_or_default().as_secs_f64() / 3600.0);
+        if let (Some(cache_file), Some(age)) = (&cache_file, cache_age) {
+            if age <= stock_args.cache_max_age {
+                if let Some(history) = read_cached_history(cache_file) {
+                    println!("Using the prices of {} cached {:.1} hours ago", symbol, age);
+                    histories.insert(symbol.to_string(), history);
+                    continue;
+                }
+            }
+        }
+        let response = fetch_stock(symbol, &stock_args.interval, &stock_args.range).await;
+        let currency = response.as_ref().ok().and_then(|data| data.metadata().ok()).and_then(|metadata| metadata.currency).unwrap_or_default();
+        match response.and_then(|data| data.quotes()) {
+            Ok(quotes) if !quotes.is_empty() => {
+                let history = History { bars: quotes.iter().map(Bar::from_quote).collect(), currency };
+                if let Some(cache_file) = &cache_file {
+                    if let Err(e) = history.write(cache_file) {
+                        println!("Error while caching the prices of {} check log file for more details", symbol);
+                        error!("Error while writing the cached prices {}:{:?}", cache_file.display(), e)
+                    }
+                }
+                histories.insert(symbol.to_string(), history);
+            }
+            Ok(_) => println!("No stock prices found for {}", symbol),
+            Err(e) => {
+                error!("Yahoo finance errored out while fetching the stock prices of {}:{}", symbol, e);
+                match (cache_file.as_deref().and_then(read_cached_history), cache_age) {
+                    (Some(history), Some(age)) => {
+                        println!("Could not fetch {}, using its stale prices cached {:.1} hours ago", symbol, age);
+                        histories.insert(symbol.to_string(), history);
+                    }
+                    _ => println!("Error while fetching {} check log file for more details", symbol),
+                }
+            }
+        }
+    }
+    histories
+}
+
+fn read_cached_history(cache_file: &Path) -> Option<History> {
+    match History::read(cache_file) {
+        Ok(history) => Some(history),
+        Err(e) => {
+            error!("Error while reading the cached prices {}:{:?}", cache_file.display(), e);
+            None
+        }
+    }
+}
+
+// Values the holdings of the portfolio file at their last close and draws the allocation and the value over time
+pub async fn run_command(stock_args: &Stock, portfolio_file: &Path) {
+    let Some(holdings) = read_portfolio(portfolio_file) else {
+        return;
+    };
+    let symbols: Vec<&str> = holdings.iter().map(|holding| holding.symbol.as_str()).collect();
+    let histories = fetch_histories(stock_args, &symbols).await;
+    let Some(portfolio) = value_portfolio(&holdings, &histories) else {
+        return;
+    };
+    let portfolio_name = portfolio_name(portfolio_file);
+    let as_of = histories.values().filter_map(|history| history.bars.last()).map(Bar::time).max();
+    println!("Portfolio {} as of {}", portfolio_name, as_of.map(|time| time.format("%Y-%m-%d").to_string()).unwrap_or_default());
+    // Bars longer than a day have no close of the previous day, the change is then since the previous bar
+    portfolio.print(if interval_seconds(&stock_args.interval) > 86_400 { "Change since last bar" } else { "Day change" });
+
+    let currency = portfolio.currencies()[0];
+    let charts = [
+        ("Allocation Chart", allocation_chart(&portfolio, &portfolio_name)),
+        ("Value Chart", value_chart(&portfolio, &portfolio_name, currency)),
+    ];
+    for (chart_name, chart) in charts {
+        match chart {
+            Ok(_) => {debug!("Portfolio {} Created Successfully", chart_name)}
+            Err(e) => {
+                println!("Error while creating the portfolio {} check log file for more details", chart_name.to_lowercase());
+                error!("Error while creating the Portfolio {}:{:?}", chart_name, e)
+            }
+        }
+    }
+}
+
+// Values the holdings, refusing portfolios whose holdings are priced in different currencies
+pub fn value_portfolio(holdings: &[Holding], histories: &HashMap<String, History>) -> Option<Portfolio> {
+    let portfolio = Portfolio::new(holdings, histories);
+    if portfolio.positions.is_empty() {
+        println!("None of the holdings could be valued");
+        return None;
+    }
+    let currencies = portfolio.currencies();
+    if currencies.len() > 1 {
+        println!("The holdings are priced in {}, values in different currencies cannot be added up", currencies.join(", "));
+        return None;
+    }
+    Some(portfolio)
+}
+
+pub fn read_portfolio(portfolio_file: &Path) -> Option<Vec<Holding>> {
+    match read_holdings(portfolio_file) {
+        Ok(holdings) => Some(holdings),
+        Err(e) => {
+            println!("Error while reading the portfolio file {}: {}", portfolio_file.display(), e);
+            error!("Error while reading the portfolio file {}:{:?}", portfolio_file.display(), e);
+            None
+        }
+    }
+}
+
+// Charts of a portfolio are named after its file
+pub fn portfolio_name(portfolio_file: &Path) -> String {
+    portfolio_file.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
@@ -0,0 +1,366 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use clap::{Args, ValueEnum};
+use log::{debug, error};
+use plotters::prelude::*;
+
+use crate::backtest::{metrics, returns, years_between, Metrics};
+use crate::portfolio;
+use crate::stats::covariance;
+use crate::{axis_title, finite_range, Stock, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+#[derive(Args, Debug)]
+pub struct RebalanceArgs {
+    /// Target weights of the --name tickers in the same order, comma separated, scaled to add up to 100%
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub weights: Vec<f64>,
+    /// When the holdings are traded back to the target weights
+    #[arg(long, value_enum, default_value_t = RebalanceRule::Monthly)]
+    pub rule: RebalanceRule,
+    /// Drift in percentage points from a target weight that triggers the threshold rule
+    #[arg(long, default_value_t = 5.0)]
+    pub band: f64,
+    /// Starting capital
+    #[arg(long, default_value_t = 10000.0)]
+    pub capital: f64,
+    /// Fixed commission per traded ticker
+    #[arg(long, default_value_t = 0.0)]
+    pub commission: f64,
+    /// Commission in percent of the traded value, charged on top of --commission
+    #[arg(long, default_value_t = 0.0)]
+    pub commission_percent: f64,
+}
+
+// When the holdings are traded back to their target weights
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum RebalanceRule {
+    /// At the close of the first trading day of every month
+    Monthly,
+    /// At the close of the first trading day of January, April, July and October
+    Quarterly,
+    /// At the close of the first trading day of every year
+    Yearly,
+    /// At the close of any day a weight drifted further than --band from its target
+    Threshold,
+}
+
+impl RebalanceRule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RebalanceRule::Monthly => "Monthly",
+            RebalanceRule::Quarterly => "Quarterly",
+            RebalanceRule::Yearly => "Yearly",
+            RebalanceRule::Threshold => "Threshold",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RebalanceConfig {
+    // Target weights as fractions adding up to 1, in the order of the symbols
+    pub targets: Vec<f64>,
+    // None only buys the targets on the first day and lets the weights drift
+    pub rule: Option<RebalanceRule>,
+    // Largest allowed difference between a weight and its target with the threshold rule, as a fraction
+    pub band: f64,
+    pub capital: f64,
+    // Fixed commission per traded symbol plus a percentage of the traded value
+    pub commission: f64,
+    pub commission_percent: f64,
+}
+
+pub struct Rebalancing {
+    // Value of the holdings at every close, after the trades of that day
+    pub equity: Vec<f64>,
+    // Half the summed distance of the weights from their targets at every close, before the trades of that day
+    pub drift: Vec<f64>,
+    // Days the holdings were rebalanced, not counting the purchase on the first day
+    pub rebalances: Vec<usize>,
+    // Sum of the value bought and sold on every rebalance divided by two, as a fraction of the equity
+    pub turnover: f64,
+    // Commissions paid, including those of the first purchase
+    pub costs: f64,
+}
+
+// Whether the day starts a new calendar period of the rule
+fn new_period(rule: RebalanceRule, previous: NaiveDate, date: NaiveDate) -> bool {
+    match rule {
+        RebalanceRule::Monthly => date.month() != previous.month() || date.year() != previous.year(),
+        RebalanceRule::Quarterly => date.month0() / 3 != previous.month0() / 3 || date.year() != previous.year(),
+        RebalanceRule::Yearly => date.year() != previous.year(),
+        RebalanceRule::Threshold => false,
+    }
+}
+
+// Replays the closes of the symbols, buying the target weights at the first close and trading back to them
+// whenever the rule says so. Trades are filled at the close.
+pub fn simulate(closes: &[Vec<f64>], dates: &[NaiveDate], config: &RebalanceConfig) -> Rebalancing {
+    let mut rebalancing = Rebalancing { equity: Vec::new(), drift: Vec::new(), rebalances: Vec::new(), turnover: 0.0, costs: 0.0 };
+    let mut shares = vec![0.0; closes.len()];
+    let mut cash = config.capital;
+    for (day, date) in dates.iter().enumerate() {
+        let prices: Vec<f64> = closes.iter().map(|closes| closes[day]).collect();
+        let values: Vec<f64> = shares.iter().zip(prices.iter()).map(|(shares, price)| shares * price).collect();
+        let equity = cash + values.iter().sum::<f64>();
+        let drift = values.iter().zip(config.targets.iter()).map(|(value, target)| (value / equity - target).abs()).sum::<f64>() / 2.0;
+        let rebalance = day == 0 || match config.rule {
+            Some(RebalanceRule::Threshold) => values.iter().zip(config.targets.iter()).any(|(value, target)| (value / equity - target).abs() > config.band),
+            Some(rule) => new_period(rule, dates[day - 1], *date),
+            None => false,
+        };
+        if rebalance {
+            let traded: Vec<f64> = values.iter().zip(config.targets.iter()).map(|(value, target)| (target * equity - value).abs()).collect();
+            let costs: f64 = traded.iter().filter(|&&traded| traded > 0.0).map(|traded| config.commission + traded * config.commission_percent / 100.0).sum();
+            // The commissions come out of the equity before it is split over the targets
+            for ((shares, target), price) in shares.iter_mut().zip(config.targets.iter()).zip(prices.iter()) {
+                *shares = target * (equity - costs) / price;
+            }
+            cash = 0.0;
+            rebalancing.costs += costs;
+            if day > 0 {
+                rebalancing.turnover += traded.iter().sum::<f64>() / 2.0 / equity;
+                rebalancing.rebalances.push(day);
+            }
+        }
+        rebalancing.equity.push(cash + shares.iter().zip(prices.iter()).map(|(shares, price)| shares * price).sum::<f64>());
+        rebalancing.drift.push(if day == 0 { 0.0 } else { drift });
+    }
+    rebalancing
+}
+
+// Annualised sample standard deviation of the difference between the returns of two equity curves
+pub fn tracking_error(times: &[DateTime<Utc>], equity: &[f64], benchmark: &[f64]) -> f64 {
+    let differences: Vec<f64> = returns(equity).iter().zip(returns(benchmark).iter()).map(|(a, b)| a - b).collect();
+    covariance(&differences, &differences).sqrt() * (differences.len() as f64 / years_between(times)).sqrt()
+}
+
+impl Rebalancing {
+    pub fn metrics(&self, times: &[DateTime<Utc>], capital: f64) -> Metrics {
+        metrics(times, &self.equity, capital, &[])
+    }
+}
+
+// Prints the rebalanced portfolio next to the one that was never rebalanced
+pub fn print(columns: &[(&str, &Rebalancing)], times: &[DateTime<Utc>], capital: f64) {
+    let years = years_between(times);
+    let width = columns.iter().map(|(name, _)| name.len()).max().unwrap_or(0).max(12);
+    let row = |label: &str, value: &dyn Fn(&Rebalancing, &Metrics) -> String| {
+        let values: Vec<String> = columns.iter().map(|(_, rebalancing)| format!("{:>width$}", value(rebalancing, &rebalancing.metrics(times, capital)))).collect();
+        println!("  {:<20} {}", label, values.join("  "));
+    };
+    let names: Vec<String> = columns.iter().map(|(name, _)| format!("{:>width$}", name)).collect();
+    println!("  {:<20} {}", "", names.join("  "));
+    row("Final equity", &|_, metrics| format!("{:.2}", metrics.final_equity));
+    row("Total return", &|_, metrics| format!("{:+.2}%", metrics.total_return * 100.0));
+    row("CAGR", &|_, metrics| format!("{:+.2}%", metrics.cagr * 100.0));
+    row("Sharpe ratio", &|_, metrics| format!("{:.2}", metrics.sharpe));
+    row("Max drawdown", &|_, metrics| format!("{:.2}%", metrics.max_drawdown * 100.0));
+    row("Rebalances", &|rebalancing, _| rebalancing.rebalances.len().to_string());
+    row("Turnover", &|rebalancing, _| format!("{:.1}%", rebalancing.turnover * 100.0));
+    row("Turnover per year", &|rebalancing, _| format!("{:.1}%", rebalancing.turnover / years * 100.0));
+    row("Commissions", &|rebalancing, _| format!("{:.2}", rebalancing.costs));
+    row("Average drift", &|rebalancing, _| format!("{:.2}%", rebalancing.drift.iter().sum::<f64>() / rebalancing.drift.len() as f64 * 100.0));
+    row("Largest drift", &|rebalancing, _| format!("{:.2}%", rebalancing.drift.iter().copied().fold(0.0, f64::max) * 100.0));
+}
+
+// Equity of the rebalanced portfolio against the one that was never rebalanced, with a marker on every rebalance
+pub fn equity_chart(
+    rebalanced: &Rebalancing,
+    drifting: &Rebalancing,
+    rule: RebalanceRule,
+    times: &[DateTime<Utc>],
+    portfolio_name: &str,
+    currency: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "rebalance_".to_owned() + portfolio_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let (min_value, max_value) = finite_range(rebalanced.equity.iter().chain(drifting.equity.iter()));
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("{} Rebalancing of {}", rule.name(), portfolio_name), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(times[0]..times[times.len() - 1], min_value..max_value)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Equity", currency))
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    for (rebalancing, label, color) in [(drifting, "No rebalancing", BLACK), (rebalanced, "Rebalanced", BLUE)] {
+        chart.draw_series(LineSeries::new(
+            times.iter().zip(rebalancing.equity.iter()).map(|(&time, &value)| (time, value)),
+            color.stroke_width(2),
+        ))?
+            .label(label)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+    }
+    chart.draw_series(rebalanced.rebalances.iter().map(|&day| TriangleMarker::new((times[day], rebalanced.equity[day]), 5, BLUE.filled())))?
+        .label("Rebalance")
+        .legend(|(x, y)| TriangleMarker::new((x + 10, y), 5, BLUE.filled()));
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+// Replays the tickers at their target weights with and without rebalancing
+pub async fn run_command(stock_args: &Stock, rebalance_args: &RebalanceArgs) {
+    let symbols: Vec<&str> = stock_args.name.split(',').map(str::trim).filter(|ticker| !ticker.is_empty()).collect();
+    if symbols.len() != rebalance_args.weights.len() {
+        println!("Give one target weight for each of the {} tickers", symbols.len());
+        return;
+    }
+    let weight_sum: f64 = rebalance_args.weights.iter().sum();
+    if rebalance_args.weights.iter().any(|weight| *weight < 0.0) || weight_sum <= 0.0 {
+        println!("Target weights must not be negative and at least one must be positive");
+        return;
+    }
+    if rebalance_args.band <= 0.0 || rebalance_args.capital <= 0.0 {
+        println!("The drift band and the capital must be positive");
+        return;
+    }
+    if rebalance_args.commission < 0.0 || rebalance_args.commission_percent < 0.0 {
+        println!("Commissions must not be negative");
+        return;
+    }
+    let histories = portfolio::fetch_histories(stock_args, &symbols).await;
+    if let Some(missing) = symbols.iter().find(|symbol| !histories.contains_key(**symbol)) {
+        println!("Cannot rebalance without the prices of {}", missing);
+        return;
+    }
+    let mut currencies: Vec<&str> = symbols.iter().map(|symbol| histories[*symbol].currency.as_str()).collect();
+    currencies.sort();
+    currencies.dedup();
+    if currencies.len() > 1 {
+        println!("The tickers are priced in {}, values in different currencies cannot be added up", currencies.join(", "));
+        return;
+    }
+    let (dates, closes) = portfolio::aligned_closes(&symbols, &histories);
+    if dates.len() < 2 {
+        println!("The tickers share too few trading days to replay");
+        return;
+    }
+    let times: Vec<DateTime<Utc>> = dates.iter().map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc()).collect();
+    let config = RebalanceConfig {
+        targets: rebalance_args.weights.iter().map(|weight| weight / weight_sum).collect(),
+        rule: Some(rebalance_args.rule),
+        band: rebalance_args.band / 100.0,
+        capital: rebalance_args.capital,
+        commission: rebalance_args.commission,
+        commission_percent: rebalance_args.commission_percent,
+    };
+    let rebalanced = simulate(&closes, &dates, &config);
+    let drifting = simulate(&closes, &dates, &RebalanceConfig { rule: None, ..config.clone() });
+
+    let targets: Vec<String> = symbols.iter().zip(config.targets.iter()).map(|(symbol, target)| format!("{} {:.1}%", symbol, target * 100.0)).collect();
+    println!("{} rebalancing of {} from {} to {}", rebalance_args.rule.name(), targets.join(", "), dates[0], dates[dates.len() - 1]);
+    print(&[(rebalance_args.rule.name(), &rebalanced), ("No rebalancing", &drifting)], &times, config.capital);
+    println!("Tracking error against no rebalancing {:.2}% per year", tracking_error(&times, &rebalanced.equity, &drifting.equity) * 100.0);
+
+    let portfolio_name = symbols.join("_");
+    match equity_chart(&rebalanced, &drifting, rebalance_args.rule, &times, &portfolio_name, currencies[0]) {
+        Ok(_) => {debug!("Rebalance Chart Created Successfully")}
+        Err(e) => {
+            println!("Error while creating the rebalance chart check log file for more details");
+            error!("Error while creating the Rebalance Chart:{:?}",e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn config(rule: Option<RebalanceRule>, band: f64, commission: f64, commission_percent: f64) -> RebalanceConfig {
+        RebalanceConfig { targets: vec![0.5, 0.5], rule, band, capital: 1000.0, commission, commission_percent }
+    }
+
+    #[test]
+    fn periods_start_on_calendar_boundaries() {
+        let (december, january) = (date(2023, 12, 29), date(2024, 1, 2));
+        for rule in [RebalanceRule::Monthly, RebalanceRule::Quarterly, RebalanceRule::Yearly] {
+            assert!(new_period(rule, december, january), "{:?} across the year change", rule);
+        }
+        assert!(!new_period(RebalanceRule::Monthly, january, date(2024, 1, 31)));
+        assert!(new_period(RebalanceRule::Monthly, date(2024, 1, 31), date(2024, 2, 1)));
+        // Same month of a different year
+        assert!(new_period(RebalanceRule::Monthly, date(2023, 1, 31), date(2024, 1, 2)));
+        assert!(!new_period(RebalanceRule::Quarterly, date(2024, 4, 1), date(2024, 6, 28)));
+        assert!(new_period(RebalanceRule::Quarterly, date(2024, 3, 28), date(2024, 4, 1)));
+        assert!(new_period(RebalanceRule::Quarterly, date(2023, 11, 1), date(2024, 10, 1)));
+        assert!(!new_period(RebalanceRule::Yearly, january, date(2024, 12, 31)));
+        assert!(!new_period(RebalanceRule::Threshold, december, january));
+    }
+
+    #[test]
+    fn monthly_rebalance_trades_back_to_the_targets() {
+        let closes = [vec![100.0, 200.0, 200.0], vec![100.0, 100.0, 100.0]];
+        let dates = [date(2024, 1, 30), date(2024, 1, 31), date(2024, 2, 1)];
+        let rebalancing = simulate(&closes, &dates, &config(Some(RebalanceRule::Monthly), 0.05, 0.0, 0.0));
+        // The purchase on the first day is not a rebalance, the first day of February is
+        assert_eq!(rebalancing.rebalances, vec![2]);
+        assert_eq!(rebalancing.equity, vec![1000.0, 1500.0, 1500.0]);
+        // Weights of 2/3 and 1/3 against targets of 1/2
+        assert_close(rebalancing.drift[0], 0.0);
+        assert_close(rebalancing.drift[1], 1.0 / 6.0);
+        assert_close(rebalancing.drift[2], 1.0 / 6.0);
+        // 250 sold and 250 bought out of 1500
+        assert_close(rebalancing.turnover, 1.0 / 6.0);
+        assert_eq!(rebalancing.costs, 0.0);
+
+        let drifting = simulate(&closes, &dates, &config(None, 0.05, 0.0, 0.0));
+        assert!(drifting.rebalances.is_empty());
+        assert_eq!(drifting.turnover, 0.0);
+    }
+
+    #[test]
+    fn commissions_come_out_before_the_split() {
+        let closes = [vec![100.0, 100.0], vec![50.0, 50.0]];
+        let dates = [date(2024, 1, 2), date(2024, 1, 3)];
+        let rebalancing = simulate(&closes, &dates, &config(None, 0.05, 1.0, 1.0));
+        // 500 bought of each: 1 + 1% of 500 per ticker
+        assert_close(rebalancing.costs, 12.0);
+        assert_close(rebalancing.equity[0], 988.0);
+        assert_close(rebalancing.equity[1], 988.0);
+        assert_close(rebalancing.drift[1], 0.0);
+    }
+
+    #[test]
+    fn threshold_waits_for_the_band() {
+        let closes = [vec![100.0, 110.0, 160.0, 160.0], vec![100.0, 100.0, 100.0, 100.0]];
+        let dates = [date(2024, 1, 2), date(2024, 1, 3), date(2024, 1, 4), date(2024, 1, 5)];
+        let rebalancing = simulate(&closes, &dates, &config(Some(RebalanceRule::Threshold), 0.1, 0.0, 0.0));
+        // 550 of 1050 is within 10 points of 50%, 800 of 1300 is not
+        assert_eq!(rebalancing.rebalances, vec![2]);
+        assert_close(rebalancing.drift[3], 0.0);
+    }
+
+    #[test]
+    fn tracking_error_is_annualised() {
+        // Four closes a quarter of a year apart
+        let times: Vec<DateTime<Utc>> = (0..4).map(|quarter| DateTime::from_timestamp(quarter * 7_889_400, 0).unwrap()).collect();
+        let flat = [100.0, 100.0, 100.0, 100.0];
+        assert_eq!(tracking_error(&times, &flat, &flat), 0.0);
+        // Return differences of 10%, -10% and 10% have a sample standard deviation of 0.2 / sqrt(3), four returns a year
+        let equity = [100.0, 110.0, 99.0, 108.9];
+        assert_close(tracking_error(&times, &equity, &flat), 0.4 / 3.0_f64.sqrt());
+    }
+}
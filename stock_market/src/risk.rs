@@ -0,0 +1,239 @@
+use clap::Args;
+use log::{debug, error};
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use std::path::Path;
+
+use crate::backtest::returns;
+use crate::portfolio;
+use crate::stats::{covariance, mean, percentile, sorted};
+use crate::{Stock, WINDOW_HEIGHT};
+
+#[derive(Args, Debug)]
+pub struct RiskArgs {
+    /// Ticker the betas of the holdings are measured against
+    #[arg(long, default_value = "SPY")]
+    pub benchmark: String,
+    /// Confidence levels of the Value at Risk and Expected Shortfall in percent, comma separated
+    #[arg(long, value_delimiter = ',', default_values_t = [95.0, 99.0])]
+    pub confidence: Vec<f64>,
+}
+
+// Risk measures of the bar-to-bar returns of a portfolio. Losses are positive fractions of the portfolio value.
+
+pub fn correlation(a: &[f64], b: &[f64]) -> f64 {
+    covariance(a, b) / (covariance(a, a) * covariance(b, b)).sqrt()
+}
+
+// Sensitivity of the asset's returns to the benchmark's returns
+pub fn beta(asset: &[f64], benchmark: &[f64]) -> f64 {
+    covariance(asset, benchmark) / covariance(benchmark, benchmark)
+}
+
+// Returns of a portfolio holding the assets with constant weights
+pub fn portfolio_returns(closes: &[Vec<f64>], weights: &[f64]) -> Vec<f64> {
+    let asset_returns: Vec<Vec<f64>> = closes.iter().map(|closes| returns(closes)).collect();
+    let bars = asset_returns.first().map_or(0, Vec::len);
+    (0..bars).map(|i| asset_returns.iter().zip(weights.iter()).map(|(returns, weight)| returns[i] * weight).sum()).collect()
+}
+
+// Value at Risk and Expected Shortfall from the returns as they happened: the loss that was only exceeded on
+// 1 - confidence of the bars, and the average loss on those bars
+pub fn historical_var(returns: &[f64], confidence: f64) -> (f64, f64) {
+    let sorted_returns = sorted(returns.iter().copied());
+    let cutoff = percentile(&sorted_returns, 1.0 - confidence);
+    let tail: Vec<f64> = sorted_returns.iter().copied().filter(|&r| r <= cutoff).collect();
+    (-cutoff, -mean(&tail))
+}
+
+// Value at Risk and Expected Shortfall of normally distributed returns with the mean and standard deviation
+// of the returns
+pub fn parametric_var(returns: &[f64], confidence: f64) -> (f64, f64) {
+    let (mu, sigma) = (mean(returns), covariance(returns, returns).sqrt());
+    let z = inverse_normal(1.0 - confidence);
+    let density = (-z * z / 2.0).exp() / (2.0 * std::f64::consts::PI).sqrt();
+    (-(mu + z * sigma), -(mu - sigma * density / (1.0 - confidence)))
+}
+
+// Quantile of the standard normal distribution (Acklam's rational approximation, relative error below 1.2e-9)
+fn inverse_normal(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969_683_028_665_376e1, 2.209_460_984_245_205e2, -2.759_285_104_469_687e2, 1.383_577_518_672_69e2, -3.066_479_806_614_716e1, 2.506_628_277_459_239];
+    const B: [f64; 5] = [-5.447_609_879_822_406e1, 1.615_858_368_580_409e2, -1.556_989_798_598_866e2, 6.680_131_188_771_972e1, -1.328_068_155_288_572e1];
+    const C: [f64; 6] = [-7.784_894_002_430_293e-3, -3.223_964_580_411_365e-1, -2.400_758_277_161_838, -2.549_732_539_343_734, 4.374_664_141_464_968, 2.938_163_982_698_783];
+    const D: [f64; 4] = [7.784_695_709_041_462e-3, 3.224_671_290_700_398e-1, 2.445_134_137_142_996, 3.754_408_661_907_416];
+    const LOW: f64 = 0.024_25;
+    if p < LOW {
+        let q = (-2.0 * p.ln()).sqrt();
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5]) / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    } else if p <= 1.0 - LOW {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    } else {
+        -inverse_normal(1.0 - p)
+    }
+}
+
+// Heatmap of the correlation matrix, red for positive and blue for negative correlations
+pub fn correlation_heatmap(symbols: &[&str], matrix: &[Vec<f64>], portfolio_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "correlation_".to_owned() + portfolio_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_HEIGHT * 2, WINDOW_HEIGHT * 2)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let size = symbols.len() as i32;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Return Correlation of {}", portfolio_name), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d((0..size - 1).into_segmented(), (0..size - 1).into_segmented())?;
+
+    // One symbol in the middle of every column and row, the first symbol in the top row
+    let label = |segment: &SegmentValue<i32>, flip: bool| match segment {
+        SegmentValue::CenterOf(i) => symbols.get(if flip { (size - 1 - i) as usize } else { *i as usize }).map(|symbol| symbol.to_string()).unwrap_or_default(),
+        _ => String::new(),
+    };
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(symbols.len())
+        .y_labels(symbols.len())
+        .x_label_formatter(&|x| label(x, false))
+        .y_label_formatter(&|y| label(y, true))
+        .draw()?;
+
+    let cells = || (0..symbols.len()).flat_map(|row| (0..symbols.len()).map(move |column| (row, column)));
+    chart.draw_series(cells().map(|(row, column)| {
+        let value = matrix[row][column];
+        // Hue red (0) or blue (2/3), the stronger the correlation the more saturated the cell
+        let hue = if value >= 0.0 { 0.0 } else { 2.0 / 3.0 };
+        let (x, y) = (column as i32, size - 1 - row as i32);
+        Rectangle::new(
+            [(SegmentValue::Exact(x), SegmentValue::Exact(y)), (SegmentValue::Exact(x + 1), SegmentValue::Exact(y + 1))],
+            HSLColor(hue, 0.7, 1.0 - 0.45 * value.abs().min(1.0)).filled(),
+        )
+    }))?;
+    chart.draw_series(cells().map(|(row, column)| {
+        let (x, y) = (column as i32, size - 1 - row as i32);
+        Text::new(format!("{:.2}", matrix[row][column]), (SegmentValue::CenterOf(x), SegmentValue::CenterOf(y)),
+                  ("sans-serif", 15).into_font().color(&BLACK).pos(Pos::new(HPos::Center, VPos::Center)))
+    }))?;
+
+    root.present()?;
+    Ok(())
+}
+
+// Risk of holding the portfolio's current weights, measured on the returns of the days all holdings and the
+// benchmark traded
+pub async fn run_command(stock_args: &Stock, portfolio_file: &Path, risk_args: &RiskArgs) {
+    if risk_args.confidence.iter().any(|confidence| *confidence <= 0.0 || *confidence >= 100.0) {
+        println!("Confidence levels must lie between 0 and 100 percent");
+        return;
+    }
+    let Some(holdings) = portfolio::read_portfolio(portfolio_file) else {
+        return;
+    };
+    let mut symbols: Vec<&str> = holdings.iter().map(|holding| holding.symbol.as_str()).collect();
+    symbols.push(&risk_args.benchmark);
+    let histories = portfolio::fetch_histories(stock_args, &symbols).await;
+    let Some(portfolio) = portfolio::value_portfolio(&holdings, &histories) else {
+        return;
+    };
+    let total = portfolio.value();
+    let allocation = portfolio.allocation();
+    let weights: Vec<f64> = allocation.iter().map(|(_, value)| value / total).collect();
+    let mut symbols: Vec<&str> = allocation.iter().map(|(symbol, _)| symbol.as_str()).collect();
+    let benchmark = histories.contains_key(&risk_args.benchmark) && !symbols.contains(&risk_args.benchmark.as_str());
+    if benchmark {
+        symbols.push(&risk_args.benchmark);
+    }
+    let (dates, closes) = portfolio::aligned_closes(&symbols, &histories);
+    if dates.len() < 3 {
+        println!("The holdings share too few trading days to measure their risk");
+        return;
+    }
+    let portfolio_name = portfolio::portfolio_name(portfolio_file);
+    let portfolio_returns = portfolio_returns(&closes[..allocation.len()], &weights);
+    println!("Risk of {} over one bar, {} returns from {} to {}, value {:.2}", portfolio_name, portfolio_returns.len(),
+             dates[0], dates[dates.len() - 1], total);
+    println!("  {:<10} {:>22} {:>22} {:>22} {:>22}", "Confidence", "Historical VaR", "Historical ES", "Parametric VaR", "Parametric ES");
+    let loss = |loss: f64| format!("{:.2}% ({:.2})", loss * 100.0, loss * total);
+    for percent in &risk_args.confidence {
+        let (historical_var, historical_es) = historical_var(&portfolio_returns, percent / 100.0);
+        let (parametric_var, parametric_es) = parametric_var(&portfolio_returns, percent / 100.0);
+        println!("  {:<10} {:>22} {:>22} {:>22} {:>22}", format!("{}%", percent), loss(historical_var), loss(historical_es),
+                 loss(parametric_var), loss(parametric_es));
+    }
+
+    let asset_returns: Vec<Vec<f64>> = closes.iter().map(|closes| returns(closes)).collect();
+    if benchmark {
+        let benchmark_returns = &asset_returns[asset_returns.len() - 1];
+        println!("Against {}", risk_args.benchmark);
+        println!("  {:<10} {:>8} {:>8} {:>12}", "Symbol", "Weight", "Beta", "Correlation");
+        for ((symbol, returns), weight) in symbols.iter().zip(asset_returns.iter()).zip(weights.iter()) {
+            println!("  {:<10} {:>7.2}% {:>8.2} {:>12.2}", symbol, weight * 100.0, beta(returns, benchmark_returns),
+                     correlation(returns, benchmark_returns));
+        }
+        println!("  {:<10} {:>7.2}% {:>8.2} {:>12.2}", "Portfolio", 100.0, beta(&portfolio_returns, benchmark_returns),
+                 correlation(&portfolio_returns, benchmark_returns));
+    } else {
+        println!("No prices of the benchmark {} to measure the betas against", risk_args.benchmark);
+    }
+
+    let matrix: Vec<Vec<f64>> = asset_returns.iter().map(|a| asset_returns.iter().map(|b| correlation(a, b)).collect()).collect();
+    match correlation_heatmap(&symbols, &matrix, &portfolio_name) {
+        Ok(_) => {debug!("Correlation Heatmap Created Successfully")}
+        Err(e) => {
+            println!("Error while creating the correlation heatmap check log file for more details");
+            error!("Error while creating the Correlation Heatmap:{:?}",e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() < tolerance, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn inverse_normal_matches_known_quantiles() {
+        assert_close(inverse_normal(0.05), -1.644_853_6, 1e-6);
+        assert_close(inverse_normal(0.01), -2.326_347_9, 1e-6);
+        assert_close(inverse_normal(0.5), 0.0, 1e-9);
+        assert_close(inverse_normal(0.975), 1.959_964_0, 1e-6);
+        assert_close(inverse_normal(0.001), -3.090_232_3, 1e-6);
+    }
+
+    #[test]
+    fn historical_var_of_ten_returns() {
+        let returns = [0.03, -0.05, 0.01, 0.06, -0.01, 0.0, 0.05, -0.03, 0.02, 0.04];
+        // The 10% percentile lies 0.9 of the way from -5% to -3%, only the -5% bar is beyond it
+        let (var, shortfall) = historical_var(&returns, 0.9);
+        assert_close(var, 0.032, 1e-12);
+        assert_close(shortfall, 0.05, 1e-12);
+        // The 20% percentile lies 0.8 of the way from -3% to -1%, the -5% and -3% bars are beyond it
+        let (var, shortfall) = historical_var(&returns, 0.8);
+        assert_close(var, 0.014, 1e-12);
+        assert_close(shortfall, 0.04, 1e-12);
+    }
+
+    #[test]
+    fn parametric_var_of_normal_returns() {
+        // Mean 0 and a standard deviation of sqrt(0.0002)
+        let sigma = 0.0002f64.sqrt();
+        let (var, shortfall) = parametric_var(&[0.01, -0.01], 0.95);
+        assert_close(var, 1.644_853_6 * sigma, 1e-8);
+        // The density at the 95% quantile divided by 5%
+        assert_close(shortfall, 2.062_712_9 * sigma, 1e-8);
+    }
+
+    #[test]
+    fn beta_and_correlation_of_a_scaled_series() {
+        let benchmark = [0.01, -0.02, 0.03, 0.0];
+        let asset: Vec<f64> = benchmark.iter().map(|r| 2.0 * r + 0.001).collect();
+        assert_close(beta(&asset, &benchmark), 2.0, 1e-12);
+        assert_close(correlation(&asset, &benchmark), 1.0, 1e-12);
+    }
+}
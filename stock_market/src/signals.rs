@@ -0,0 +1,144 @@
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::fs::File;
+
+// Direction in which a series crossed a level or another series
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cross {
+    Above,
+    Below,
+}
+
+// Indices at which `series` crosses `level`: the first bar strictly on the other side of the level than the
+// last bar strictly on one side. Touching the level and turning back is not a cross. A NaN (e.g. indicator
+// warm-up) forgets the side, so no crossing is reported across it.
+pub fn level_crossings(series: &[f64], level: f64) -> Vec<(usize, Cross)> {
+    let mut crossings = Vec::new();
+    let mut side: Option<Cross> = None;
+    for (i, &value) in series.iter().enumerate() {
+        if !value.is_finite() {
+            side = None;
+            continue;
+        }
+        let current = if value > level {
+            Cross::Above
+        } else if value < level {
+            Cross::Below
+        } else {
+            continue;
+        };
+        if side.is_some_and(|side| side != current) {
+            crossings.push((i, current));
+        }
+        side = Some(current);
+    }
+    crossings
+}
+
+// Indices at which `fast` crosses `slow`, `Cross::Above` meaning `fast` moved above `slow`
+pub fn series_crossings(fast: &[f64], slow: &[f64]) -> Vec<(usize, Cross)> {
+    let spread: Vec<f64> = fast.iter().zip(slow.iter()).map(|(&fast, &slow)| fast - slow).collect();
+    level_crossings(&spread, 0.0)
+}
+
+// A single dated event found by one of the indicator scans
+pub struct Signal {
+    pub time: DateTime<Utc>,
+    pub indicator: String,
+    pub event: String,
+    pub price: f64,
+    pub value: f64,
+}
+
+// Signals of every indicator for a ticker, printed as text and optionally written as JSON
+pub struct SignalReport {
+    ticker_name: String,
+    signals: Vec<Signal>,
+}
+
+impl SignalReport {
+    pub fn new(ticker_name: &str) -> SignalReport {
+        SignalReport {
+            ticker_name: ticker_name.to_owned(),
+            signals: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, time: DateTime<Utc>, indicator: &str, event: &str, price: f64, value: f64) {
+        self.signals.push(Signal {
+            time,
+            indicator: indicator.to_owned(),
+            event: event.to_owned(),
+            price,
+            value,
+        });
+    }
+
+    pub fn print(&self) {
+        println!("Signals for {} ({} found)", self.ticker_name, self.signals.len());
+        for signal in self.sorted() {
+            println!("  {} {:<12} {:<30} close {:.2} value {:.2}",
+                     signal.time.format("%Y-%m-%d"), signal.indicator, signal.event, signal.price, signal.value);
+        }
+    }
+
+    // Writes signals_<ticker>.json and returns the name of the file
+    pub fn write(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let signals: Vec<serde_json::Value> = self.sorted().iter()
+            .map(|signal| json!({
+                "date": signal.time.format("%Y-%m-%d").to_string(),
+                "indicator": signal.indicator,
+                "event": signal.event,
+                "price": signal.price,
+                "value": signal.value,
+            }))
+            .collect();
+        let document = json!({
+            "ticker": self.ticker_name,
+            "signals": signals,
+        });
+
+        let report_file_name = "signals_".to_owned() + &self.ticker_name + ".json";
+        serde_json::to_writer_pretty(File::create(&report_file_name)?, &document)?;
+        Ok(report_file_name)
+    }
+
+    // Signals in date order, indicators are scanned one after the other so they arrive grouped
+    fn sorted(&self) -> Vec<&Signal> {
+        let mut signals: Vec<&Signal> = self.signals.iter().collect();
+        signals.sort_by_key(|signal| signal.time);
+        signals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossings_need_a_change_of_side() {
+        assert_eq!(level_crossings(&[29.0, 31.0, 29.0], 30.0), vec![(1, Cross::Above), (2, Cross::Below)]);
+        // Touching the level and turning back
+        assert!(level_crossings(&[31.0, 30.0, 31.0], 30.0).is_empty());
+        assert!(level_crossings(&[29.0, 30.0, 30.0, 29.0], 30.0).is_empty());
+        // Moving through the level over several bars is one cross, on the first bar past it
+        assert_eq!(level_crossings(&[29.0, 30.0, 30.0, 31.0, 32.0], 30.0), vec![(3, Cross::Above)]);
+        // Starting on the level is no side yet
+        assert!(level_crossings(&[30.0, 31.0], 30.0).is_empty());
+    }
+
+    #[test]
+    fn crossings_skip_nan() {
+        assert!(level_crossings(&[f64::NAN, f64::NAN, 31.0], 30.0).is_empty());
+        assert_eq!(level_crossings(&[f64::NAN, 29.0, 31.0], 30.0), vec![(2, Cross::Above)]);
+        assert!(level_crossings(&[29.0, f64::NAN, 31.0], 30.0).is_empty());
+    }
+
+    #[test]
+    fn series_crossings_compare_fast_with_slow() {
+        let fast = [1.0, 2.0, 3.0, 3.0, 1.0];
+        let slow = [2.0, 2.0, 2.0, 3.0, 2.0];
+        // Equal on the second bar, above on the third, equal again on the fourth and below on the fifth
+        assert_eq!(series_crossings(&fast, &slow), vec![(2, Cross::Above), (4, Cross::Below)]);
+    }
+}
@@ -0,0 +1,53 @@
+// Summary statistics shared by the risk, Monte Carlo, walk-forward, rebalancing and optimisation modules
+
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+// Mean of the finite values, skipping the NaN and infinite metrics of e.g. windows without trades
+pub fn finite_mean(values: impl Iterator<Item = f64>) -> f64 {
+    mean(&values.filter(|value| value.is_finite()).collect::<Vec<f64>>())
+}
+
+// Sample covariance of two series of the same length
+pub fn covariance(a: &[f64], b: &[f64]) -> f64 {
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    a.iter().zip(b.iter()).map(|(x, y)| (x - mean_a) * (y - mean_b)).sum::<f64>() / (a.len() as f64 - 1.0)
+}
+
+// Value below which `fraction` of the sorted values lie, interpolated between the two nearest values
+pub fn percentile(sorted: &[f64], fraction: f64) -> f64 {
+    let position = (sorted.len() - 1) as f64 * fraction;
+    let (below, above) = (position.floor() as usize, position.ceil() as usize);
+    sorted[below] + (sorted[above] - sorted[below]) * (position - below as f64)
+}
+
+pub fn sorted(values: impl Iterator<Item = f64>) -> Vec<f64> {
+    let mut values: Vec<f64> = values.collect();
+    values.sort_by(f64::total_cmp);
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_interpolates_between_neighbours() {
+        let values = sorted([4.0, 1.0, 3.0, 2.0, 5.0].into_iter());
+        assert_eq!(values, vec![1.0, 2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(percentile(&values, 0.0), 1.0);
+        assert_eq!(percentile(&values, 0.5), 3.0);
+        assert_eq!(percentile(&values, 1.0), 5.0);
+        assert!((percentile(&values, 0.1) - 1.4).abs() < 1e-12);
+    }
+
+    #[test]
+    fn covariance_is_the_sample_covariance() {
+        let (a, b) = ([1.0, 2.0, 3.0, 4.0], [2.0, 4.0, 6.0, 9.0]);
+        assert_eq!(mean(&a), 2.5);
+        // Deviations -1.5, -0.5, 0.5, 1.5 and -3.25, -1.25, 0.75, 3.75 over 3
+        assert!((covariance(&a, &b) - 11.5 / 3.0).abs() < 1e-12);
+        assert_eq!(finite_mean([1.0, f64::NAN, 3.0, f64::INFINITY].into_iter()), 2.0);
+    }
+}
@@ -0,0 +1,213 @@
+use clap::ValueEnum;
+
+use crate::backtest::Strategy;
+use crate::bar::Bar;
+use crate::{bollinger_bands_values, ema_values, ema_warmup, macd_values, macd_warmup, rsi_values, rsi_warmup, sma_values, sma_warmup};
+
+// Signals are computed up front with the same indicator functions the charts use. Every value only depends on
+// the bars up to its own, so looking one up in `on_bar` does not peek into the future.
+
+// Built-in strategies that can be selected with --strategy
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum StrategyKind {
+    /// Long while the fast SMA is above the slow SMA
+    SmaCross,
+    /// Long while the fast EMA is above the slow EMA
+    EmaCross,
+    /// Buy when the RSI falls below the oversold level, sell when it rises above the overbought level
+    Rsi,
+    /// Trade the Bollinger Bands as set by --bb-mode
+    Bollinger,
+    /// Long while the MACD line is above its signal line
+    Macd,
+}
+
+// How the Bollinger strategy reads the bands
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum BollingerMode {
+    /// Mean reversion: buy a close at or below the lower band, sell once the close is back at the average
+    Touch,
+    /// Momentum: buy a close above the upper band, sell once the close falls below the average
+    Breakout,
+}
+
+// Parameters of every built-in strategy, each strategy only reads its own
+#[derive(Clone, Copy, Debug)]
+pub struct StrategyParams {
+    pub fast_period: usize,
+    pub slow_period: usize,
+    pub rsi_period: usize,
+    pub rsi_oversold: f64,
+    pub rsi_overbought: f64,
+    pub bb_period: usize,
+    pub bb_multiplier: f64,
+    pub bb_mode: BollingerMode,
+    pub macd_fast: usize,
+    pub macd_slow: usize,
+    pub macd_signal: usize,
+}
+
+impl StrategyParams {
+    // Whether the strategies can run with these parameters
+    pub fn is_valid(&self) -> bool {
+        ![self.fast_period, self.rsi_period, self.bb_period, self.macd_fast, self.macd_signal].contains(&0)
+            && self.fast_period < self.slow_period && self.macd_fast < self.macd_slow && self.bb_multiplier > 0.0
+            && (0.0..=100.0).contains(&self.rsi_oversold) && (0.0..=100.0).contains(&self.rsi_overbought) && self.rsi_oversold < self.rsi_overbought
+    }
+
+    // Bars the strategy's indicators need before its first signal
+    pub fn warmup(&self, kind: StrategyKind) -> usize {
+        match kind {
+            StrategyKind::SmaCross => sma_warmup(self.slow_period),
+            StrategyKind::EmaCross => ema_warmup(self.slow_period),
+            StrategyKind::Rsi => rsi_warmup(self.rsi_period),
+            StrategyKind::Bollinger => sma_warmup(self.bb_period),
+            StrategyKind::Macd => macd_warmup(self.macd_slow, self.macd_signal),
+        }
+    }
+}
+
+pub fn build_strategy(kind: StrategyKind, params: &StrategyParams, bars: &[Bar]) -> Box<dyn Strategy> {
+    let closes: Vec<f64> = bars.iter().map(|bar| bar.close).collect();
+    match kind {
+        StrategyKind::SmaCross => Box::new(MovingAverageCrossover {
+            name: format!("SMA {}/{} crossover", params.fast_period, params.slow_period),
+            fast: sma_values(&closes, params.fast_period),
+            slow: sma_values(&closes, params.slow_period),
+        }),
+        StrategyKind::EmaCross => Box::new(MovingAverageCrossover {
+            name: format!("EMA {}/{} crossover", params.fast_period, params.slow_period),
+            fast: ema_values(&closes, params.fast_period),
+            slow: ema_values(&closes, params.slow_period),
+        }),
+        StrategyKind::Rsi => Box::new(RsiMeanReversion {
+            name: format!("RSI {} {}/{} mean reversion", params.rsi_period, params.rsi_oversold, params.rsi_overbought),
+            rsi: rsi_values(&closes, params.rsi_period),
+            oversold: params.rsi_oversold,
+            overbought: params.rsi_overbought,
+            long: false,
+        }),
+        StrategyKind::Bollinger => {
+            let (average, upper, lower) = bollinger_bands_values(&closes, params.bb_period, params.bb_multiplier);
+            let mode = match params.bb_mode {
+                BollingerMode::Touch => "touch",
+                BollingerMode::Breakout => "breakout",
+            };
+            Box::new(BollingerStrategy {
+                name: format!("Bollinger {} {} {}", params.bb_period, params.bb_multiplier, mode),
+                mode: params.bb_mode,
+                average,
+                upper,
+                lower,
+                long: false,
+            })
+        }
+        StrategyKind::Macd => {
+            let (macd_line, signal_line, _) = macd_values(&closes, params.macd_fast, params.macd_slow, params.macd_signal);
+            // Same rule as the moving average crossovers, with the MACD line as the fast series
+            Box::new(MovingAverageCrossover {
+                name: format!("MACD {}/{}/{} signal cross", params.macd_fast, params.macd_slow, params.macd_signal),
+                fast: macd_line,
+                slow: signal_line,
+            })
+        }
+    }
+}
+
+// Long while the fast series is above the slow one, flat otherwise
+struct MovingAverageCrossover {
+    name: String,
+    fast: Vec<f64>,
+    slow: Vec<f64>,
+}
+
+impl Strategy for MovingAverageCrossover {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn on_bar(&mut self, _bars: &[Bar], index: usize) -> f64 {
+        // NaN during the warm-up compares false, so the strategy stays flat until both series exist
+        if self.fast[index] > self.slow[index] { 1.0 } else { 0.0 }
+    }
+}
+
+struct RsiMeanReversion {
+    name: String,
+    rsi: Vec<f64>,
+    oversold: f64,
+    overbought: f64,
+    long: bool,
+}
+
+impl Strategy for RsiMeanReversion {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn on_bar(&mut self, _bars: &[Bar], index: usize) -> f64 {
+        let rsi = self.rsi[index];
+        if rsi < self.oversold {
+            self.long = true;
+        } else if rsi > self.overbought {
+            self.long = false;
+        }
+        if self.long { 1.0 } else { 0.0 }
+    }
+}
+
+struct BollingerStrategy {
+    name: String,
+    mode: BollingerMode,
+    average: Vec<f64>,
+    upper: Vec<f64>,
+    lower: Vec<f64>,
+    long: bool,
+}
+
+impl Strategy for BollingerStrategy {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn on_bar(&mut self, bars: &[Bar], index: usize) -> f64 {
+        let close = bars[index].close;
+        let (entry, exit) = match self.mode {
+            BollingerMode::Touch => (close <= self.lower[index], close >= self.average[index]),
+            BollingerMode::Breakout => (close > self.upper[index], close < self.average[index]),
+        };
+        if entry {
+            self.long = true;
+        } else if exit {
+            self.long = false;
+        }
+        if self.long { 1.0 } else { 0.0 }
+    }
+}
+
+// The benchmark every strategy is compared with: signals on the first bar and holds, so it is filled at the
+// second bar's open with --fill next-open and at the first bar's close with --fill close
+pub struct BuyAndHold;
+
+impl Strategy for BuyAndHold {
+    fn name(&self) -> String {
+        "Buy and hold".to_owned()
+    }
+
+    fn on_bar(&mut self, _bars: &[Bar], _index: usize) -> f64 {
+        1.0
+    }
+}
+
+// Never holds a position, stands in for the parameters of a walk-forward window in which no combination traded
+pub struct Flat;
+
+impl Strategy for Flat {
+    fn name(&self) -> String {
+        "Flat".to_owned()
+    }
+
+    fn on_bar(&mut self, _bars: &[Bar], _index: usize) -> f64 {
+        0.0
+    }
+}
@@ -0,0 +1,439 @@
+use clap::{Args, ValueEnum};
+use log::{debug, error};
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+use crate::backtest::{backtest_config, fetch_backtest_bars, run_backtest, strategy_params, BacktestArgs, BacktestConfig, Metrics};
+use crate::bar::Bar;
+use crate::strategies::{build_strategy, BuyAndHold, StrategyKind, StrategyParams};
+use crate::{Stock, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+// Parameter grid shared by the sweep and the walk-forward analysis
+#[derive(Args, Debug)]
+pub struct SweepGrid {
+    /// Parameter to sweep as name=start:end:step (end included), e.g. fast-period=5:30:5, repeat for more parameters
+    #[arg(long = "param", required = true)]
+    pub params: Vec<SweepRange>,
+    /// Metric the combinations are ranked by
+    #[arg(long, value_enum, default_value_t = RankMetric::Sharpe)]
+    pub rank_by: RankMetric,
+}
+
+#[derive(Args, Debug)]
+pub struct SweepArgs {
+    #[command(flatten)]
+    pub backtest: BacktestArgs,
+    #[command(flatten)]
+    pub grid: SweepGrid,
+    /// Number of best combinations to print, all of them are written to the CSV file
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+}
+
+// Strategy parameters that can be swept with --param
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SweepParam {
+    FastPeriod,
+    SlowPeriod,
+    RsiPeriod,
+    RsiOversold,
+    RsiOverbought,
+    BbPeriod,
+    BbMultiplier,
+    MacdFast,
+    MacdSlow,
+    MacdSignal,
+}
+
+impl SweepParam {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SweepParam::FastPeriod => "fast_period",
+            SweepParam::SlowPeriod => "slow_period",
+            SweepParam::RsiPeriod => "rsi_period",
+            SweepParam::RsiOversold => "rsi_oversold",
+            SweepParam::RsiOverbought => "rsi_overbought",
+            SweepParam::BbPeriod => "bb_period",
+            SweepParam::BbMultiplier => "bb_multiplier",
+            SweepParam::MacdFast => "macd_fast",
+            SweepParam::MacdSlow => "macd_slow",
+            SweepParam::MacdSignal => "macd_signal",
+        }
+    }
+
+    pub fn apply(&self, params: &mut StrategyParams, value: f64) {
+        let period = value.round().max(0.0) as usize;
+        match self {
+            SweepParam::FastPeriod => params.fast_period = period,
+            SweepParam::SlowPeriod => params.slow_period = period,
+            SweepParam::RsiPeriod => params.rsi_period = period,
+            SweepParam::RsiOversold => params.rsi_oversold = value,
+            SweepParam::RsiOverbought => params.rsi_overbought = value,
+            SweepParam::BbPeriod => params.bb_period = period,
+            SweepParam::BbMultiplier => params.bb_multiplier = value,
+            SweepParam::MacdFast => params.macd_fast = period,
+            SweepParam::MacdSlow => params.macd_slow = period,
+            SweepParam::MacdSignal => params.macd_signal = period,
+        }
+    }
+}
+
+// A parameter and the values it is swept over, parsed from `name=start:end:step` (end included) or `name=value`
+#[derive(Clone, Debug)]
+pub struct SweepRange {
+    pub param: SweepParam,
+    pub values: Vec<f64>,
+}
+
+impl FromStr for SweepRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, range) = s.split_once('=').ok_or("expected name=start:end:step, e.g. fast-period=5:30:5")?;
+        let param = SweepParam::from_str(name.trim(), true)?;
+        let numbers: Vec<f64> = range.split(':')
+            .map(|number| number.trim().parse::<f64>().map_err(|e| format!("{}: {}", number, e)))
+            .collect::<Result<_, _>>()?;
+        // Values are rounded to the decimals of the start and step, so 0.5:1.5:0.1 gives 1.2 and not 1.2000000000000002
+        let decimals = range.split(':')
+            .map(|number| number.trim().split_once('.').map_or(0, |(_, fraction)| fraction.chars().take_while(char::is_ascii_digit).count()))
+            .max()
+            .unwrap_or(0);
+        let scale = 10f64.powi(decimals as i32);
+        let values = match numbers[..] {
+            [value] => vec![value],
+            [start, end, step] if step > 0.0 && start <= end => {
+                // Inclusive of the end, with a little tolerance for fractional steps such as 0.1
+                let count = ((end - start) / step + 1e-9).floor() as usize + 1;
+                (0..count).map(|i| ((start + i as f64 * step) * scale).round() / scale).collect()
+            }
+            _ => return Err("expected start:end:step with start <= end and a positive step".to_owned()),
+        };
+        Ok(SweepRange { param, values })
+    }
+}
+
+// Metric the sweep results are ranked by
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum RankMetric {
+    TotalReturn,
+    Cagr,
+    Sharpe,
+    Sortino,
+    /// Smallest maximum drawdown first
+    MaxDrawdown,
+    WinRate,
+    ProfitFactor,
+}
+
+impl RankMetric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RankMetric::TotalReturn => "Total return",
+            RankMetric::Cagr => "CAGR",
+            RankMetric::Sharpe => "Sharpe ratio",
+            RankMetric::Sortino => "Sortino ratio",
+            RankMetric::MaxDrawdown => "Max drawdown",
+            RankMetric::WinRate => "Win rate",
+            RankMetric::ProfitFactor => "Profit factor",
+        }
+    }
+
+    pub fn value(&self, metrics: &Metrics) -> f64 {
+        match self {
+            RankMetric::TotalReturn => metrics.total_return,
+            RankMetric::Cagr => metrics.cagr,
+            RankMetric::Sharpe => metrics.sharpe,
+            RankMetric::Sortino => metrics.sortino,
+            RankMetric::MaxDrawdown => metrics.max_drawdown,
+            RankMetric::WinRate => metrics.win_rate,
+            RankMetric::ProfitFactor => metrics.profit_factor,
+        }
+    }
+
+    // Higher is better for every metric but the drawdown. NaN and combinations without trades rank last,
+    // otherwise never trading would be the smallest drawdown
+    pub fn score(&self, metrics: &Metrics) -> f64 {
+        let value = match self {
+            RankMetric::MaxDrawdown => -self.value(metrics),
+            _ => self.value(metrics),
+        };
+        if value.is_nan() || metrics.trades == 0 { f64::NEG_INFINITY } else { value }
+    }
+}
+
+// One combination of the swept parameters, `values` in the order of the sweep ranges
+pub struct SweepResult {
+    pub values: Vec<f64>,
+    pub params: StrategyParams,
+    pub metrics: Metrics,
+}
+
+// Every combination of the range values applied on top of `base`, combinations the strategy cannot run
+// with (e.g. a fast period that is not shorter than the slow one) are left out
+pub fn combinations(base: &StrategyParams, ranges: &[SweepRange]) -> Vec<(Vec<f64>, StrategyParams)> {
+    let mut combinations = vec![(Vec::new(), *base)];
+    for range in ranges {
+        combinations = combinations.into_iter()
+            .flat_map(|(values, params)| range.values.iter().map(move |&value| {
+                let mut params = params;
+                range.param.apply(&mut params, value);
+                ([values.clone(), vec![value]].concat(), params)
+            }))
+            .collect();
+    }
+    combinations.retain(|(_, params)| params.is_valid());
+    combinations
+}
+
+// Backtests every combination on `bars[start..]`, split over all CPU cores, and returns the results best first
+pub fn run_sweep(
+    bars: &[Bar],
+    start: usize,
+    kind: StrategyKind,
+    combinations: Vec<(Vec<f64>, StrategyParams)>,
+    config: &BacktestConfig,
+    rank_by: RankMetric,
+) -> Vec<SweepResult> {
+    let threads = std::thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+    let chunk_size = combinations.len().div_ceil(threads).max(1);
+    let mut results: Vec<SweepResult> = std::thread::scope(|scope| {
+        let workers: Vec<_> = combinations.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || {
+                chunk.iter().map(|(values, params)| {
+                    let mut strategy = build_strategy(kind, params, bars);
+                    let result = run_backtest(bars, start, strategy.as_mut(), config);
+                    SweepResult { values: values.clone(), params: *params, metrics: result.metrics() }
+                }).collect::<Vec<SweepResult>>()
+            }))
+            .collect();
+        workers.into_iter().flat_map(|worker| worker.join().unwrap()).collect()
+    });
+    results.sort_by(|a, b| rank_by.score(&b.metrics).total_cmp(&rank_by.score(&a.metrics)));
+    results
+}
+
+// Writes sweep_<ticker>.csv with one row per combination, best first, and returns the name of the file
+pub fn write_csv(results: &[SweepResult], ranges: &[SweepRange], ticker_name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let mut csv = String::new();
+    let names: Vec<&str> = ranges.iter().map(|range| range.param.name()).collect();
+    writeln!(csv, "rank,{},final_equity,total_return,cagr,sharpe,sortino,max_drawdown,trades,win_rate,profit_factor", names.join(","))?;
+    for (rank, result) in results.iter().enumerate() {
+        let values: Vec<String> = result.values.iter().map(|value| value.to_string()).collect();
+        let metrics = &result.metrics;
+        writeln!(csv, "{},{},{},{},{},{},{},{},{},{},{}", rank + 1, values.join(","), metrics.final_equity, metrics.total_return, metrics.cagr,
+                 metrics.sharpe, metrics.sortino, metrics.max_drawdown, metrics.trades, metrics.win_rate, metrics.profit_factor)?;
+    }
+    let csv_file_name = "sweep_".to_owned() + ticker_name + ".csv";
+    std::fs::write(&csv_file_name, csv)?;
+    Ok(csv_file_name)
+}
+
+// Heatmap of the metric over the two swept parameters, red for the worst and green for the best combination.
+// Combinations that were left out or never traded stay grey and the best one is framed. An infinite score
+// (a profit factor without losing trades) is drawn at the top of the scale.
+pub fn heatmap(
+    results: &[SweepResult],
+    ranges: &[SweepRange],
+    rank_by: RankMetric,
+    strategy_name: &str,
+    ticker_name: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (x_range, y_range) = (&ranges[0], &ranges[1]);
+    let chart_file_name = "sweep_heatmap_".to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH / 2, WINDOW_HEIGHT * 2)).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let cell = |result: &SweepResult| {
+        let x = x_range.values.iter().position(|&value| value == result.values[0])?;
+        let y = y_range.values.iter().position(|&value| value == result.values[1])?;
+        Some((x, y))
+    };
+    let scores: Vec<f64> = results.iter().map(|result| rank_by.score(&result.metrics)).filter(|score| score.is_finite()).collect();
+    let (min_score, max_score) = scores.iter().fold((f64::MAX, f64::MIN), |(min, max), &score| (min.min(score), max.max(score)));
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("{} of {} for {}", rank_by.name(), strategy_name, ticker_name), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d((0..x_range.values.len() as i32 - 1).into_segmented(), (0..y_range.values.len() as i32 - 1).into_segmented())?;
+
+    // One label in the middle of every column and row
+    let label = |values: &[f64], segment: &SegmentValue<i32>| match segment {
+        SegmentValue::CenterOf(i) => values.get(*i as usize).map(|value| value.to_string()).unwrap_or_default(),
+        _ => String::new(),
+    };
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_labels(x_range.values.len())
+        .y_labels(y_range.values.len())
+        .x_desc(x_range.param.name())
+        .y_desc(y_range.param.name())
+        .x_label_formatter(&|x| label(&x_range.values, x))
+        .y_label_formatter(&|y| label(&y_range.values, y))
+        .draw()?;
+
+    let area = |x: usize, y: usize| [(SegmentValue::Exact(x as i32), SegmentValue::Exact(y as i32)), (SegmentValue::Exact(x as i32 + 1), SegmentValue::Exact(y as i32 + 1))];
+    let grey = RGBColor(200, 200, 200).filled();
+    chart.draw_series(std::iter::once(Rectangle::new(
+        [(SegmentValue::Exact(0), SegmentValue::Exact(0)), (SegmentValue::Exact(x_range.values.len() as i32), SegmentValue::Exact(y_range.values.len() as i32))],
+        grey,
+    )))?;
+    chart.draw_series(results.iter().filter_map(|result| {
+        let (x, y) = cell(result)?;
+        let score = rank_by.score(&result.metrics);
+        let color = if score == f64::INFINITY {
+            HSLColor(1.0 / 3.0, 0.7, 0.5).filled()
+        } else if score.is_finite() {
+            // Hue from red (0) to green (1/3)
+            let position = if max_score > min_score { (score - min_score) / (max_score - min_score) } else { 1.0 };
+            HSLColor(position / 3.0, 0.7, 0.5).filled()
+        } else {
+            grey
+        };
+        Some(Rectangle::new(area(x, y), color))
+    }))?;
+
+    // Metric values in the cells when they are wide enough to hold the text
+    let cell_pixels = (WINDOW_WIDTH / 2 - 80) as usize / x_range.values.len();
+    if cell_pixels >= 40 {
+        chart.draw_series(results.iter().filter_map(|result| {
+            let (x, y) = cell(result)?;
+            let value = rank_by.value(&result.metrics);
+            let text = match rank_by {
+                RankMetric::Sharpe | RankMetric::Sortino | RankMetric::ProfitFactor => format!("{:.2}", value),
+                _ => format!("{:.1}%", value * 100.0),
+            };
+            Some(Text::new(text, (SegmentValue::CenterOf(x as i32), SegmentValue::CenterOf(y as i32)), ("sans-serif", 12).into_font().color(&BLACK).pos(Pos::new(HPos::Center, VPos::Center))))
+        }))?;
+    }
+
+    if let Some((x, y)) = results.first().and_then(cell) {
+        chart.draw_series(std::iter::once(Rectangle::new(area(x, y), BLACK.stroke_width(3))))?;
+    }
+
+    root.present()?;
+    Ok(())
+}
+
+// Backtests every combination of the swept parameters, prints the best ones and writes them all to a CSV
+// file, with a heatmap when two parameters are swept
+pub async fn run_command(stock_args: &Stock, sweep_args: &SweepArgs) {
+    let backtest_args = &sweep_args.backtest;
+    if let Err(e) = backtest_args.check() {
+        println!("{}", e);
+        return;
+    }
+    let combinations = combinations(&strategy_params(stock_args, backtest_args), &sweep_args.grid.params);
+    if combinations.is_empty() {
+        println!("None of the parameter combinations is valid, the fast periods must be shorter than the slow periods");
+        return;
+    }
+    // Enough history for the slowest combination
+    let warmup = combinations.iter().map(|(_, params)| params.warmup(backtest_args.strategy)).max().unwrap_or(0);
+    let Some((bars, start, _)) = fetch_backtest_bars(stock_args, warmup).await else {
+        return;
+    };
+    let config = backtest_config(backtest_args);
+    let strategy_name = backtest_args.strategy.to_possible_value().map(|value| value.get_name().to_owned()).unwrap_or_default();
+    let names: Vec<&str> = sweep_args.grid.params.iter().map(|range| range.param.name()).collect();
+    println!("Sweeping {} combinations of {} for the {} strategy on {}", combinations.len(), names.join(" x "), strategy_name, stock_args.name);
+
+    let results = run_sweep(&bars, start, backtest_args.strategy, combinations, &config, sweep_args.grid.rank_by);
+    let benchmark = run_backtest(&bars, start, &mut BuyAndHold, &config).metrics();
+    let rank_metric = sweep_args.grid.rank_by.name();
+    let headers: Vec<String> = names.iter().map(|name| format!("{:>14}", name)).collect();
+    println!("  {:>4} {} {:>14} {:>13} {:>13} {:>7}", "rank", headers.join(""), rank_metric, "Total return", "Max drawdown", "Trades");
+    let row = |label: String, values: &[String], metrics: &Metrics| {
+        println!("  {:>4} {} {:>14.4} {:>12.2}% {:>12.2}% {:>7}", label, values.join(""), sweep_args.grid.rank_by.value(metrics),
+                 metrics.total_return * 100.0, metrics.max_drawdown * 100.0, metrics.trades);
+    };
+    for (rank, result) in results.iter().take(sweep_args.top).enumerate() {
+        let values: Vec<String> = result.values.iter().map(|value| format!("{:>14}", value)).collect();
+        row((rank + 1).to_string(), &values, &result.metrics);
+    }
+    row("".to_owned(), &[format!("{:>width$}", "Buy and hold", width = 14 * names.len())], &benchmark);
+
+    match write_csv(&results, &sweep_args.grid.params, &stock_args.name) {
+        Ok(csv_file_name) => println!("Sweep results written to {}", csv_file_name),
+        Err(e) => {
+            println!("Error while writing the sweep results check log file for more details");
+            error!("Error while writing the sweep results:{:?}",e)
+        }
+    }
+    if sweep_args.grid.params.len() == 2 {
+        match heatmap(&results, &sweep_args.grid.params, sweep_args.grid.rank_by, &strategy_name, &stock_args.name) {
+            Ok(_) => {debug!("Sweep Heatmap Created Successfully")}
+            Err(e) => {
+                println!("Error while creating the sweep heatmap check log file for more details");
+                error!("Error while creating the Sweep Heatmap:{:?}",e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(s: &str) -> Vec<f64> {
+        SweepRange::from_str(s).unwrap().values
+    }
+
+    #[test]
+    fn ranges_include_the_end() {
+        assert_eq!(values("fast-period=5:30:5"), vec![5.0, 10.0, 15.0, 20.0, 25.0, 30.0]);
+        assert_eq!(values("bb-multiplier=0.5:2:0.5"), vec![0.5, 1.0, 1.5, 2.0]);
+        // An end the step does not land on is left out
+        assert_eq!(values("slow-period=10:25:10"), vec![10.0, 20.0]);
+    }
+
+    #[test]
+    fn fractional_steps_are_rounded() {
+        let values = values("bb-multiplier=0.5:1.5:0.1");
+        assert_eq!(values.len(), 11);
+        assert_eq!(values[7], 1.2);
+        assert_eq!(values[10], 1.5);
+    }
+
+    #[test]
+    fn single_value() {
+        let range = SweepRange::from_str("rsi-period=7").unwrap();
+        assert_eq!(range.param, SweepParam::RsiPeriod);
+        assert_eq!(range.values, vec![7.0]);
+    }
+
+    #[test]
+    fn bad_ranges_are_rejected() {
+        for s in ["fast-period=5:30:0", "fast-period=5:30:-5", "fast-period=30:5:5", "fast-period=5:30", "fast-period=five", "fast-period", "speed=5"] {
+            assert!(SweepRange::from_str(s).is_err(), "{} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn combinations_cover_the_grid_and_drop_invalid_ones() {
+        let base = StrategyParams {
+            fast_period: 10,
+            slow_period: 30,
+            rsi_period: 14,
+            rsi_oversold: 30.0,
+            rsi_overbought: 70.0,
+            bb_period: 20,
+            bb_multiplier: 2.0,
+            bb_mode: crate::strategies::BollingerMode::Touch,
+            macd_fast: 12,
+            macd_slow: 26,
+            macd_signal: 9,
+        };
+        let ranges = [SweepRange::from_str("fast-period=5:10:5").unwrap(), SweepRange::from_str("slow-period=10:20:10").unwrap()];
+        let combinations = combinations(&base, &ranges);
+        // fast 10 with slow 10 is not a valid crossover
+        let grid: Vec<Vec<f64>> = combinations.iter().map(|(values, _)| values.clone()).collect();
+        assert_eq!(grid, vec![vec![5.0, 10.0], vec![5.0, 20.0], vec![10.0, 20.0]]);
+        let (_, params) = &combinations[1];
+        assert_eq!((params.fast_period, params.slow_period, params.rsi_period), (5, 20, 14));
+    }
+}
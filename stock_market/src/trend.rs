@@ -0,0 +1,228 @@
+use chrono::{DateTime, Utc};
+use plotters::prelude::*;
+
+use crate::bar::Bar;
+use crate::mask_warmup;
+use crate::momentum::{oscillator_chart, OscillatorChart};
+
+// +DI and -DI need `period` price changes before Wilder's smoothing has its first value
+pub fn di_warmup(period: usize) -> usize {
+    period
+}
+
+// The ADX averages `period` DX values on top of that
+pub fn adx_warmup(period: usize) -> usize {
+    di_warmup(period) + period - 1
+}
+
+// Average Directional Index with the +DI and -DI lines, all smoothed the way Wilder describes it.
+// Returns (adx, plus_di, minus_di).
+pub fn adx_values(bars: &[Bar], period: usize) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+    let mut adx = vec![f64::NAN; bars.len()];
+    let mut plus_di = vec![f64::NAN; bars.len()];
+    let mut minus_di = vec![f64::NAN; bars.len()];
+
+    let (mut smoothed_tr, mut smoothed_plus_dm, mut smoothed_minus_dm) = (0.0, 0.0, 0.0);
+    let mut dx_sum = 0.0;
+    for i in 1..bars.len() {
+        let (bar, previous) = (&bars[i], &bars[i - 1]);
+        let true_range = (bar.high - bar.low)
+            .max((bar.high - previous.close).abs())
+            .max((bar.low - previous.close).abs());
+        let up_move = bar.high - previous.high;
+        let down_move = previous.low - bar.low;
+        let plus_dm = if up_move > down_move && up_move > 0.0 { up_move } else { 0.0 };
+        let minus_dm = if down_move > up_move && down_move > 0.0 { down_move } else { 0.0 };
+
+        // The first smoothed values are plain sums over the first `period` changes
+        if i <= period {
+            smoothed_tr += true_range;
+            smoothed_plus_dm += plus_dm;
+            smoothed_minus_dm += minus_dm;
+        } else {
+            smoothed_tr += true_range - smoothed_tr / period as f64;
+            smoothed_plus_dm += plus_dm - smoothed_plus_dm / period as f64;
+            smoothed_minus_dm += minus_dm - smoothed_minus_dm / period as f64;
+        }
+        if i < di_warmup(period) {
+            continue;
+        }
+
+        let (plus, minus) = if smoothed_tr > 0.0 {
+            (100.0 * smoothed_plus_dm / smoothed_tr, 100.0 * smoothed_minus_dm / smoothed_tr)
+        } else {
+            (0.0, 0.0)
+        };
+        plus_di[i] = plus;
+        minus_di[i] = minus;
+        let dx = if plus + minus > 0.0 { 100.0 * (plus - minus).abs() / (plus + minus) } else { 0.0 };
+
+        // The ADX starts as the mean of the first `period` DX values and is smoothed from there on
+        if i < adx_warmup(period) {
+            dx_sum += dx;
+        } else if i == adx_warmup(period) {
+            adx[i] = (dx_sum + dx) / period as f64;
+        } else {
+            adx[i] = (adx[i - 1] * (period - 1) as f64 + dx) / period as f64;
+        }
+    }
+    (adx, plus_di, minus_di)
+}
+
+// Parabolic SAR: the stop trails the price and accelerates by `step` (up to `max_step`) every time
+// the trend makes a new extreme. The SAR flips to the other side of the price when it is hit.
+pub fn parabolic_sar_values(bars: &[Bar], step: f64, max_step: f64) -> Vec<f64> {
+    let mut sar_values = vec![f64::NAN; bars.len()];
+    if bars.len() < 2 {
+        return sar_values;
+    }
+
+    let mut rising = bars[1].close >= bars[0].close;
+    let (mut sar, mut extreme_point) = if rising {
+        (bars[0].low.min(bars[1].low), bars[0].high.max(bars[1].high))
+    } else {
+        (bars[0].high.max(bars[1].high), bars[0].low.min(bars[1].low))
+    };
+    let mut acceleration = step;
+    sar_values[1] = sar;
+
+    for i in 2..bars.len() {
+        let bar = &bars[i];
+        let mut next_sar = sar + acceleration * (extreme_point - sar);
+        if rising {
+            // The stop never moves into the range of the two previous bars
+            next_sar = next_sar.min(bars[i - 1].low).min(bars[i - 2].low);
+            if bar.low < next_sar {
+                rising = false;
+                next_sar = extreme_point;
+                extreme_point = bar.low;
+                acceleration = step;
+            } else if bar.high > extreme_point {
+                extreme_point = bar.high;
+                acceleration = (acceleration + step).min(max_step);
+            }
+        } else {
+            next_sar = next_sar.max(bars[i - 1].high).max(bars[i - 2].high);
+            if bar.high > next_sar {
+                rising = true;
+                next_sar = extreme_point;
+                extreme_point = bar.high;
+                acceleration = step;
+            } else if bar.low < extreme_point {
+                extreme_point = bar.low;
+                acceleration = (acceleration + step).min(max_step);
+            }
+        }
+        sar = next_sar;
+        sar_values[i] = sar;
+    }
+    sar_values
+}
+
+// Aroon up/down: how recently (in percent of `period`) the highest high and the lowest low of the last
+// `period` bars were made. Returns (aroon_up, aroon_down, aroon_oscillator).
+pub fn aroon_values(bars: &[Bar], period: usize) -> (Vec<f64>, Vec<f64>, Vec<f64>) {
+    let mut aroon_up = vec![f64::NAN; bars.len()];
+    let mut aroon_down = vec![f64::NAN; bars.len()];
+    for i in 0..bars.len() {
+        let window = &bars[i.saturating_sub(period)..=i];
+        // On ties the most recent extreme counts
+        let (mut highest, mut lowest) = (0, 0);
+        for (j, bar) in window.iter().enumerate() {
+            if bar.high >= window[highest].high {
+                highest = j;
+            }
+            if bar.low <= window[lowest].low {
+                lowest = j;
+            }
+        }
+        let bars_since_high = window.len() - 1 - highest;
+        let bars_since_low = window.len() - 1 - lowest;
+        aroon_up[i] = 100.0 * (period - bars_since_high.min(period)) as f64 / period as f64;
+        aroon_down[i] = 100.0 * (period - bars_since_low.min(period)) as f64 / period as f64;
+    }
+    mask_warmup(&mut aroon_up, period);
+    mask_warmup(&mut aroon_down, period);
+    let aroon_oscillator: Vec<f64> = aroon_up.iter().zip(aroon_down.iter()).map(|(&up, &down)| up - down).collect();
+    (aroon_up, aroon_down, aroon_oscillator)
+}
+
+pub fn adx(adx_line: &[f64], plus_di: &[f64], minus_di: &[f64], ticker_name: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
+    oscillator_chart(OscillatorChart {
+        file_prefix: "adx_",
+        caption: "Average Directional Index Chart for ",
+        y_desc: "ADX / DI",
+        lines: vec![("ADX", adx_line, BLACK), ("+DI", plus_di, GREEN), ("-DI", minus_di, RED)],
+        fixed_range: Some((0.0, 100.0)),
+        zones: None,
+        // Above 25 the trend is usually considered strong
+        levels: &[25.0],
+    }, ticker_name, times)
+}
+
+pub fn aroon(aroon_up: &[f64], aroon_down: &[f64], aroon_oscillator: &[f64], ticker_name: &str, times: &[DateTime<Utc>]) -> Result<(), Box<dyn std::error::Error>> {
+    oscillator_chart(OscillatorChart {
+        file_prefix: "aroon_",
+        caption: "Aroon Chart for ",
+        y_desc: "Aroon",
+        lines: vec![("Aroon Up", aroon_up, GREEN), ("Aroon Down", aroon_down, RED), ("Aroon Oscillator", aroon_oscillator, BLUE)],
+        fixed_range: Some((-100.0, 100.0)),
+        zones: None,
+        levels: &[0.0],
+    }, ticker_name, times)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Daily bars one above and below the given closes
+    fn bars(closes: &[f64]) -> Vec<Bar> {
+        closes.iter().enumerate().map(|(i, &close)| Bar::new(i as u64 * 86_400, close, close + 1.0, close - 1.0, close, 0.0)).collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn sar_trails_a_rise_and_flips_above_on_the_drop() {
+        let bars = bars(&[10.0, 11.0, 12.0, 13.0, 9.0]);
+        let sar = parabolic_sar_values(&bars, 0.02, 0.2);
+        assert!(sar[0].is_nan());
+        // Starts at the lowest low, held below the two previous lows, then accelerates with each new high
+        assert_eq!(sar[1..3], [9.0, 9.0]);
+        assert_close(sar[3], 9.16);
+        assert!((1..4).all(|i| sar[i] < bars[i].low));
+        // The low of 8 hits the stop, which jumps to the highest high of the rise
+        assert_eq!(sar[4], 14.0);
+    }
+
+    #[test]
+    fn aroon_on_a_steady_rise_and_fall() {
+        let rising: Vec<f64> = (0..10).map(|i| 10.0 + i as f64).collect();
+        let (up, down, oscillator) = aroon_values(&bars(&rising), 5);
+        assert!(up[..5].iter().all(|value| value.is_nan()));
+        assert!(up[5..].iter().all(|&value| value == 100.0));
+        assert!(down[5..].iter().all(|&value| value == 0.0));
+        assert!(oscillator[5..].iter().all(|&value| value == 100.0));
+
+        let falling: Vec<f64> = rising.iter().rev().copied().collect();
+        let (up, down, oscillator) = aroon_values(&bars(&falling), 5);
+        assert!(up[5..].iter().all(|&value| value == 0.0));
+        assert!(down[5..].iter().all(|&value| value == 100.0));
+        assert!(oscillator[5..].iter().all(|&value| value == -100.0));
+    }
+
+    #[test]
+    fn adx_of_a_steady_rise_is_all_plus_di() {
+        let closes: Vec<f64> = (0..10).map(|i| 10.0 + i as f64).collect();
+        let (adx, plus_di, minus_di) = adx_values(&bars(&closes), 3);
+        assert!(plus_di[..3].iter().all(|value| value.is_nan()));
+        assert!(adx[..5].iter().all(|value| value.is_nan()));
+        // Every bar moves the high up by 1 with a true range of 2
+        assert!(plus_di[3..].iter().all(|&value| (value - 50.0).abs() < 1e-9));
+        assert!(minus_di[3..].iter().all(|&value| value == 0.0));
+        assert!(adx[5..].iter().all(|&value| (value - 100.0).abs() < 1e-9));
+    }
+}
@@ -0,0 +1,328 @@
+use chrono::{DateTime, Utc};
+use clap::{Args, ValueEnum};
+use log::{debug, error};
+use plotters::prelude::*;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use std::fmt::Write as _;
+
+use crate::backtest::{backtest_config, fetch_backtest_bars, print_metrics, run_backtest, strategy_params, Backtest, BacktestArgs, BacktestConfig, Metrics};
+use crate::bar::Bar;
+use crate::{axis_title, finite_range, Stock, WINDOW_HEIGHT, WINDOW_WIDTH};
+use crate::stats::finite_mean;
+use crate::strategies::{build_strategy, BuyAndHold, Flat, StrategyKind, StrategyParams};
+use crate::sweep::{combinations, run_sweep, RankMetric, SweepGrid, SweepRange};
+
+#[derive(Args, Debug)]
+pub struct WalkForwardArgs {
+    #[command(flatten)]
+    pub backtest: BacktestArgs,
+    #[command(flatten)]
+    pub grid: SweepGrid,
+    /// Bars in every in-sample (optimisation) window
+    #[arg(long, default_value_t = 252)]
+    pub in_sample: usize,
+    /// Bars in every out-of-sample (test) window, the windows move forward by this many bars
+    #[arg(long, default_value_t = 63)]
+    pub out_of_sample: usize,
+    /// Start every in-sample window at the first bar instead of rolling it forward
+    #[arg(long)]
+    pub anchored: bool,
+}
+
+// Sizes of the in-sample and out-of-sample windows in bars. Rolling windows keep the in-sample size and move
+// forward by the out-of-sample size, anchored in-sample windows always start at the first bar and grow.
+pub struct WindowSizes {
+    pub in_sample: usize,
+    pub out_of_sample: usize,
+    pub anchored: bool,
+}
+
+// One optimisation on the in-sample bars followed by the test of the best parameters on the out-of-sample bars.
+// Bar indices count from the first bar including the history.
+pub struct Window {
+    pub in_sample: (usize, usize),
+    pub out_of_sample: (usize, usize),
+    // Best values of the swept parameters, in the order of the sweep ranges. None when no combination traded
+    // in-sample, the out-of-sample window then stays flat
+    pub values: Option<Vec<f64>>,
+    pub in_sample_metrics: Metrics,
+    pub out_of_sample_metrics: Metrics,
+}
+
+pub struct WalkForward {
+    pub windows: Vec<Window>,
+    // Out-of-sample backtests of all windows stitched together, a position still open at the end of a window
+    // is closed with its costs so the next window starts flat with the equity the previous one ended with
+    pub result: Backtest,
+}
+
+pub fn run_walk_forward(
+    bars: &[Bar],
+    start: usize,
+    kind: StrategyKind,
+    combinations: &[(Vec<f64>, StrategyParams)],
+    config: &BacktestConfig,
+    rank_by: RankMetric,
+    sizes: &WindowSizes,
+) -> WalkForward {
+    let result = Backtest {
+        strategy_name: "Walk-forward".to_owned(),
+        capital: config.capital,
+        fill: config.fill,
+        start: start + sizes.in_sample,
+        times: Vec::new(),
+        equity: Vec::new(),
+        positions: Vec::new(),
+        trades: Vec::new(),
+    };
+    let mut walk_forward = WalkForward { windows: Vec::new(), result };
+    let mut capital = config.capital;
+    for ((in_sample_start, in_sample_end), (_, out_of_sample_end)) in windows(bars.len(), start, sizes) {
+        // Neither the optimisation nor the strategy's indicators get to see a bar past the window they run on
+        let ranked = run_sweep(&bars[..in_sample_end], in_sample_start, kind, combinations.to_vec(), config, rank_by);
+        let Some(best) = ranked.into_iter().next() else {
+            break;
+        };
+        let test_bars = &bars[..out_of_sample_end];
+        let traded = best.metrics.trades > 0;
+        let mut strategy = if traded { build_strategy(kind, &best.params, test_bars) } else { Box::new(Flat) };
+        let test_config = BacktestConfig { capital, ..*config };
+        let mut test = run_backtest(test_bars, in_sample_end, strategy.as_mut(), &test_config);
+        // The next window starts flat, so a position still open pays for its exit here
+        test.close_at_last_bar(&test_bars[test_bars.len() - 1], &test_config);
+
+        capital = test.equity.last().copied().unwrap_or(capital);
+        walk_forward.windows.push(Window {
+            in_sample: (in_sample_start, in_sample_end),
+            out_of_sample: (in_sample_end, out_of_sample_end),
+            values: traded.then_some(best.values),
+            in_sample_metrics: best.metrics,
+            out_of_sample_metrics: test.metrics(),
+        });
+        walk_forward.result.times.extend(test.times);
+        walk_forward.result.equity.extend(test.equity);
+        walk_forward.result.positions.extend(test.positions);
+        walk_forward.result.trades.extend(test.trades);
+    }
+    walk_forward
+}
+
+// In-sample and out-of-sample bar ranges of every window over `bar_count` bars from `start`, the last
+// out-of-sample window is cut short by the end of the bars
+fn windows(bar_count: usize, start: usize, sizes: &WindowSizes) -> Vec<((usize, usize), (usize, usize))> {
+    let mut windows = Vec::new();
+    let mut in_sample_end = start + sizes.in_sample;
+    while in_sample_end < bar_count {
+        let in_sample_start = if sizes.anchored { start } else { in_sample_end - sizes.in_sample };
+        let out_of_sample_end = (in_sample_end + sizes.out_of_sample).min(bar_count);
+        windows.push(((in_sample_start, in_sample_end), (in_sample_end, out_of_sample_end)));
+        in_sample_end = out_of_sample_end;
+    }
+    windows
+}
+
+impl WalkForward {
+    // Prints every window, the in-sample against the out-of-sample results and the stitched out-of-sample
+    // metrics next to those of the benchmark over the same bars
+    pub fn print(&self, bars: &[Bar], ranges: &[SweepRange], rank_by: RankMetric, benchmark: &Backtest) {
+        let date = |index: usize| bars[index].time().format("%Y-%m-%d").to_string();
+        let names: Vec<String> = ranges.iter().map(|range| format!("{:>14}", range.param.name())).collect();
+        println!("  {:<21} {:<21} {} {:>17} {:>17} {:>11}", "in-sample", "out-of-sample", names.join(""),
+                 format!("IS {}", rank_by.name()), format!("OOS {}", rank_by.name()), "OOS return");
+        for window in &self.windows {
+            let values = match &window.values {
+                Some(values) => values.iter().map(|value| format!("{:>14}", value)).collect(),
+                None => format!("{:>width$}", "no trades, flat", width = 14 * ranges.len()),
+            };
+            println!("  {} {} {} {} {} {:>17.4} {:>17.4} {:>10.2}%",
+                     date(window.in_sample.0), date(window.in_sample.1 - 1), date(window.out_of_sample.0), date(window.out_of_sample.1 - 1),
+                     values, rank_by.value(&window.in_sample_metrics), rank_by.value(&window.out_of_sample_metrics),
+                     window.out_of_sample_metrics.total_return * 100.0);
+        }
+        let untraded = self.windows.iter().filter(|window| window.values.is_none()).count();
+        if untraded > 0 {
+            println!("No combination traded in {} in-sample windows, they stayed flat out of sample and are left out of the means", untraded);
+        }
+
+        // Degradation: how much of the in-sample result survives out of sample
+        let optimised = || self.windows.iter().filter(|window| window.values.is_some());
+        let in_sample = finite_mean(optimised().map(|window| rank_by.value(&window.in_sample_metrics)));
+        let out_of_sample = finite_mean(optimised().map(|window| rank_by.value(&window.out_of_sample_metrics)));
+        println!("Mean in-sample {}      {:.4}", rank_by.name(), in_sample);
+        println!("Mean out-of-sample {}  {:.4}", rank_by.name(), out_of_sample);
+        // Compared on the score so that a deeper drawdown also counts as a degradation
+        let in_sample_score = finite_mean(optimised().map(|window| rank_by.score(&window.in_sample_metrics)));
+        let out_of_sample_score = finite_mean(optimised().map(|window| rank_by.score(&window.out_of_sample_metrics)));
+        if in_sample_score != 0.0 && in_sample_score.is_finite() && out_of_sample_score.is_finite() {
+            println!("Degradation                  {:.1}%", (in_sample_score - out_of_sample_score) / in_sample_score.abs() * 100.0);
+        }
+        let in_sample_cagr = finite_mean(optimised().map(|window| window.in_sample_metrics.cagr));
+        let out_of_sample_cagr = finite_mean(optimised().map(|window| window.out_of_sample_metrics.cagr));
+        if in_sample_cagr != 0.0 && in_sample_cagr.is_finite() && out_of_sample_cagr.is_finite() {
+            println!("Walk-forward efficiency      {:.2} (mean out-of-sample CAGR / mean in-sample CAGR)", out_of_sample_cagr / in_sample_cagr);
+        }
+
+        println!("Stitched out-of-sample results");
+        print_metrics(&[(&self.result.strategy_name, &self.result.metrics()), (&benchmark.strategy_name, &benchmark.metrics())]);
+    }
+
+    // Writes walk_forward_<ticker>.csv with one row per window and returns the name of the file
+    pub fn write_csv(&self, bars: &[Bar], ranges: &[SweepRange], rank_by: RankMetric, ticker_name: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let date = |index: usize| bars[index].time().format("%Y-%m-%d").to_string();
+        let mut csv = String::new();
+        let names: Vec<&str> = ranges.iter().map(|range| range.param.name()).collect();
+        writeln!(csv, "in_sample_start,in_sample_end,out_of_sample_start,out_of_sample_end,{},in_sample_{metric},out_of_sample_{metric},in_sample_return,out_of_sample_return",
+                 names.join(","), metric = rank_by.name().to_lowercase().replace(' ', "_"))?;
+        for window in &self.windows {
+            // The parameters of a window that stayed flat are left empty
+            let values: Vec<String> = match &window.values {
+                Some(values) => values.iter().map(|value| value.to_string()).collect(),
+                None => vec![String::new(); ranges.len()],
+            };
+            writeln!(csv, "{},{},{},{},{},{},{},{},{}", date(window.in_sample.0), date(window.in_sample.1 - 1), date(window.out_of_sample.0),
+                     date(window.out_of_sample.1 - 1), values.join(","), rank_by.value(&window.in_sample_metrics), rank_by.value(&window.out_of_sample_metrics),
+                     window.in_sample_metrics.total_return, window.out_of_sample_metrics.total_return)?;
+        }
+        let csv_file_name = "walk_forward_".to_owned() + ticker_name + ".csv";
+        std::fs::write(&csv_file_name, csv)?;
+        Ok(csv_file_name)
+    }
+}
+
+// Stitched out-of-sample equity against buy-and-hold over the same bars, every other window shaded and the
+// best parameters of each window written at its start
+pub fn equity_chart(walk_forward: &WalkForward, benchmark: &Backtest, ticker_name: &str, currency: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let chart_file_name = "walk_forward_".to_owned() + ticker_name + ".png";
+    let root = BitMapBackend::new(&chart_file_name, (WINDOW_WIDTH, WINDOW_HEIGHT)).into_drawing_area();
+    root.fill(&WHITE)?;
+    let result = &walk_forward.result;
+    let times = &result.times;
+    if times.is_empty() {
+        return Err("no out-of-sample window fits into the bars".into());
+    }
+    let (min_value, max_value) = finite_range(result.equity.iter().chain(benchmark.equity.iter()));
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(format!("Walk-forward Out-of-sample Equity for {}", ticker_name), ("sans-serif", 30).into_font())
+        .margin(10)
+        .x_label_area_size(55)
+        .y_label_area_size(60)
+        .build_cartesian_2d(times[0]..times[times.len() - 1], min_value..max_value)?;
+
+    chart.configure_mesh()
+        .x_labels(5)
+        .y_labels(5)
+        .x_desc("Date")
+        .y_desc(axis_title("Equity", currency))
+        .x_label_formatter(&|x| x.format("%Y-%m-%d").to_string())
+        .draw()?;
+
+    let window_times: Vec<(DateTime<Utc>, DateTime<Utc>)> = walk_forward.windows.iter()
+        .map(|window| (times[window.out_of_sample.0 - result.start], times[window.out_of_sample.1 - 1 - result.start]))
+        .collect();
+    chart.draw_series(window_times.iter().step_by(2).map(|&(first, last)| Rectangle::new([(first, min_value), (last, max_value)], BLACK.mix(0.06).filled())))?;
+    chart.draw_series(walk_forward.windows.iter().zip(window_times.iter()).map(|(window, &(first, _))| {
+        let label = match &window.values {
+            Some(values) => values.iter().map(|value| value.to_string()).collect::<Vec<String>>().join("/"),
+            None => "flat".to_owned(),
+        };
+        Text::new(label, (first, max_value), ("sans-serif", 13).into_font().color(&BLACK).pos(Pos::new(HPos::Left, VPos::Top)))
+    }))?;
+
+    for (backtest, color) in [(benchmark, BLACK), (result, BLUE)] {
+        chart.draw_series(LineSeries::new(
+            times.iter().zip(backtest.equity.iter()).map(|(&time, &value)| (time, value)),
+            color.stroke_width(2),
+        ))?
+            .label(backtest.strategy_name.clone())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color.stroke_width(2)));
+    }
+
+    chart.configure_series_labels()
+        .position(SeriesLabelPosition::LowerLeft)
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+// Optimises the swept parameters on every in-sample window, tests the best ones on the following out-of-sample
+// window and reports how much of the in-sample performance held up
+pub async fn run_command(stock_args: &Stock, walk_forward_args: &WalkForwardArgs) {
+    if let Err(e) = walk_forward_args.backtest.check() {
+        println!("{}", e);
+        return;
+    }
+    if walk_forward_args.in_sample == 0 || walk_forward_args.out_of_sample == 0 {
+        println!("The in-sample and out-of-sample windows must be at least 1 bar long");
+        return;
+    }
+    let backtest_args = &walk_forward_args.backtest;
+    let combinations = combinations(&strategy_params(stock_args, backtest_args), &walk_forward_args.grid.params);
+    if combinations.is_empty() {
+        println!("None of the parameter combinations is valid, the fast periods must be shorter than the slow periods");
+        return;
+    }
+    let warmup = combinations.iter().map(|(_, params)| params.warmup(backtest_args.strategy)).max().unwrap_or(0);
+    let Some((bars, start, currency)) = fetch_backtest_bars(stock_args, warmup).await else {
+        return;
+    };
+    if start + walk_forward_args.in_sample >= bars.len() {
+        println!("{} bars are not enough for an in-sample window of {} bars and an out-of-sample window", bars.len() - start, walk_forward_args.in_sample);
+        return;
+    }
+    let config = backtest_config(backtest_args);
+    let sizes = WindowSizes {
+        in_sample: walk_forward_args.in_sample,
+        out_of_sample: walk_forward_args.out_of_sample,
+        anchored: walk_forward_args.anchored,
+    };
+    let strategy_name = backtest_args.strategy.to_possible_value().map(|value| value.get_name().to_owned()).unwrap_or_default();
+    println!("Walk-forward of the {} strategy on {}, {} combinations per window ranked by {}", strategy_name, stock_args.name,
+             combinations.len(), walk_forward_args.grid.rank_by.name());
+
+    let walk_forward = run_walk_forward(&bars, start, backtest_args.strategy, &combinations, &config, walk_forward_args.grid.rank_by, &sizes);
+    // Buy and hold over the same out-of-sample bars
+    let benchmark = run_backtest(&bars, walk_forward.result.start, &mut BuyAndHold, &config);
+    walk_forward.print(&bars, &walk_forward_args.grid.params, walk_forward_args.grid.rank_by, &benchmark);
+
+    match walk_forward.write_csv(&bars, &walk_forward_args.grid.params, walk_forward_args.grid.rank_by, &stock_args.name) {
+        Ok(csv_file_name) => println!("Walk-forward windows written to {}", csv_file_name),
+        Err(e) => {
+            println!("Error while writing the walk-forward windows check log file for more details");
+            error!("Error while writing the walk-forward windows:{:?}",e)
+        }
+    }
+    match equity_chart(&walk_forward, &benchmark, &stock_args.name, &currency) {
+        Ok(_) => {debug!("Walk-forward Chart Created Successfully")}
+        Err(e) => {
+            println!("Error while creating the walk-forward chart check log file for more details");
+            error!("Error while creating the Walk-forward Chart:{:?}",e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rolling_windows_move_by_the_out_of_sample_size() {
+        let sizes = WindowSizes { in_sample: 4, out_of_sample: 3, anchored: false };
+        assert_eq!(windows(10, 0, &sizes), vec![((0, 4), (4, 7)), ((3, 7), (7, 10))]);
+        // One more bar makes a last out-of-sample window of a single bar
+        assert_eq!(windows(11, 0, &sizes), vec![((0, 4), (4, 7)), ((3, 7), (7, 10)), ((6, 10), (10, 11))]);
+        // History before `start` is never part of a window
+        assert_eq!(windows(12, 2, &sizes), vec![((2, 6), (6, 9)), ((5, 9), (9, 12))]);
+        assert!(windows(6, 2, &sizes).is_empty());
+    }
+
+    #[test]
+    fn anchored_windows_grow_from_the_start() {
+        let sizes = WindowSizes { in_sample: 4, out_of_sample: 3, anchored: true };
+        assert_eq!(windows(11, 1, &sizes), vec![((1, 5), (5, 8)), ((1, 8), (8, 11))]);
+        assert_eq!(windows(12, 1, &sizes), vec![((1, 5), (5, 8)), ((1, 8), (8, 11)), ((1, 11), (11, 12))]);
+    }
+}